@@ -0,0 +1,202 @@
+/* ---------------------------------------------------------------- *
+ * NOISESOCKET                                                      *
+ * ---------------------------------------------------------------- */
+
+use crate::{
+	consts::{DHLEN, EMPTY_KEY, MAC_LENGTH, MAX_MESSAGE},
+	noisesession::NoiseSession,
+	types::{Message, MessageBuffer},
+};
+use byteorder::{BigEndian, ByteOrder};
+
+const PROLOGUE_INITIAL: &[u8] = b"NoiseSocketInit1";
+const PROLOGUE_SWITCH: &[u8] = b"NoiseSocketInit2";
+const PROLOGUE_RETRY: &[u8] = b"NoiseSocketInit3";
+const LENGTH_FIELD: usize = 2;
+
+pub const PROTOCOL_NAME: &[u8] = b"Noise_I1K_25519_ChaChaPoly_BLAKE2s";
+const HANDSHAKE_LENGTH: u32 = 3;
+
+/* Lengths of the ne and ns fields of each handshake message. */
+pub(crate) fn message_layout(mc: u32) -> (usize, usize) {
+	match mc {
+		0 => (DHLEN, DHLEN + MAC_LENGTH),
+		1 => (DHLEN, 0),
+		2 => (0, 0),
+		_ => (0, 0),
+	}
+}
+
+fn write_length_prefixed(out: &mut Vec<u8>, data: &[u8]) {
+	assert!(data.len() <= MAX_MESSAGE, "NoiseSocket field too long");
+	let mut length = [0u8; LENGTH_FIELD];
+	BigEndian::write_u16(&mut length, data.len() as u16);
+	out.extend(&length[..]);
+	out.extend(data);
+}
+
+fn read_length_prefixed<'a>(data: &'a [u8], offset: &mut usize) -> Option<&'a [u8]> {
+	let length = BigEndian::read_u16(data.get(*offset..*offset + LENGTH_FIELD)?) as usize;
+	let start = *offset + LENGTH_FIELD;
+	let field = data.get(start..start + length)?;
+	*offset = start + length;
+	Some(field)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Response {
+	Accept,
+	Switch,
+	Retry,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HandshakeMessage {
+	pub negotiation_data: Vec<u8>,
+	pub noise_message: Vec<u8>,
+}
+impl HandshakeMessage {
+	/* An empty noise_message asks the initiator to retry with the protocol named in negotiation_data. */
+	pub fn retry_request(negotiation_data: &[u8]) -> HandshakeMessage {
+		HandshakeMessage {
+			negotiation_data: Vec::from(negotiation_data),
+			noise_message: Vec::new(),
+		}
+	}
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut out: Vec<u8> = Vec::new();
+		write_length_prefixed(&mut out, &self.negotiation_data[..]);
+		write_length_prefixed(&mut out, &self.noise_message[..]);
+		out
+	}
+	pub fn from_bytes(data: &[u8]) -> Option<HandshakeMessage> {
+		let mut offset: usize = 0;
+		let negotiation_data = read_length_prefixed(data, &mut offset)?;
+		let noise_message = read_length_prefixed(data, &mut offset)?;
+		if offset != data.len() {
+			return None;
+		}
+		Some(HandshakeMessage {
+			negotiation_data: Vec::from(negotiation_data),
+			noise_message: Vec::from(noise_message),
+		})
+	}
+	/* Responses to an initiator's first message: empty negotiation_data accepts its protocol,
+	 * otherwise the responder either switches protocol or asks for a retry. */
+	pub fn response(&self) -> Response {
+		if self.noise_message.is_empty() {
+			Response::Retry
+		} else if self.negotiation_data.is_empty() {
+			Response::Accept
+		} else {
+			Response::Switch
+		}
+	}
+}
+
+pub fn initial_prologue(negotiation_data: &[u8]) -> Message {
+	let mut prologue: Vec<u8> = Vec::from(PROLOGUE_INITIAL);
+	write_length_prefixed(&mut prologue, negotiation_data);
+	Message::from_vec(prologue)
+}
+
+pub fn switch_prologue(initial: &HandshakeMessage, negotiation_data: &[u8]) -> Message {
+	let mut prologue: Vec<u8> = Vec::from(PROLOGUE_SWITCH);
+	prologue.extend(initial.to_bytes());
+	write_length_prefixed(&mut prologue, negotiation_data);
+	Message::from_vec(prologue)
+}
+
+pub fn retry_prologue(initial: &HandshakeMessage, retry: &HandshakeMessage, negotiation_data: &[u8]) -> Message {
+	let mut prologue: Vec<u8> = Vec::from(PROLOGUE_RETRY);
+	prologue.extend(initial.to_bytes());
+	write_length_prefixed(&mut prologue, &retry.negotiation_data[..]);
+	write_length_prefixed(&mut prologue, negotiation_data);
+	Message::from_vec(prologue)
+}
+
+pub struct NoiseSocket {
+	session: NoiseSession,
+	mc: u32,
+}
+impl NoiseSocket {
+	/* The session must be initialized with one of the prologues above. */
+	pub fn new(session: NoiseSession) -> NoiseSocket {
+		NoiseSocket { session, mc: 0 }
+	}
+	pub fn is_handshake_finished(&self) -> bool {
+		self.mc >= HANDSHAKE_LENGTH
+	}
+	pub fn get_session(&self) -> &NoiseSession {
+		&self.session
+	}
+	/* Returns None if the session could not write the message, for the reason given by its
+	 * get_handshake_error(); the handshake cannot go on. */
+	pub fn write_handshake_message(&mut self, negotiation_data: &[u8], payload: &[u8]) -> Option<HandshakeMessage> {
+		assert!(!self.is_handshake_finished(), "Handshake already finished");
+		let (ne_length, _) = message_layout(self.mc);
+		let message: MessageBuffer = self.session.send_message(Message::from_vec(Vec::from(payload)))?;
+		self.mc += 1;
+		let mut noise_message: Vec<u8> = Vec::from(&message.ne[..ne_length]);
+		noise_message.extend(&message.ns[..]);
+		noise_message.extend(&message.ciphertext[..]);
+		Some(HandshakeMessage {
+			negotiation_data: Vec::from(negotiation_data),
+			noise_message,
+		})
+	}
+	pub fn read_handshake_message(&mut self, message: &HandshakeMessage) -> Option<Vec<u8>> {
+		if self.is_handshake_finished() {
+			return None;
+		}
+		let (ne_length, ns_length) = message_layout(self.mc);
+		let noise_message: &[u8] = &message.noise_message[..];
+		if noise_message.len() < ne_length + ns_length {
+			return None;
+		}
+		let mut ne = EMPTY_KEY;
+		ne[..ne_length].copy_from_slice(&noise_message[..ne_length]);
+		let mut buffer = MessageBuffer {
+			ne,
+			ns: Vec::from(&noise_message[ne_length..ne_length + ns_length]),
+			ciphertext: Vec::from(&noise_message[ne_length + ns_length..]),
+		};
+		self.mc += 1;
+		self.session.recv_message(&mut buffer)
+	}
+	pub fn write_message(&mut self, body: &[u8], padding: usize) -> Vec<u8> {
+		assert!(self.is_handshake_finished(), "Handshake not finished");
+		assert!(
+			LENGTH_FIELD + body.len() + padding + MAC_LENGTH <= MAX_MESSAGE,
+			"NoiseSocket message too long"
+		);
+		let mut plaintext: Vec<u8> = Vec::new();
+		write_length_prefixed(&mut plaintext, body);
+		plaintext.extend(vec![0u8; padding]);
+		let message: MessageBuffer = self
+			.session
+			.send_message(Message::from_vec(plaintext))
+			.expect("Transport message not written; write_message");
+		let mut out: Vec<u8> = Vec::new();
+		write_length_prefixed(&mut out, &message.ciphertext[..]);
+		out
+	}
+	pub fn read_message(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+		if !self.is_handshake_finished() {
+			return None;
+		}
+		let mut offset: usize = 0;
+		let ciphertext = read_length_prefixed(packet, &mut offset)?;
+		if offset != packet.len() || ciphertext.len() < MAC_LENGTH {
+			return None;
+		}
+		let mut buffer = MessageBuffer {
+			ne: EMPTY_KEY,
+			ns: Vec::new(),
+			ciphertext: Vec::from(ciphertext),
+		};
+		let plaintext: Vec<u8> = self.session.recv_message(&mut buffer)?;
+		let mut offset: usize = 0;
+		Some(Vec::from(read_length_prefixed(&plaintext[..], &mut offset)?))
+	}
+}
@@ -1,9 +1,19 @@
 #![allow(non_snake_case, non_upper_case_globals)]
 
 use noiseexplorer_i1k::{
+	cert::CertificateAuthority,
+	cookie::{CookieError, CookieGuard, CookieMessage},
+	disco::DiscoSymmetricState,
+	knownpeers::{KnownPeers, TrustMode},
+	mux::{Multiplexer, INITIAL_WINDOW},
 	noisesession::NoiseSession,
-	types::{Keypair, Message, MessageBuffer, PrivateKey, PublicKey},
+	noisesocket::{retry_prologue, HandshakeMessage, NoiseSocket, Response, PROTOCOL_NAME},
+	oracle::LocalKeyOracle,
+	replay::{ReplayCache, ReplayError},
+	table::SessionTable,
+	types::{HandshakeError, Keypair, Message, MessageBuffer, PrivateKey, PublicKey, StaticKeyOracle},
 };
+use std::{fs, ops::Range, path::PathBuf, sync::Arc, time::Duration};
 
 #[test]
 fn noiseexplorer_test_i1k() {
@@ -16,37 +26,37 @@ fn noiseexplorer_test_i1k() {
 	let mut responder_session: NoiseSession = NoiseSession::init_session(false, prologueB, Keypair::from_private_key(resp_static_private), PublicKey::empty());
 	initiator_session.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("893e28b9dc6ca8d611ab664754b8ceb7bac5117349a4439a6b0569da977c464a")));
 	responder_session.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("bbdb4cdbd309f1a1f2e1456967fe288cadd6f712d65dc7b7793d5e63da6b375b")));
-	let mut messageA: MessageBuffer = initiator_session.send_message(Message::from_str("4c756477696720766f6e204d69736573"));
+	let mut messageA: MessageBuffer = initiator_session.send_message(Message::from_str("4c756477696720766f6e204d69736573")).unwrap();
 	let mut validA: bool = false;
 	if let Some(_x) = responder_session.recv_message(&mut messageA) {
 		validA = true;
 	}
 	let tA: Message = Message::from_str("ca35def5ae56cec33dc2036731ab14896bc4c75dbb07a61f879f8e3afa4c7944d203bb6ff07617535a8ae6edd3453496a5cdd89213abfc0d5548bf2c68caad6d9b6a063f53eb6e5736b93c69e3ac0679739ea6aca4d3dddad5d7c16a40978fbc0bd0cd4269b69233f8e054bc06bfd5b5");
-	let mut messageB: MessageBuffer = responder_session.send_message(Message::from_str("4d757272617920526f746862617264"));
+	let mut messageB: MessageBuffer = responder_session.send_message(Message::from_str("4d757272617920526f746862617264")).unwrap();
 	let mut validB: bool = false;
 	if let Some(_x) = initiator_session.recv_message(&mut messageB) {
 		validB = true;
 	}
 	let tB: Message = Message::from_str("95ebc60d2b1fa672c1f46a8aa265ef51bfe38e7ccb39ec5be34069f14480884350c8dd93488a51a98e5ab8f923f52558533a2e3e70ee83fcb968b28239446e");
-	let mut messageC: MessageBuffer = initiator_session.send_message(Message::from_str("462e20412e20486179656b"));
+	let mut messageC: MessageBuffer = initiator_session.send_message(Message::from_str("462e20412e20486179656b")).unwrap();
 	let mut validC: bool = false;
 	if let Some(_x) = responder_session.recv_message(&mut messageC) {
 		validC = true;
 	}
 	let tC: Message = Message::from_str("712ae62c9ad7d33bf98d5447e77ffeee4f3933a66c892b6a76d4d7");
-	let mut messageD: MessageBuffer = responder_session.send_message(Message::from_str("4361726c204d656e676572"));
+	let mut messageD: MessageBuffer = responder_session.send_message(Message::from_str("4361726c204d656e676572")).unwrap();
 	let mut validD: bool = false;
 	if let Some(_x) = initiator_session.recv_message(&mut messageD) {
 		validD = true;
 	}
 	let tD: Message = Message::from_str("510867587e50c01439f6df4201db76355cb2ea0a46398f64f55a7a");
-	let mut messageE: MessageBuffer = initiator_session.send_message(Message::from_str("4a65616e2d426170746973746520536179"));
+	let mut messageE: MessageBuffer = initiator_session.send_message(Message::from_str("4a65616e2d426170746973746520536179")).unwrap();
 	let mut validE: bool = false;
 	if let Some(_x) = responder_session.recv_message(&mut messageE) {
 		validE = true;
 	}
 	let tE: Message = Message::from_str("e96974beb8b4959ef468fc4380539d132a88e775ff70cac15dbcfba6de80e71405");
-	let mut messageF: MessageBuffer = responder_session.send_message(Message::from_str("457567656e2042f6686d20766f6e2042617765726b"));
+	let mut messageF: MessageBuffer = responder_session.send_message(Message::from_str("457567656e2042f6686d20766f6e2042617765726b")).unwrap();
 	let mut validF: bool = false;
 	if let Some(_x) = initiator_session.recv_message(&mut messageF) {
 		validF = true;
@@ -102,3 +112,609 @@ fn noiseexplorer_test_i1k() {
 		&cB
 	);
 }
+
+const PROLOGUE: &str = "4a6f686e2047616c74";
+const HANDSHAKE_LENGTH: usize = 3;
+const PAYLOADS: [&str; 6] = [
+	"4c756477696720766f6e204d69736573",
+	"4d757272617920526f746862617264",
+	"462e20412e20486179656b",
+	"4361726c204d656e676572",
+	"4a65616e2d426170746973746520536179",
+	"457567656e2042f6686d20766f6e2042617765726b",
+];
+const CIPHERTEXTS: [&str; 6] = [
+	"ca35def5ae56cec33dc2036731ab14896bc4c75dbb07a61f879f8e3afa4c7944d203bb6ff07617535a8ae6edd3453496a5cdd89213abfc0d5548bf2c68caad6d9b6a063f53eb6e5736b93c69e3ac0679739ea6aca4d3dddad5d7c16a40978fbc0bd0cd4269b69233f8e054bc06bfd5b5",
+	"95ebc60d2b1fa672c1f46a8aa265ef51bfe38e7ccb39ec5be34069f14480884350c8dd93488a51a98e5ab8f923f52558533a2e3e70ee83fcb968b28239446e",
+	"712ae62c9ad7d33bf98d5447e77ffeee4f3933a66c892b6a76d4d7",
+	"510867587e50c01439f6df4201db76355cb2ea0a46398f64f55a7a",
+	"e96974beb8b4959ef468fc4380539d132a88e775ff70cac15dbcfba6de80e71405",
+	"3e0da42459f5204cca1b89a7b616a849611a36882902c7de7a9a78ef01da8b644c80ed0f37",
+];
+/* The same test vector over Disco, computed from its specification with the strobe-rs and x25519-dalek crates. */
+const DISCO_CIPHERTEXTS: [&str; 6] = [
+	"ca35def5ae56cec33dc2036731ab14896bc4c75dbb07a61f879f8e3afa4c79448bfbef94b8a3b80b53ba8bc52a421cb38af1b5c044779821aaebe4b59e51982ae880e6680ad9c7baf21912dd52e1c16ce84d03f10574d3078bdca33c0ec242791d06a1fb84ea0a8889f164a3bfca67a4",
+	"95ebc60d2b1fa672c1f46a8aa265ef51bfe38e7ccb39ec5be34069f1448088433dd96b66f18994b62f9c2245d11edaf0708883bcb6f69a1e76b3786c75e08a",
+	"28a9936fd6ac59f4d341cd21ac0cce2e690906f18febadbdb47e68",
+	"9ff9ebb4b088d75f8302a22ec1450786b1bd3ebd9df3f4458bd496",
+	"cac51a57d7a28531b14efc7744baae7f4d8fe0017643fb0e5248f17149e1283507",
+	"84d152dd7205ef944d6b12c824f495bd04db65a1f6703843dcc95351b37ae294e20b14670a",
+];
+/* Whether each handshake message carries an ephemeral and a static key. */
+const WIRE_KEYS: [(bool, bool); 5] = [(true, true), (true, false), (false, false), (false, false), (false, false)];
+
+fn static_keys() -> (PrivateKey, PrivateKey) {
+	(PrivateKey::from_str("e61ef9919cde45dd5f82166404bd08e38bceb5dfdfded0a34c8df7ed542214d1"), PrivateKey::from_str("4a3acbfdb163dec651dfa3194dece676d437029c62a408b4c5ea9114246e4893"))
+}
+
+/* Both parties of the test vector, built by init with the given prologue and static keypairs. */
+fn init_sessions<T>(
+	init: fn(bool, Message, Keypair, PublicKey) -> T,
+	prologue: Message,
+	initiator_static: Keypair,
+	responder_static: Keypair,
+) -> (T, T) {
+	let initiator: T = init(true, prologue.clone(), initiator_static, static_keys().1.generate_public_key());
+	let responder: T = init(false, prologue, responder_static, PublicKey::empty());
+	(initiator, responder)
+}
+
+fn sessions() -> (NoiseSession, NoiseSession) {
+	let (initiator_static, responder_static) = static_keys();
+	init_sessions(
+		NoiseSession::init_session,
+		Message::from_str(PROLOGUE),
+		Keypair::from_private_key(initiator_static),
+		Keypair::from_private_key(responder_static),
+	)
+}
+
+/* Fixes the ephemeral keys of the test vector, so that messages can be compared with its ciphertexts. */
+fn set_vector_ephemerals<T: Party>(initiator: &mut T, responder: &mut T) {
+	initiator.set_ephemeral(Keypair::from_private_key(PrivateKey::from_str("893e28b9dc6ca8d611ab664754b8ceb7bac5117349a4439a6b0569da977c464a")));
+	responder.set_ephemeral(Keypair::from_private_key(PrivateKey::from_str("bbdb4cdbd309f1a1f2e1456967fe288cadd6f712d65dc7b7793d5e63da6b375b")));
+}
+
+trait Party {
+	fn set_ephemeral(&mut self, e: Keypair);
+	fn send(&mut self, message: Message) -> MessageBuffer;
+	fn recv(&mut self, message: &mut MessageBuffer) -> Option<Vec<u8>>;
+}
+impl Party for NoiseSession {
+	fn set_ephemeral(&mut self, e: Keypair) {
+		self.set_ephemeral_keypair(e);
+	}
+	fn send(&mut self, message: Message) -> MessageBuffer {
+		self.send_message(message).unwrap()
+	}
+	fn recv(&mut self, message: &mut MessageBuffer) -> Option<Vec<u8>> {
+		self.recv_message(message)
+	}
+}
+impl Party for NoiseSession<DiscoSymmetricState> {
+	fn set_ephemeral(&mut self, e: Keypair) {
+		self.set_ephemeral_keypair(e);
+	}
+	fn send(&mut self, message: Message) -> MessageBuffer {
+		self.send_message(message).unwrap()
+	}
+	fn recv(&mut self, message: &mut MessageBuffer) -> Option<Vec<u8>> {
+		self.recv_message(message)
+	}
+}
+
+/* Sends the given messages of the test vector, showing each to inspect with its receiver before it is read. */
+fn exchange<T: Party>(
+	initiator: &mut T,
+	responder: &mut T,
+	indices: Range<usize>,
+	inspect: &mut dyn FnMut(usize, &MessageBuffer, &T),
+) -> Vec<MessageBuffer> {
+	let mut messages: Vec<MessageBuffer> = Vec::new();
+	for i in indices {
+		let (sender, receiver): (&mut T, &mut T) = if i % 2 == 0 {
+			(&mut *initiator, &mut *responder)
+		} else {
+			(&mut *responder, &mut *initiator)
+		};
+		let payload: Message = Message::from_str(PAYLOADS[i]);
+		let mut message: MessageBuffer = sender.send(payload.clone());
+		inspect(i, &message, receiver);
+		assert!(receiver.recv(&mut message) == Some(payload.as_bytes().clone()), "Message {} FAIL", i);
+		messages.push(message);
+	}
+	messages
+}
+
+fn handshake<T: Party>(initiator: &mut T, responder: &mut T) -> Vec<MessageBuffer> {
+	exchange(initiator, responder, 0..HANDSHAKE_LENGTH, &mut |_, _, _| {})
+}
+
+fn transport<T: Party>(initiator: &mut T, responder: &mut T) -> Vec<MessageBuffer> {
+	exchange(initiator, responder, HANDSHAKE_LENGTH..PAYLOADS.len(), &mut |_, _, _| {})
+}
+
+/* Compares messages, as they would be sent on the wire, with the ciphertexts of a test vector. */
+fn assert_vectors(messages: &[MessageBuffer], ciphertexts: &[&str]) {
+	for (i, message) in messages.iter().enumerate() {
+		let mut wire: Vec<u8> = Vec::new();
+		if let Some((ephemeral, static_key)) = WIRE_KEYS.get(i) {
+			if *ephemeral {
+				wire.extend_from_slice(&message.ne[..]);
+			}
+			if *static_key {
+				wire.extend_from_slice(&message.ns[..]);
+			}
+		}
+		wire.extend_from_slice(&message.ciphertext[..]);
+		assert!(wire == *Message::from_str(ciphertexts[i]).as_bytes(), "Test {} FAIL", i);
+	}
+}
+
+#[test]
+fn noiseexplorer_test_i1k_split() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	set_vector_ephemerals(&mut initiator_session, &mut responder_session);
+	let mut messages: Vec<MessageBuffer> = handshake(&mut initiator_session, &mut responder_session);
+	let (mut initiator_sender, mut initiator_receiver) = initiator_session.into_split();
+	let (mut responder_sender, mut responder_receiver) = responder_session.into_split();
+	for (i, payload) in PAYLOADS.iter().enumerate().skip(HANDSHAKE_LENGTH) {
+		let mut message: MessageBuffer = if i % 2 == 0 {
+			initiator_sender.send_message(Message::from_str(payload))
+		} else {
+			responder_sender.send_message(Message::from_str(payload))
+		};
+		let receiver = if i % 2 == 0 { &mut responder_receiver } else { &mut initiator_receiver };
+		assert!(receiver.recv_message(&mut message).unwrap() == *Message::from_str(payload).as_bytes());
+		messages.push(message);
+	}
+	assert_vectors(&messages[..], &CIPHERTEXTS);
+}
+
+#[test]
+#[should_panic(expected = "Re-handshake in progress; into_split")]
+fn noiseexplorer_test_i1k_split_during_rehandshake() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	handshake(&mut initiator_session, &mut responder_session);
+	let mut request: MessageBuffer = initiator_session.rehandshake().unwrap();
+	let (_, mut receiver) = responder_session.into_split();
+	assert!(receiver.recv_message(&mut request).is_none());
+	initiator_session.into_split();
+}
+
+#[test]
+fn noiseexplorer_test_i1k_noisesocket() {
+	let initial: HandshakeMessage = HandshakeMessage::from_bytes(&HandshakeMessage {
+		negotiation_data: b"Noise_I1K_448_ChaChaPoly_BLAKE2b".to_vec(),
+		noise_message: vec![0u8; 56],
+	}.to_bytes()[..]).unwrap();
+	let retry: HandshakeMessage = HandshakeMessage::retry_request(PROTOCOL_NAME);
+	assert!(retry.response() == Response::Retry);
+	let (initiator_static, responder_static) = static_keys();
+	let (initiator_session, responder_session) = init_sessions(
+		NoiseSession::init_session,
+		retry_prologue(&initial, &retry, PROTOCOL_NAME),
+		Keypair::from_private_key(initiator_static),
+		Keypair::from_private_key(responder_static),
+	);
+	let mut initiator_socket: NoiseSocket = NoiseSocket::new(initiator_session);
+	let mut responder_socket: NoiseSocket = NoiseSocket::new(responder_session);
+	for (i, payload) in PAYLOADS.iter().enumerate() {
+		let payload: Message = Message::from_str(payload);
+		let (sender, receiver) = if i % 2 == 0 {
+			(&mut initiator_socket, &mut responder_socket)
+		} else {
+			(&mut responder_socket, &mut initiator_socket)
+		};
+		if i < HANDSHAKE_LENGTH {
+			let negotiation_data: &[u8] = if i == 0 { PROTOCOL_NAME } else { &[] };
+			let message: HandshakeMessage =
+				HandshakeMessage::from_bytes(&sender.write_handshake_message(negotiation_data, &payload.as_bytes()[..]).unwrap().to_bytes()[..]).unwrap();
+			assert!(receiver.read_handshake_message(&message).unwrap() == *payload.as_bytes());
+		} else {
+			let packet: Vec<u8> = sender.write_message(&payload.as_bytes()[..], i * 8);
+			assert!(receiver.read_message(&packet[..]).unwrap() == *payload.as_bytes());
+		}
+	}
+	assert!(initiator_socket.is_handshake_finished() && responder_socket.is_handshake_finished());
+}
+
+#[test]
+fn noiseexplorer_test_i1k_elligator() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	initiator_session.set_elligator(true);
+	responder_session.set_elligator(true);
+	handshake(&mut initiator_session, &mut responder_session);
+	transport(&mut initiator_session, &mut responder_session);
+}
+
+#[test]
+fn noiseexplorer_test_i1k_elligator_not_representable() {
+	let (mut initiator_session, _) = sessions();
+	initiator_session.set_elligator(true);
+	initiator_session.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("0101010101010101010101010101010101010101010101010101010101010101")));
+	for _ in 0..2 {
+		assert!(initiator_session.send_message(Message::from_vec(b"payload".to_vec())).is_none());
+		assert!(initiator_session.get_handshake_error() == Some(HandshakeError::EphemeralNotRepresentable));
+	}
+	let (mut initiator_session, _) = sessions();
+	initiator_session.set_elligator(true);
+	initiator_session.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("0101010101010101010101010101010101010101010101010101010101010101")));
+	let mut socket: NoiseSocket = NoiseSocket::new(initiator_session.clone());
+	assert!(socket.write_handshake_message(PROTOCOL_NAME, b"payload").is_none());
+	assert!(!socket.is_handshake_finished());
+	assert!(socket.get_session().get_handshake_error() == Some(HandshakeError::EphemeralNotRepresentable));
+	let mut table: SessionTable<u32, u8> = SessionTable::new(1, 1, Duration::from_secs(60));
+	assert!(table.insert(1, 7, initiator_session).is_some() && table.half_open() == 1);
+	assert!(table.send_message(&1, Message::from_vec(b"payload".to_vec())).is_none());
+	assert!(table.half_open() == 0 && table.send_message(&1, Message::from_vec(b"payload".to_vec())).is_none());
+}
+
+#[test]
+fn noiseexplorer_test_i1k_disco() {
+	let (initiator_static, responder_static) = static_keys();
+	let (mut initiator_session, mut responder_session): (NoiseSession<DiscoSymmetricState>, NoiseSession<DiscoSymmetricState>) =
+		init_sessions(
+			NoiseSession::init_disco_session,
+			Message::from_str(PROLOGUE),
+			Keypair::from_private_key(initiator_static),
+			Keypair::from_private_key(responder_static),
+		);
+	set_vector_ephemerals(&mut initiator_session, &mut responder_session);
+	let mut messages: Vec<MessageBuffer> = handshake(&mut initiator_session, &mut responder_session);
+	messages.append(&mut transport(&mut initiator_session, &mut responder_session));
+	assert_vectors(&messages[..], &DISCO_CIPHERTEXTS);
+}
+
+#[test]
+fn noiseexplorer_test_i1k_ratchet() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	initiator_session.set_ratchet(true);
+	responder_session.set_ratchet(true);
+	handshake(&mut initiator_session, &mut responder_session);
+	transport(&mut initiator_session, &mut responder_session);
+}
+
+#[test]
+fn noiseexplorer_test_i1k_rehandshake() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	handshake(&mut initiator_session, &mut responder_session);
+	transport(&mut initiator_session, &mut responder_session);
+	let exported: Vec<u8> = responder_session.export_keying_material(b"media", b"", 32);
+	let mut request: MessageBuffer = initiator_session.rehandshake().unwrap();
+	let mut in_flight: MessageBuffer = initiator_session.send_message(Message::from_vec(b"in flight".to_vec())).unwrap();
+	assert!(responder_session.recv_message(&mut request).unwrap().is_empty());
+	assert!(responder_session.is_rehandshake_message());
+	let mut response: MessageBuffer = responder_session.take_rehandshake_message().unwrap();
+	let mut fresh: MessageBuffer = responder_session.send_message(Message::from_vec(b"fresh".to_vec())).unwrap();
+	assert!(responder_session.recv_message(&mut in_flight).unwrap() == b"in flight");
+	assert!(!responder_session.is_rehandshake_message());
+	let mut stale: NoiseSession = initiator_session.clone();
+	assert!(stale.recv_message(&mut fresh.clone()).is_none());
+	assert!(initiator_session.recv_message(&mut response).unwrap().is_empty());
+	assert!(initiator_session.recv_message(&mut fresh).unwrap() == b"fresh");
+	let mut confirm: MessageBuffer = initiator_session.take_rehandshake_message().unwrap();
+	let mut after: MessageBuffer = initiator_session.send_message(Message::from_vec(b"after".to_vec())).unwrap();
+	assert!(responder_session.recv_message(&mut confirm).unwrap().is_empty());
+	assert!(responder_session.recv_message(&mut after).unwrap() == b"after");
+	let mut empty: MessageBuffer = initiator_session.send_message(Message::from_vec(Vec::new())).unwrap();
+	assert!(responder_session.recv_message(&mut empty).unwrap().is_empty());
+	assert!(!responder_session.is_rehandshake_message());
+	assert!(responder_session.take_rehandshake_message().is_none());
+	assert!(initiator_session.export_keying_material(b"media", b"", 32) == exported);
+}
+
+#[test]
+fn noiseexplorer_test_i1k_mux() {
+	fn pump(from: &mut Multiplexer, to: &mut Multiplexer) -> usize {
+		let mut count: usize = 0;
+		while let Some(mut message) = from.poll_message() {
+			assert!(to.recv_message(&mut message).is_some());
+			count += 1;
+		}
+		count
+	}
+	let (mut initiator_session, mut responder_session) = sessions();
+	handshake(&mut initiator_session, &mut responder_session);
+	let mut initiator_mux: Multiplexer = Multiplexer::new(initiator_session);
+	let mut responder_mux: Multiplexer = Multiplexer::new(responder_session);
+	let bulk: u32 = initiator_mux.open();
+	let small: u32 = initiator_mux.open();
+	let data: Vec<u8> = (0..3 * INITIAL_WINDOW).map(|x| x as u8).collect();
+	initiator_mux.write(bulk, &data[..]);
+	initiator_mux.write(small, b"small");
+	initiator_mux.close(small);
+	initiator_mux.close(bulk);
+	pump(&mut initiator_mux, &mut responder_mux);
+	assert!(responder_mux.accept() == Some(bulk) && responder_mux.accept() == Some(small));
+	assert!(responder_mux.read(small) == b"small" && responder_mux.is_eof(small));
+	let mut received: Vec<u8> = responder_mux.read(bulk);
+	assert!(received.len() == INITIAL_WINDOW as usize);
+	let reply: u32 = responder_mux.open();
+	responder_mux.write(reply, b"reply");
+	responder_mux.close(reply);
+	while pump(&mut responder_mux, &mut initiator_mux) + pump(&mut initiator_mux, &mut responder_mux) > 0 {
+		received.extend(responder_mux.read(bulk));
+	}
+	assert!(received == data && responder_mux.is_eof(bulk));
+	assert!(initiator_mux.accept() == Some(reply) && initiator_mux.read(reply) == b"reply");
+}
+
+#[test]
+fn noiseexplorer_test_i1k_mux_max_streams() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	handshake(&mut initiator_session, &mut responder_session);
+	let mut initiator_mux: Multiplexer = Multiplexer::new(initiator_session);
+	let mut responder_mux: Multiplexer = Multiplexer::new(responder_session);
+	responder_mux.set_max_streams(1);
+	let first: u32 = initiator_mux.open();
+	let refused: u32 = initiator_mux.open();
+	initiator_mux.write(first, b"first");
+	initiator_mux.write(refused, b"refused");
+	for _ in 0..2 {
+		while let Some(mut message) = initiator_mux.poll_message() {
+			assert!(responder_mux.recv_message(&mut message).is_some());
+		}
+		while let Some(mut message) = responder_mux.poll_message() {
+			assert!(initiator_mux.recv_message(&mut message).is_some());
+		}
+		if responder_mux.accept().is_some() {
+			responder_mux.close(first);
+			initiator_mux.close(first);
+		}
+	}
+	assert!(responder_mux.read(first) == b"first" && responder_mux.read(refused).is_empty());
+	assert!(initiator_mux.is_reset(refused) && !initiator_mux.is_reset(first));
+	initiator_mux.close(refused);
+	let next: u32 = initiator_mux.open();
+	let mut message: MessageBuffer = initiator_mux.poll_message().unwrap();
+	assert!(responder_mux.recv_message(&mut message).is_some() && responder_mux.accept() == Some(next));
+	assert!(responder_mux.poll_message().is_none());
+}
+
+#[test]
+fn noiseexplorer_test_i1k_session_table() {
+	let (mut initiator_session, responder_session) = sessions();
+	let mut stale: SessionTable<u32, u8> = SessionTable::new(2, 2, Duration::from_secs(0));
+	assert!(stale.insert(1, 7, responder_session.clone()).is_some());
+	assert!(stale.expire() == 1 && stale.half_open() == 0);
+	let mut table: SessionTable<u32, u8> = SessionTable::new(2, 1, Duration::from_secs(60));
+	assert!(table.insert(1, 7, responder_session.clone()).is_some());
+	assert!(table.insert(2, 7, responder_session.clone()).is_none());
+	assert!(table.insert(2, 8, responder_session.clone()).is_some());
+	assert!(table.insert(3, 9, responder_session).is_none());
+	for (i, payload) in PAYLOADS.iter().enumerate() {
+		if i % 2 == 0 {
+			let mut message: MessageBuffer = initiator_session.send_message(Message::from_str(payload)).unwrap();
+			assert!(table.recv_message(&1, &mut message).is_some());
+		} else {
+			let mut message: MessageBuffer = table.send_message(&1, Message::from_str(payload)).unwrap();
+			assert!(initiator_session.recv_message(&mut message).is_some());
+		}
+	}
+	assert!(table.half_open() == 1 && table.established() == 1);
+	assert!(table.get_session(&1).unwrap().is_handshake_finished());
+	assert!(table.remove(&2).is_none() && table.half_open() == 0);
+}
+
+#[test]
+fn noiseexplorer_test_i1k_replay() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	let mut replica: NoiseSession = responder_session.clone();
+	let mut cache: ReplayCache = ReplayCache::new(Duration::from_secs(60));
+	let mut message: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();
+	let mut replayed: MessageBuffer = message.clone();
+	assert!(cache.recv_first_message(&mut responder_session, &mut message).is_ok());
+	assert!(cache.recv_first_message(&mut replica, &mut replayed) == Err(ReplayError::Replayed));
+	exchange(&mut initiator_session, &mut responder_session, 1..PAYLOADS.len(), &mut |_, _, _| {});
+}
+
+#[test]
+fn noiseexplorer_test_i1k_replay_elligator() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	initiator_session.set_elligator(true);
+	responder_session.set_elligator(true);
+	let mut cache: ReplayCache = ReplayCache::new(Duration::from_secs(60));
+	let mut message: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();
+	let replayed: MessageBuffer = message.clone();
+	assert!(cache.recv_first_message(&mut responder_session.clone(), &mut message).is_ok());
+	for flipped in [0x40u8, 0x80, 0xc0].iter() {
+		let mut reencoded: MessageBuffer = replayed.clone();
+		reencoded.ne[31] ^= flipped;
+		assert!(cache.recv_first_message(&mut responder_session.clone(), &mut reencoded) == Err(ReplayError::Replayed));
+	}
+}
+
+#[test]
+fn noiseexplorer_test_i1k_cookie() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	set_vector_ephemerals(&mut initiator_session, &mut responder_session);
+	let mut guard: CookieGuard = CookieGuard::new();
+	guard.set_under_load(true);
+	let first: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();
+	let sent: CookieMessage = CookieMessage::first(first.clone());
+	let mut received: CookieMessage = CookieMessage::from_bytes(&sent.to_bytes()[..]).unwrap();
+	let reply: CookieMessage = match guard.recv_first_message(&mut responder_session, b"192.0.2.1:443", &mut received) {
+		Err(CookieError::Retry(reply)) => CookieMessage::from_bytes(&reply.to_bytes()[..]).unwrap(),
+		_ => panic!("Cookie not requested"),
+	};
+	let retry: CookieMessage = sent.retry(&reply).unwrap();
+	let mut spoofed: CookieMessage = retry.clone();
+	assert!(matches!(guard.recv_first_message(&mut responder_session, b"192.0.2.2:443", &mut spoofed), Err(CookieError::Retry(_))));
+	let mut received: CookieMessage = CookieMessage::from_bytes(&retry.to_bytes()[..]).unwrap();
+	let plaintext: Vec<u8> = guard.recv_first_message(&mut responder_session, b"192.0.2.1:443", &mut received).ok().unwrap();
+	assert!(plaintext == *Message::from_str(PAYLOADS[0]).as_bytes());
+	let mut messages: Vec<MessageBuffer> = vec![first];
+	messages.append(&mut exchange(&mut initiator_session, &mut responder_session, 1..PAYLOADS.len(), &mut |_, _, _| {}));
+	assert_vectors(&messages[..], &CIPHERTEXTS);
+}
+
+#[test]
+fn noiseexplorer_test_i1k_low_order() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	let mut message: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();
+	message.ne = [0u8; 32];
+	let mut permissive: NoiseSession = responder_session.clone();
+	permissive.set_reject_low_order(false);
+	assert!(responder_session.recv_message(&mut message.clone()).is_none());
+	assert!(responder_session.get_handshake_error() == Some(HandshakeError::LowOrderPoint));
+	permissive.recv_message(&mut message);
+	assert!(permissive.get_handshake_error().is_none());
+}
+
+#[test]
+fn noiseexplorer_test_i1k_decrypt_failure() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	exchange(&mut initiator_session, &mut responder_session, 0..0, &mut |_, _, _| {});
+	let mut message: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();
+	let mut truncated: MessageBuffer = message.clone();
+	truncated.ciphertext.truncate(8);
+	let mut tampered: MessageBuffer = message.clone();
+	tampered.ciphertext[0] ^= 0x01;
+	let receiver: &mut NoiseSession = &mut responder_session;
+	assert!(receiver.clone().recv_message(&mut truncated).is_none());
+	assert!(receiver.clone().recv_message(&mut tampered).is_none());
+	assert!(receiver.recv_message(&mut message).unwrap() == *Message::from_str(PAYLOADS[0]).as_bytes());
+}
+
+#[test]
+fn noiseexplorer_test_i1k_static_key_verifier() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	responder_session.set_static_key_verifier(Arc::new(|_: &PublicKey| false));
+	exchange(&mut initiator_session, &mut responder_session, 0..0, &mut |_, _, _| {});
+	let mut message: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();
+	assert!(responder_session.recv_message(&mut message).is_none());
+	assert!(responder_session.get_handshake_error() == Some(HandshakeError::StaticKeyRejected));
+}
+
+#[test]
+fn noiseexplorer_test_i1k_known_peers() {
+	let path: PathBuf = std::env::temp_dir().join(format!("noiseexplorer_I1K_known_peers_{}", std::process::id()));
+	let _ = fs::remove_file(&path);
+	let store: Arc<KnownPeers> = Arc::new(KnownPeers::open(&path, TrustMode::TrustOnFirstUse).unwrap());
+	let (mut initiator_session, mut responder_session) = sessions();
+	responder_session.set_known_peer(&store, "peer");
+	exchange(&mut initiator_session, &mut responder_session, 0..1, &mut |_, _, _| {});
+	let responder_static: PrivateKey = static_keys().1;
+	assert!(store.get("peer").unwrap().as_bytes() == static_keys().0.generate_public_key().as_bytes());
+	let (mut initiator_session, mut responder_session) = init_sessions(
+		NoiseSession::init_session,
+		Message::from_str(PROLOGUE),
+		Keypair::new(),
+		Keypair::from_private_key(responder_static),
+	);
+	responder_session.set_known_peer(&store, "peer");
+	exchange(&mut initiator_session, &mut responder_session, 0..0, &mut |_, _, _| {});
+	let mut message: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();
+	assert!(responder_session.recv_message(&mut message).is_none());
+	assert!(responder_session.get_handshake_error() == Some(HandshakeError::StaticKeyMismatch));
+	assert!(KnownPeers::open(&path, TrustMode::Strict).unwrap().get("peer").is_some());
+	fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn noiseexplorer_test_i1k_certificate() {
+	let authority: CertificateAuthority = CertificateAuthority::from_bytes([0x42u8; 32]);
+	let (initiator_static, responder_static) = static_keys();
+	let (mut initiator_session, mut responder_session) = sessions();
+	initiator_session.set_certificate(authority.issue("initiator", &initiator_static.generate_public_key(), u64::MAX));
+	initiator_session.set_trust_anchors(vec![authority.get_public_key()]);
+	responder_session.set_certificate(authority.issue("responder", &responder_static.generate_public_key(), u64::MAX));
+	responder_session.set_trust_anchors(vec![authority.get_public_key()]);
+	exchange(&mut initiator_session, &mut responder_session, 0..PAYLOADS.len(), &mut |i, message, receiver| {
+		if i == 0 {
+			let mut stranger: NoiseSession = receiver.clone();
+			stranger.set_trust_anchors(vec![CertificateAuthority::from_bytes([0x43u8; 32]).get_public_key()]);
+			assert!(stranger.recv_message(&mut message.clone()).is_none());
+			assert!(stranger.get_handshake_error() == Some(HandshakeError::CertificateRejected));
+		}
+	});
+	assert!(responder_session.get_remote_certificate().unwrap().get_name() == "initiator");
+}
+
+#[test]
+fn noiseexplorer_test_i1k_static_key_oracle() {
+	let (initiator_static, responder_static) = static_keys();
+	let (mut initiator_session, mut responder_session) = init_sessions(
+		NoiseSession::init_session,
+		Message::from_str(PROLOGUE),
+		Keypair::from_oracle(Arc::new(LocalKeyOracle::new(initiator_static))),
+		Keypair::from_oracle(Arc::new(LocalKeyOracle::new(responder_static))),
+	);
+	set_vector_ephemerals(&mut initiator_session, &mut responder_session);
+	let mut messages: Vec<MessageBuffer> = handshake(&mut initiator_session, &mut responder_session);
+	messages.extend(transport(&mut initiator_session, &mut responder_session));
+	assert_vectors(&messages[..], &CIPHERTEXTS);
+}
+
+#[test]
+fn noiseexplorer_test_i1k_static_key_oracle_failure() {
+	struct RefusingOracle(PublicKey);
+	impl StaticKeyOracle for RefusingOracle {
+		fn public_key(&self) -> PublicKey {
+			self.0
+		}
+		fn dh(&self, _: &PublicKey) -> Option<[u8; 32]> {
+			None
+		}
+	}
+	let (initiator_static, responder_static) = static_keys();
+	let initiator_oracle: Arc<RefusingOracle> = Arc::new(RefusingOracle(initiator_static.generate_public_key()));
+	let (mut initiator_session, mut responder_session) = init_sessions(
+		NoiseSession::init_session,
+		Message::from_str(PROLOGUE),
+		Keypair::from_oracle(initiator_oracle),
+		Keypair::from_private_key(responder_static),
+	);
+	exchange(&mut initiator_session, &mut responder_session, 0..2, &mut |_, _, _| {});
+	assert!(initiator_session.send_message(Message::from_str(PAYLOADS[2])).is_none());
+	assert!(initiator_session.get_handshake_error() == Some(HandshakeError::OracleFailed));
+}
+
+#[test]
+fn noiseexplorer_test_i1k_static_key_rotation() {
+	let (initiator_static, responder_static) = static_keys();
+	let (mut initiator_session, mut responder_session) = init_sessions(
+		NoiseSession::init_session,
+		Message::from_str(PROLOGUE),
+		Keypair::from_private_key(initiator_static),
+		Keypair::new(),
+	);
+	set_vector_ephemerals(&mut initiator_session, &mut responder_session);
+	let mut rotated: NoiseSession = responder_session.clone();
+	rotated.set_previous_static_keys(vec![Keypair::new()]);
+	responder_session.set_previous_static_keys(vec![Keypair::new(), Keypair::from_private_key(responder_static)]);
+	let mut messages: Vec<MessageBuffer> = exchange(&mut initiator_session, &mut responder_session, 0..HANDSHAKE_LENGTH, &mut |i, message, _| {
+		if i == 0 {
+			assert!(rotated.recv_message(&mut message.clone()).is_none());
+			assert!(rotated.get_static_key_index().is_none());
+		}
+	});
+	messages.extend(transport(&mut initiator_session, &mut responder_session));
+	assert!(responder_session.get_static_key_index() == Some(2));
+	assert_vectors(&messages[..], &CIPHERTEXTS);
+}
+
+#[test]
+fn noiseexplorer_test_i1k_associated_data() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	handshake(&mut initiator_session, &mut responder_session);
+	let payload: Message = Message::from_str(PAYLOADS[HANDSHAKE_LENGTH]);
+	let mut message: MessageBuffer = initiator_session.send_message_with_ad(b"associated", payload.clone());
+	let mut unbound: NoiseSession = responder_session.clone();
+	assert!(unbound.recv_message(&mut message.clone()).is_none());
+	assert!(unbound.recv_message_with_ad(b"other", &mut message.clone()).is_none());
+	assert!(responder_session.recv_message_with_ad(b"associated", &mut message).unwrap() == *payload.as_bytes());
+	let mut reply: MessageBuffer = responder_session.send_message_with_ad(b"associated", payload.clone());
+	assert!(initiator_session.recv_message_with_ad(b"associated", &mut reply).unwrap() == *payload.as_bytes());
+}
+
+#[test]
+fn noiseexplorer_test_i1k_keying_material_exporter() {
+	let (mut initiator_session, mut responder_session) = sessions();
+	handshake(&mut initiator_session, &mut responder_session);
+	let exported: Vec<u8> = initiator_session.export_keying_material(b"media", b"", 48);
+	assert!(exported.len() == 48 && exported == responder_session.export_keying_material(b"media", b"", 48));
+	assert!(exported != initiator_session.export_keying_material(b"media", b"1", 48));
+	assert!(exported[..32] != initiator_session.export_keying_material(b"signing", b"", 32)[..]);
+}
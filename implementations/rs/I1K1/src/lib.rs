@@ -17,8 +17,20 @@ I1K1:
 pub(crate) mod macros;
 
 pub(crate) mod consts;
+pub(crate) mod elligator;
 pub(crate) mod prims;
+pub(crate) mod ratchet;
+pub(crate) mod rehandshake;
 pub(crate) mod state;
 
+pub mod cert;
+pub mod disco;
+pub mod knownpeers;
+pub mod mux;
 pub mod noisesession;
+pub mod noisesocket;
+pub mod openssh;
+pub mod oracle;
+pub mod replay;
+pub mod table;
 pub mod types;
\ No newline at end of file
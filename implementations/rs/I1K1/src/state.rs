@@ -3,11 +3,17 @@
  * ---------------------------------------------------------------- */
 
 use crate::{
-	consts::{DHLEN, EMPTY_KEY, HASHLEN, NONCE_LENGTH, ZEROLEN},
-	prims::{decrypt, encrypt, hash, hkdf},
-	types::{Hash, Key, Keypair, MessageBuffer, Nonce, Psk, PublicKey},
+	consts::{EMPTY_KEY, HASHLEN, NONCE_LENGTH, ZEROLEN},
+	prims::{decrypt, encrypt, Blake2sHash, HashFunction},
+	types::{
+		DhKeypair, DhPublicKey, HandshakeError, Hash, Key, Keypair, MessageBuffer, Nonce, Psk, PublicKeyBytes,
+		StaticKeyVerifier,
+	},
 };
+use crate::cert::{self, Certificate, AUTHORITY_KEY_LENGTH};
 use hacl_star::chacha20poly1305;
+use std::{marker::PhantomData, sync::Arc};
+use zeroize::Zeroize;
 
 fn from_slice_hashlen(bytes: &[u8]) -> [u8; HASHLEN] {
 	let mut array = [0u8; HASHLEN];
@@ -16,8 +22,34 @@ fn from_slice_hashlen(bytes: &[u8]) -> [u8; HASHLEN] {
 	array
 }
 
+/* Transport cipher returned by split(). */
+pub trait Cipher: Clone {
+	fn new() -> Self;
+	fn write_message_regular(&mut self, payload: &[u8]) -> MessageBuffer;
+	fn read_message_regular(&mut self, message: &MessageBuffer) -> Option<Vec<u8>>;
+	fn write_message_with_ad(&mut self, ad: &[u8], payload: &[u8]) -> MessageBuffer;
+	fn read_message_with_ad(&mut self, ad: &[u8], message: &MessageBuffer) -> Option<Vec<u8>>;
+}
+
+/* The operations used by the handshake message functions, implemented by
+ * SymmetricState and by the STROBE-based DiscoSymmetricState. */
+pub trait Symmetric: Clone {
+	type Cipher: Cipher;
+	const CIPHER_SUITE: &'static str;
+	fn initialize_symmetric(protocol_name: &[u8]) -> Self;
+	fn mix_key(&mut self, input_key_material: &[u8]);
+	fn mix_hash(&mut self, data: &[u8]);
+	fn mix_key_and_hash(&mut self, input_key_material: &[u8]);
+	fn get_handshake_hash(&mut self) -> [u8; HASHLEN];
+	fn get_chaining_key(&self) -> [u8; HASHLEN];
+	fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Option<Vec<u8>>;
+	fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Option<Vec<u8>>;
+	fn split(&mut self) -> (Self::Cipher, Self::Cipher);
+	fn clear(&mut self);
+}
+
 #[derive(Clone)]
-pub(crate) struct CipherState {
+pub struct CipherState {
 	k: Key,
 	n: Nonce,
 }
@@ -70,7 +102,7 @@ impl CipherState {
 			self.n.increment();
 			Some(plaintext)
 		} else {
-			println!("Unsuccessful Decryption, problem with ad, nonce not incremented\n\nDECRYPT({:X?}, {:X?}, {:X?}, {:X?})", from_slice_hashlen(&self.k.as_bytes()[..]), self.n.get_value(), ad, ciphertext);
+			/* Silent, as transport messages failing under the regular AD are retried as re-handshake messages. */
 			None
 		}
 	}
@@ -83,32 +115,47 @@ impl CipherState {
 		self.k.clear();
 		self.k = Key::from_bytes(in_out);
 	}
-	pub(crate) fn write_message_regular(&mut self, payload: &[u8]) -> MessageBuffer {
+}
+
+impl Cipher for CipherState {
+	fn new() -> CipherState {
+		CipherState::from_key(Key::new())
+	}
+	fn write_message_regular(&mut self, payload: &[u8]) -> MessageBuffer {
+		self.write_message_with_ad(&ZEROLEN[..], payload)
+	}
+	fn read_message_regular(&mut self, message: &MessageBuffer) -> Option<Vec<u8>> {
+		self.read_message_with_ad(&ZEROLEN[..], message)
+	}
+	fn write_message_with_ad(&mut self, ad: &[u8], payload: &[u8]) -> MessageBuffer {
 		MessageBuffer {
 			ne: EMPTY_KEY,
 			ns: Vec::new(),
-			ciphertext: self.encrypt_with_ad(&ZEROLEN[..], payload),
+			ciphertext: self.encrypt_with_ad(ad, payload),
 		}
 	}
-	pub(crate) fn read_message_regular(&mut self, message: &MessageBuffer) -> Option<Vec<u8>> {
-		self.decrypt_with_ad(&ZEROLEN[..], &message.ciphertext)
+	fn read_message_with_ad(&mut self, ad: &[u8], message: &MessageBuffer) -> Option<Vec<u8>> {
+		self.decrypt_with_ad(ad, &message.ciphertext)
 	}
 }
 
 #[derive(Clone)]
-pub struct SymmetricState {
+pub struct SymmetricState<H: HashFunction = Blake2sHash> {
 	cs: CipherState,
 	ck: Hash,
 	h: Hash,
+	hash: PhantomData<H>,
 }
 
-impl SymmetricState {
-	pub(crate) fn clear(&mut self) {
+impl<H: HashFunction> Symmetric for SymmetricState<H> {
+	type Cipher = CipherState;
+	const CIPHER_SUITE: &'static str = H::CIPHER_SUITE;
+	fn clear(&mut self) {
 		self.cs.clear_key();
 		self.ck.clear();
 		self.h.clear();
 	}
-	pub fn initialize_symmetric(protocol_name: &[u8]) -> SymmetricState {
+	fn initialize_symmetric(protocol_name: &[u8]) -> SymmetricState<H> {
 		let h: Hash;
 		match protocol_name.len() {
 			0..=31 => {
@@ -119,17 +166,17 @@ impl SymmetricState {
 				h = Hash::new(from_slice_hashlen(&temp[..]));
 			}
 			32 => h = Hash::new(from_slice_hashlen(protocol_name)),
-			_ => h = Hash::new(from_slice_hashlen(&hash(protocol_name))),
+			_ => h = Hash::new(from_slice_hashlen(&H::hash(protocol_name))),
 		}
 		let ck: Hash = Hash::new(from_slice_hashlen(h.as_bytes()));
 		let cs: CipherState = CipherState::new();
-		SymmetricState { cs, ck, h }
+		SymmetricState { cs, ck, h, hash: PhantomData }
 	}
-	pub(crate) fn mix_key(&mut self, input_key_material: &[u8]) {
+	fn mix_key(&mut self, input_key_material: &[u8]) {
 		let mut out0: Vec<u8> = Vec::from(&EMPTY_KEY[..]);
 		let mut out1: Vec<u8> = Vec::from(&EMPTY_KEY[..]);
 		let mut out2: Vec<u8> = Vec::from(&EMPTY_KEY[..]);
-		hkdf(
+		H::hkdf(
 			&self.ck.as_bytes()[..],
 			input_key_material,
 			2,
@@ -142,17 +189,16 @@ impl SymmetricState {
 		temp_k.copy_from_slice(&out1[..32]);
 		self.cs = CipherState::from_key(Key::from_bytes(temp_k));
 	}
-	pub(crate) fn mix_hash(&mut self, data: &[u8]) {
+	fn mix_hash(&mut self, data: &[u8]) {
 		let mut temp: Vec<u8> = Vec::from(&self.h.as_bytes()[..]);
 		temp.extend(data);
-		self.h = Hash::new(from_slice_hashlen(&hash(&temp)[..]));
+		self.h = Hash::new(from_slice_hashlen(&H::hash(&temp)[..]));
 	}
-	#[allow(dead_code)]
-	pub(crate) fn mix_key_and_hash(&mut self, input_key_material: &[u8]) {
+	fn mix_key_and_hash(&mut self, input_key_material: &[u8]) {
 		let mut out0: Vec<u8> = Vec::from(&EMPTY_KEY[..]);
 		let mut out1: Vec<u8> = Vec::from(&EMPTY_KEY[..]);
 		let mut out2: Vec<u8> = Vec::from(&EMPTY_KEY[..]);
-		hkdf(
+		H::hkdf(
 			&self.ck.as_bytes()[..],
 			input_key_material,
 			3,
@@ -167,29 +213,30 @@ impl SymmetricState {
 		temp_k.copy_from_slice(&out2[..32]);
 		self.cs = CipherState::from_key(Key::from_bytes(temp_k));
 	}
-	#[allow(dead_code)]
-	pub(crate) fn get_handshake_hash(&self) -> [u8; HASHLEN] {
+	fn get_handshake_hash(&mut self) -> [u8; HASHLEN] {
 		from_slice_hashlen(&self.h.as_bytes()[..])
 	}
-	pub(crate) fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Option<Vec<u8>> {
+	fn get_chaining_key(&self) -> [u8; HASHLEN] {
+		from_slice_hashlen(&self.ck.as_bytes()[..])
+	}
+	fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Option<Vec<u8>> {
 		let ciphertext: Vec<u8> =
 			Vec::from(&self.cs.encrypt_with_ad(&self.h.as_bytes()[..], plaintext)[..]);
 		self.mix_hash(&ciphertext);
 		Some(ciphertext)
 	}
-	pub(crate) fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+	fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Option<Vec<u8>> {
 		if let Some(plaintext) = self.cs.decrypt_with_ad(&self.h.as_bytes()[..], &ciphertext) {
 			self.mix_hash(ciphertext);
 			return Some(Vec::from(&plaintext[..]));
-		} else {
-			panic!("Invalid ad");
 		}
+		None
 	}
-	pub(crate) fn split(&mut self) -> (CipherState, CipherState) {
+	fn split(&mut self) -> (CipherState, CipherState) {
 		let mut temp_k1: Vec<u8> = Vec::from(&EMPTY_KEY[..]);
 		let mut temp_k2: Vec<u8> = Vec::from(&EMPTY_KEY[..]);
 		let mut out2: Vec<u8> = Vec::from(&EMPTY_KEY[..]);
-		hkdf(
+		H::hkdf(
 			&self.ck.as_bytes()[..],
 			&ZEROLEN[..],
 			2,
@@ -206,114 +253,260 @@ impl SymmetricState {
 }
 
 #[derive(Clone)]
-pub struct HandshakeState {
-	ss: SymmetricState,
-	s: Keypair,
-	e: Keypair,
-	rs: PublicKey,
-	re: PublicKey,
+pub struct HandshakeState<S = SymmetricState, K: DhKeypair = Keypair> {
+	pub(crate) ss: S,
+	s: K,
+	e: K,
+	rs: K::PublicKey,
+	re: K::PublicKey,
 	psk: Psk,
+	elligator: bool,
+	reject_low_order: bool,
+	verifier: Option<Arc<dyn StaticKeyVerifier<K::PublicKey>>>,
+	remote_certificate: Option<Certificate>,
+	certificate: Option<Certificate>,
+	anchors: Vec<[u8; AUTHORITY_KEY_LENGTH]>,
+	hint: Vec<u8>,
+	error: Option<HandshakeError>,
 }
 
 /* HandshakeState */
-impl HandshakeState {
+impl<S: Symmetric, K: DhKeypair> HandshakeState<S, K> {
 	pub(crate) fn clear(&mut self) {
+        self.ss.clear();
         self.s.clear();
         self.e.clear();
         self.rs.clear();
         self.re.clear();
         self.psk.clear();
+        self.hint.zeroize();
     }
-	pub(crate) fn set_ephemeral_keypair(&mut self, e: Keypair) {
+	pub(crate) fn set_ephemeral_keypair(&mut self, e: K) {
         self.e = e;
     }
-	pub(crate) fn initialize_initiator(prologue: &[u8], s: Keypair, rs: PublicKey, psk: Psk) -> HandshakeState {
-		let protocol_name = b"Noise_I1K1_25519_ChaChaPoly_BLAKE2s";
-		let mut ss: SymmetricState = SymmetricState::initialize_symmetric(&protocol_name[..]);
+	pub(crate) fn set_elligator(&mut self, elligator: bool) {
+		self.elligator = elligator;
+	}
+	pub(crate) fn set_reject_low_order(&mut self, reject_low_order: bool) {
+		self.reject_low_order = reject_low_order;
+	}
+	pub(crate) fn set_static_key_verifier(&mut self, verifier: Arc<dyn StaticKeyVerifier<K::PublicKey>>) {
+		self.verifier = Some(verifier);
+	}
+	pub(crate) fn get_error(&self) -> Option<HandshakeError> {
+		self.error
+	}
+	/* Called by the write functions for our ephemeral key as sent. An ephemeral
+	 * set with set_ephemeral_keypair() may have no Elligator2 representative,
+	 * which fails the message with EphemeralNotRepresentable. */
+	fn encode_ephemeral(&mut self) -> Option<PublicKeyBytes<K>> {
+		if !self.elligator {
+			return Some(self.e.get_public_key().as_bytes());
+		}
+		let ne: Option<PublicKeyBytes<K>> = self.e.get_public_key().elligator_encode();
+		if ne.is_none() {
+			self.error = Some(HandshakeError::EphemeralNotRepresentable);
+		}
+		ne
+	}
+	/* The peer's ephemeral key as received, whichever representative encodes it. */
+	pub(crate) fn decode_ephemeral(&self, ne: &PublicKeyBytes<K>) -> PublicKeyBytes<K> {
+		if self.elligator {
+			K::PublicKey::elligator_decode(ne)
+		} else {
+			*ne
+		}
+	}
+	/* Called by the read functions on every key received from the peer. */
+	fn check_remote_key(&mut self, key: K::PublicKey, ephemeral: bool) -> Option<()> {
+		if self.reject_low_order && key.is_low_order() {
+			self.error = Some(HandshakeError::LowOrderPoint);
+			return None;
+		}
+		if ephemeral && !self.e.is_empty() && key.as_bytes() == self.e.get_public_key().as_bytes() {
+			self.error = Some(HandshakeError::Reflection);
+			return None;
+		}
+		if !ephemeral {
+			if let Some(verifier) = &self.verifier {
+				if let Err(error) = verifier.verify(&key) {
+					self.error = Some(error);
+					return None;
+				}
+			}
+		}
+		Some(())
+	}
+	pub(crate) fn get_static_keypair(&self) -> K {
+		self.s.clone()
+	}
+	pub(crate) fn get_remote_certificate(&self) -> Option<Certificate> {
+		self.remote_certificate.clone()
+	}
+	pub(crate) fn set_certificate(&mut self, certificate: Certificate) {
+		self.certificate = Some(certificate);
+	}
+	pub(crate) fn set_trust_anchors(&mut self, anchors: Vec<[u8; AUTHORITY_KEY_LENGTH]>) {
+		self.anchors = anchors;
+	}
+	/* Called by the write functions of messages carrying our static key. */
+	fn attach_certificate(&self, payload: &[u8]) -> Vec<u8> {
+		match &self.certificate {
+			Some(certificate) => cert::attach(certificate, payload),
+			None => Vec::from(payload),
+		}
+	}
+	/* Called by the read functions of messages carrying the peer's static key,
+	 * before the handshake may complete. */
+	fn check_certificate(&mut self, plaintext: Vec<u8>) -> Option<Vec<u8>> {
+		if self.anchors.is_empty() {
+			return Some(plaintext);
+		}
+		match cert::detach(&plaintext[..]) {
+			Some((certificate, payload))
+				if certificate.verify(&self.anchors[..], cert::unix_time())
+					&& certificate.get_static_key().as_bytes()[..] == *self.rs.as_bytes().as_ref() =>
+			{
+				self.remote_certificate = Some(certificate);
+				Some(payload)
+			}
+			_ => {
+				self.error = Some(HandshakeError::CertificateRejected);
+				None
+			}
+		}
+	}
+	/* Called by the read and write functions for DH with our static key, which
+	 * fails the message rather than the process if the key is held by an oracle. */
+	fn mix_static_dh(&mut self, public_key: &PublicKeyBytes<K>) -> Option<()> {
+		match self.s.try_dh(public_key) {
+			Some(shared) => {
+				self.ss.mix_key(&shared);
+				Some(())
+			}
+			None => {
+				self.error = Some(HandshakeError::OracleFailed);
+				None
+			}
+		}
+	}
+	pub(crate) fn get_remote_static_public_key(&self) -> K::PublicKey {
+		self.rs
+	}
+	pub(crate) fn get_ephemeral_keypair(&self) -> K {
+		self.e.clone()
+	}
+	pub(crate) fn get_remote_ephemeral_public_key(&self) -> K::PublicKey {
+		self.re
+	}
+	pub(crate) fn initialize_initiator(prologue: &[u8], s: K, rs: K::PublicKey, psk: Psk) -> HandshakeState<S, K> {
+		let protocol_name = format!("Noise_I1K1_{}_{}", K::DH_NAME, S::CIPHER_SUITE);
+		HandshakeState::initialize_initiator_with_name(protocol_name.as_bytes(), prologue, s, rs, psk)
+	}
+
+	/* For protocols that run this pattern under a protocol name of their own. */
+	pub(crate) fn initialize_initiator_with_name(protocol_name: &[u8], prologue: &[u8], s: K, rs: K::PublicKey, psk: Psk) -> HandshakeState<S, K> {
+		let mut ss: S = S::initialize_symmetric(protocol_name);
 		ss.mix_hash(prologue);
-		ss.mix_hash(&rs.as_bytes()[..]);
-		HandshakeState{ss, s, e: Keypair::new_empty(), rs, re: PublicKey::empty(), psk}
+		ss.mix_hash(rs.as_bytes().as_ref());
+		HandshakeState{ss, s, e: K::new_empty(), rs, re: K::PublicKey::empty(), psk, elligator: false, reject_low_order: true, verifier: None, remote_certificate: None, certificate: None, anchors: Vec::new(), hint: Vec::new(), error: None}
+	}
+
+	pub(crate) fn initialize_responder(prologue: &[u8], s: K, rs: K::PublicKey, psk: Psk) -> HandshakeState<S, K> {
+		let protocol_name = format!("Noise_I1K1_{}_{}", K::DH_NAME, S::CIPHER_SUITE);
+		HandshakeState::initialize_responder_with_name(protocol_name.as_bytes(), prologue, s, rs, psk)
 	}
 
-	pub(crate) fn initialize_responder(prologue: &[u8], s: Keypair, rs: PublicKey, psk: Psk) -> HandshakeState {
-		let protocol_name = b"Noise_I1K1_25519_ChaChaPoly_BLAKE2s";
-		let mut ss: SymmetricState = SymmetricState::initialize_symmetric(&protocol_name[..]);
+	/* For protocols that run this pattern under a protocol name of their own. */
+	pub(crate) fn initialize_responder_with_name(protocol_name: &[u8], prologue: &[u8], s: K, rs: K::PublicKey, psk: Psk) -> HandshakeState<S, K> {
+		let mut ss: S = S::initialize_symmetric(protocol_name);
 		ss.mix_hash(prologue);
-		ss.mix_hash(&s.get_public_key().as_bytes()[..]);
-		HandshakeState{ss, s, e: Keypair::new_empty(), rs, re: PublicKey::empty(), psk}
+		ss.mix_hash(s.get_public_key().as_bytes().as_ref());
+		HandshakeState{ss, s, e: K::new_empty(), rs, re: K::PublicKey::empty(), psk, elligator: false, reject_low_order: true, verifier: None, remote_certificate: None, certificate: None, anchors: Vec::new(), hint: Vec::new(), error: None}
 	}
-	pub(crate) fn write_message_a(&mut self, payload: &[u8]) -> (MessageBuffer) {
+	pub(crate) fn write_message_a(&mut self, payload: &[u8]) -> (Option<MessageBuffer<PublicKeyBytes<K>>>) {
+		if self.error.is_some() {
+			return None;
+		}
 		let mut ns: Vec<u8> = Vec::new();
-		let ne: [u8; DHLEN];
+		let ne: PublicKeyBytes<K>;
 		if self.e.is_empty() {
-			self.e = Keypair::new();
+			self.e = if self.elligator { K::new_elligator() } else { K::new() };
 		}
-		ne = self.e.get_public_key().as_bytes();
-		self.ss.mix_hash(&ne[..]);
+		ne = self.encode_ephemeral()?;
+		self.ss.mix_hash(self.e.get_public_key().as_bytes().as_ref());
 		/* No PSK, so skipping mixKey */
-		if let Some(x) = self.ss.encrypt_and_hash(&self.s.get_public_key().as_bytes()[..]) {
+		if let Some(x) = self.ss.encrypt_and_hash(self.s.get_public_key().as_bytes().as_ref()) {
 			ns.clone_from(&x);
 		}
 		let mut ciphertext: Vec<u8> = Vec::new();
-		if let Some(x) = self.ss.encrypt_and_hash(payload) {
+		if let Some(x) = self.ss.encrypt_and_hash(&self.attach_certificate(payload)[..]) {
 			ciphertext.clone_from(&x);
 		}
-		MessageBuffer { ne, ns, ciphertext }
+		Some(MessageBuffer { ne, ns, ciphertext })
 	}
 
-	pub(crate) fn write_message_b(&mut self, payload: &[u8]) -> (MessageBuffer) {
+	pub(crate) fn write_message_b(&mut self, payload: &[u8]) -> (Option<MessageBuffer<PublicKeyBytes<K>>>) {
+		if self.error.is_some() {
+			return None;
+		}
 		let ns: Vec<u8> = Vec::new();
-		let ne: [u8; DHLEN];
+		let ne: PublicKeyBytes<K>;
 		if self.e.is_empty() {
-			self.e = Keypair::new();
+			self.e = if self.elligator { K::new_elligator() } else { K::new() };
 		}
-		ne = self.e.get_public_key().as_bytes();
-		self.ss.mix_hash(&ne[..]);
+		ne = self.encode_ephemeral()?;
+		self.ss.mix_hash(self.e.get_public_key().as_bytes().as_ref());
 		/* No PSK, so skipping mixKey */
 		self.ss.mix_key(&self.e.dh(&self.re.as_bytes()));
-		self.ss.mix_key(&self.s.dh(&self.re.as_bytes()));
+		self.mix_static_dh(&self.re.as_bytes())?;
 		let mut ciphertext: Vec<u8> = Vec::new();
 		if let Some(x) = self.ss.encrypt_and_hash(payload) {
 			ciphertext.clone_from(&x);
 		}
-		MessageBuffer { ne, ns, ciphertext }
+		Some(MessageBuffer { ne, ns, ciphertext })
 	}
 
-	pub(crate) fn write_message_c(&mut self, payload: &[u8]) -> ((Hash, MessageBuffer, CipherState, CipherState)) {
+	pub(crate) fn write_message_c(&mut self, payload: &[u8]) -> (Option<(Hash, MessageBuffer<PublicKeyBytes<K>>, S::Cipher, S::Cipher, Hash)>) {
+		if self.error.is_some() {
+			return None;
+		}
 		let ns: Vec<u8> = Vec::new();
-		let ne: [u8; DHLEN] = EMPTY_KEY;
-		self.ss.mix_key(&self.s.dh(&self.re.as_bytes()));
+		let ne: PublicKeyBytes<K> = K::PublicKey::empty().as_bytes();
+		self.mix_static_dh(&self.re.as_bytes())?;
 		let mut ciphertext: Vec<u8> = Vec::new();
 		if let Some(x) = self.ss.encrypt_and_hash(payload) {
 			ciphertext.clone_from(&x);
 		}
-		let h: Hash = Hash::new(from_slice_hashlen(self.ss.h.as_bytes()));
+		let ck: Hash = Hash::new(self.ss.get_chaining_key());
 		let (cs1, cs2) = self.ss.split();
+		let h: Hash = Hash::new(self.ss.get_handshake_hash());
 		self.ss.clear();
 		let messagebuffer = MessageBuffer { ne, ns, ciphertext };
-		(h, messagebuffer, cs1, cs2)
+		Some((h, messagebuffer, cs1, cs2, ck))
 	}
 
 
-	pub(crate) fn read_message_a(&mut self, message: &mut MessageBuffer) -> (Option<Vec<u8>>) {
-		self.re = PublicKey::from_bytes(message.ne);
-		self.ss.mix_hash(&self.re.as_bytes()[..DHLEN]);
+	pub(crate) fn read_message_a(&mut self, message: &mut MessageBuffer<PublicKeyBytes<K>>) -> (Option<Vec<u8>>) {
+		self.re = K::PublicKey::from_slice(self.decode_ephemeral(&message.ne).as_ref())?;
+		self.check_remote_key(self.re, true)?;
+		self.ss.mix_hash(self.re.as_bytes().as_ref());
 		/* No PSK, so skipping mixKey */
 		if let Some(x) = self.ss.decrypt_and_hash(&message.ns) {
-			if x.len() != DHLEN {
-				return None
-			}
-			self.rs = PublicKey::from_bytes(from_slice_hashlen(&x[..]));
+			self.rs = K::PublicKey::from_slice(&x[..])?;
+			self.check_remote_key(self.rs, false)?;
 		} else { return None }
 		if let Some(plaintext) = self.ss.decrypt_and_hash(&message.ciphertext) {
+			let plaintext: Vec<u8> = self.check_certificate(plaintext)?;
 			return Some(plaintext);
 		}
 		None
 	}
 
-	pub(crate) fn read_message_b(&mut self, message: &mut MessageBuffer) -> (Option<Vec<u8>>) {
-		self.re = PublicKey::from_bytes(message.ne);
-		self.ss.mix_hash(&self.re.as_bytes()[..DHLEN]);
+	pub(crate) fn read_message_b(&mut self, message: &mut MessageBuffer<PublicKeyBytes<K>>) -> (Option<Vec<u8>>) {
+		self.re = K::PublicKey::from_slice(self.decode_ephemeral(&message.ne).as_ref())?;
+		self.check_remote_key(self.re, true)?;
+		self.ss.mix_hash(self.re.as_bytes().as_ref());
 		/* No PSK, so skipping mixKey */
 		self.ss.mix_key(&self.e.dh(&self.re.as_bytes()));
 		self.ss.mix_key(&self.e.dh(&self.rs.as_bytes()));
@@ -323,13 +516,14 @@ impl HandshakeState {
 		None
 	}
 
-	pub(crate) fn read_message_c(&mut self, message: &mut MessageBuffer) -> ( Option<(Hash, Vec<u8>, CipherState, CipherState)>) {
+	pub(crate) fn read_message_c(&mut self, message: &mut MessageBuffer<PublicKeyBytes<K>>) -> ( Option<(Hash, Vec<u8>, S::Cipher, S::Cipher, Hash)>) {
 		self.ss.mix_key(&self.e.dh(&self.rs.as_bytes()));
 		if let Some(plaintext) = self.ss.decrypt_and_hash(&message.ciphertext) {
-			let h: Hash = Hash::new(from_slice_hashlen(self.ss.h.as_bytes()));
+			let ck: Hash = Hash::new(self.ss.get_chaining_key());
 			let (cs1, cs2) = self.ss.split();
+			let h: Hash = Hash::new(self.ss.get_handshake_hash());
 			self.ss.clear();
-			return Some((h, plaintext, cs1, cs2));
+			return Some((h, plaintext, cs1, cs2, ck));
 		}
 		None
 	}
@@ -341,3 +535,25 @@ impl HandshakeState {
 fn initkey_test() {
 	CipherState::new();
 }
+
+#[test]
+fn remote_key_test() {
+	use crate::{consts::DHLEN, types::PublicKey};
+	let mut hs: HandshakeState = HandshakeState::initialize_responder(&[], Keypair::new(), PublicKey::empty(), Psk::new());
+	hs.set_ephemeral_keypair(Keypair::new());
+	assert!(hs.check_remote_key(Keypair::new().get_public_key(), true).is_some());
+	assert!(hs.check_remote_key(PublicKey::empty(), false).is_none());
+	assert!(hs.get_error() == Some(HandshakeError::LowOrderPoint));
+	assert!(hs.check_remote_key(hs.e.get_public_key(), true).is_none());
+	assert!(hs.get_error() == Some(HandshakeError::Reflection));
+	hs.set_reject_low_order(false);
+	assert!(hs.check_remote_key(PublicKey::from_bytes([0xffu8; DHLEN]), false).is_some());
+	let trusted: PublicKey = Keypair::new().get_public_key();
+	hs.set_static_key_verifier(Arc::new(move |rs: &PublicKey| rs.as_bytes() == trusted.as_bytes()));
+	assert!(hs.check_remote_key(trusted, false).is_some() && hs.check_remote_key(trusted, true).is_some());
+	assert!(hs.check_remote_key(Keypair::new().get_public_key(), false).is_none());
+	assert!(hs.get_error() == Some(HandshakeError::StaticKeyRejected));
+	let mut p_plus_one = [0xffu8; DHLEN];
+	p_plus_one[0] = 0xee;
+	assert!(PublicKey::from_bytes(p_plus_one).is_low_order());
+}
@@ -3,74 +3,249 @@
  * ---------------------------------------------------------------- */
 
 use crate::{
-    state::{CipherState, HandshakeState},
-    types::{Hash, Keypair, Message, MessageBuffer, Psk, PublicKey},
+    consts::{DHLEN, HASHLEN},
+    disco::DiscoSymmetricState,
+    knownpeers::KnownPeers,
+    prims::{hkdf, hmac},
+    state::{Cipher, HandshakeState, Symmetric, SymmetricState},
+    types::{HandshakeError, Hash, Keypair, Message, MessageBuffer, Psk, PublicKey, StaticKeyVerifier},
 };
+use crate::cert::{Certificate, AUTHORITY_KEY_LENGTH};
+use crate::{
+    ratchet::DoubleRatchet,
+    rehandshake::{Rehandshake, REHANDSHAKE_AD},
+};
+use std::sync::Arc;
+use zeroize::Zeroize;
+
+const EXPORTER_LABEL: &[u8] = b"NoiseExplorer exporter";
 
 #[derive(Clone)]
-pub struct NoiseSession {
-    hs: HandshakeState,
+pub struct NoiseSession<S: Symmetric = SymmetricState> {
+    hs: HandshakeState<S>,
     h: Hash,
-    cs1: CipherState,
-    cs2: CipherState,
+    exporter: Hash,
+    cs1: S::Cipher,
+    cs2: S::Cipher,
     mc: u32,
     i: bool,
+    dr: bool,
+    ratchet: Option<DoubleRatchet>,
+    rh: Option<Rehandshake<S>>,
+    rehandshake_read: bool,
+    previous_statics: Vec<Keypair>,
+    static_key_index: Option<usize>,
 }
+
 impl NoiseSession {
+	pub fn init_session(initiator: bool, prologue: Message, s: Keypair, rs: PublicKey) -> NoiseSession {
+		NoiseSession::init(initiator, prologue, s, rs)
+	}
+}
+
+impl NoiseSession<DiscoSymmetricState> {
+	pub fn init_disco_session(initiator: bool, prologue: Message, s: Keypair, rs: PublicKey) -> NoiseSession<DiscoSymmetricState> {
+		NoiseSession::init(initiator, prologue, s, rs)
+	}
+}
+
+impl<S: Symmetric> NoiseSession<S> {
 	pub fn set_ephemeral_keypair(&mut self, e: Keypair) {
         self.hs.set_ephemeral_keypair(e);
     }
-	pub fn init_session(initiator: bool, prologue: Message, s: Keypair, rs: PublicKey) -> NoiseSession {
+	/* Sends ephemeral keys as Elligator2 representatives; both parties must agree. */
+	pub fn set_elligator(&mut self, elligator: bool) {
+		self.hs.set_elligator(elligator);
+	}
+	/* On by default; turning it off accepts small-order keys as the Noise specification permits. */
+	pub fn set_reject_low_order(&mut self, reject_low_order: bool) {
+		self.hs.set_reject_low_order(reject_low_order);
+	}
+	/* Consulted on every static key the peer sends during the handshake. */
+	pub fn set_static_key_verifier(&mut self, verifier: Arc<dyn StaticKeyVerifier>) {
+		self.hs.set_static_key_verifier(verifier);
+	}
+	/* Vets the static key the peer sends against the store, as the peer called name. */
+	pub fn set_known_peer(&mut self, store: &Arc<KnownPeers>, name: &str) {
+		self.set_static_key_verifier(KnownPeers::verifier(store, name));
+	}
+	/* Why the last handshake message was refused, if it was refused for a reason other than failing to decrypt. */
+	pub fn get_handshake_error(&self) -> Option<HandshakeError> {
+		self.hs.get_error()
+	}
+	pub fn is_initiator(&self) -> bool {
+		self.i
+	}
+	pub(crate) fn get_message_count(&self) -> u32 {
+		self.mc
+	}
+	pub(crate) fn get_static_public_key(&self) -> PublicKey {
+		self.hs.get_static_keypair().get_public_key()
+	}
+	pub(crate) fn decode_ephemeral(&self, ne: &[u8; DHLEN]) -> [u8; DHLEN] {
+		self.hs.decode_ephemeral(ne)
+	}
+	/* Wipes the handshake secrets of a session that is being abandoned. */
+	pub(crate) fn clear_handshake(&mut self) {
+		self.hs.clear();
+		self.exporter.clear();
+		self.clear_previous_static_keys();
+	}
+	fn clear_previous_static_keys(&mut self) {
+		for s in self.previous_statics.iter_mut() {
+			s.clear();
+		}
+		self.previous_statics.clear();
+	}
+	/* Which static key the initiator's first message was sent to: 0 for the current one,
+	 * i for the i-th of set_previous_static_keys(). None until that message has been read. */
+	pub fn get_static_key_index(&self) -> Option<usize> {
+		self.static_key_index
+	}
+	/* Called at split with the final chaining key, which is wiped once the
+	 * exporter master secret, bound to the handshake hash, is derived from it. */
+	fn set_exporter_secret(&mut self, mut ck: Hash) {
+		let mut input: Vec<u8> = Vec::from(EXPORTER_LABEL);
+		input.extend_from_slice(&self.h.as_bytes()[..]);
+		let mut secret = [0u8; HASHLEN];
+		hkdf(&ck.as_bytes()[..], &input[..], 1, &mut secret[..], &mut [], &mut []);
+		self.exporter = Hash::new(secret);
+		secret.zeroize();
+		ck.clear();
+	}
+	/* Derives len bytes of keying material from the exporter master secret.
+	 * Outputs for different labels or contexts are independent of each other
+	 * and of the transport keys; re-handshakes do not change them. */
+	pub fn export_keying_material(&self, label: &[u8], context: &[u8], len: usize) -> Vec<u8> {
+		if !self.is_handshake_finished() {
+			panic!("Handshake not complete; export_keying_material");
+		}
+		if len > 255 * HASHLEN {
+			panic!("Output too long; export_keying_material");
+		}
+		let mut info: Vec<u8> = Vec::new();
+		info.extend_from_slice(&(label.len() as u64).to_be_bytes());
+		info.extend_from_slice(label);
+		info.extend_from_slice(&(context.len() as u64).to_be_bytes());
+		info.extend_from_slice(context);
+		let mut out: Vec<u8> = Vec::with_capacity(len);
+		let mut block: Vec<u8> = Vec::new();
+		let mut counter: u8 = 1;
+		while out.len() < len {
+			block.extend_from_slice(&info[..]);
+			block.push(counter);
+			let mut next = [0u8; HASHLEN];
+			hmac(&self.exporter.as_bytes()[..], &block[..], &mut next[..]);
+			block.zeroize();
+			block = Vec::from(&next[..]);
+			out.extend_from_slice(&next[..(len - out.len()).min(HASHLEN)]);
+			next.zeroize();
+			counter = counter.wrapping_add(1);
+		}
+		block.zeroize();
+		out
+	}
+	fn init(initiator: bool, prologue: Message, s: Keypair, rs: PublicKey) -> NoiseSession<S> {
 		if initiator {
 			NoiseSession{
 				hs: HandshakeState::initialize_initiator(prologue.as_bytes(), s, rs, Psk::new()),
 				mc: 0,
 				i: initiator,
-				cs1: CipherState::new(),
-				cs2: CipherState::new(),
+				cs1: S::Cipher::new(),
+				cs2: S::Cipher::new(),
 				h: Hash::empty(),
+				exporter: Hash::empty(),
+				dr: false,
+				ratchet: None,
+				rh: None,
+				rehandshake_read: false,
+				previous_statics: Vec::new(),
+				static_key_index: None,
 			}
 		} else {
 			NoiseSession {
 				hs: HandshakeState::initialize_responder(prologue.as_bytes(), s, rs, Psk::new()),
 				mc: 0,
 				i: initiator,
-				cs1: CipherState::new(),
-				cs2: CipherState::new(),
+				cs1: S::Cipher::new(),
+				cs2: S::Cipher::new(),
 				h: Hash::empty(),
+				exporter: Hash::empty(),
+				dr: false,
+				ratchet: None,
+				rh: None,
+				rehandshake_read: false,
+				previous_statics: Vec::new(),
+				static_key_index: None,
 			}
 		}
 	}
 	
-	pub fn send_message(&mut self, message: Message) -> MessageBuffer {
+	/* Returns None if a handshake message could not be written, for the reason given by
+	 * get_handshake_error(); the handshake cannot go on. */
+	pub fn send_message(&mut self, message: Message) -> Option<MessageBuffer> {
 		if self.mc == 0 {
-			self.mc += 1;
-			self.hs.write_message_a(&message.as_bytes()[..])
+			match self.hs.write_message_a(&message.as_bytes()[..]) {
+				Some(buffer) => {
+					self.mc += 1;
+					Some(buffer)
+				}
+				None => {
+					self.clear_handshake();
+					None
+				}
+			}
 		}
 		else if self.mc == 1 {
-			let temp = self.hs.write_message_b(&message.as_bytes()[..]);
+			let temp = match self.hs.write_message_b(&message.as_bytes()[..]) {
+				Some(temp) => temp,
+				None => {
+					self.clear_handshake();
+					return None;
+				}
+			};
 			self.h = temp.0;
 			self.cs1 = temp.2;
 			self.cs2 = temp.3;
+			if self.dr {
+				self.ratchet = Some(DoubleRatchet::new(
+					self.i,
+					&temp.4,
+					&self.h,
+					self.hs.get_ephemeral_keypair(),
+					self.hs.get_remote_ephemeral_public_key(),
+				));
+			}
+			self.rh = Some(Rehandshake::new(
+				self.i,
+				self.hs.get_static_keypair(),
+				self.hs.get_remote_static_public_key(),
+				self.h.clone(),
+			));
+			self.set_exporter_secret(temp.4);
 			self.hs.clear();
 			self.mc += 1;
-			temp.1
+			Some(temp.1)
 		}
-		else if self.i {
+		else if let Some(ratchet) = &mut self.ratchet {
+			self.mc += 1;
+			Some(ratchet.write_message(&message.as_bytes()[..]))
+		} else if self.i {
 			let buffer = self.cs1.write_message_regular(&message.as_bytes()[..]);
 			self.mc += 1;
-			buffer
+			Some(buffer)
 		} else {
 			let buffer = self.cs2.write_message_regular(&message.as_bytes()[..]);
 			self.mc += 1;
-			buffer
+			Some(buffer)
 		}
 	}
 	
 	pub fn recv_message(&mut self, message: &mut MessageBuffer) -> Option<Vec<u8>> {
 		let mut plaintext: Option<Vec<u8>> = None;
+		self.rehandshake_read = false;
 		if self.mc == 0 {
-			plaintext = self.hs.read_message_a(message);
+			plaintext = self.read_with_static_keys(message, HandshakeState::read_message_a);
 		}
 		else if self.mc == 1 {
 			if let Some(temp) = self.hs.read_message_b(message) {
@@ -78,21 +253,238 @@ impl NoiseSession {
 				plaintext = Some(temp.1);
 				self.cs1 = temp.2;
 				self.cs2 = temp.3;
+				if self.dr {
+					self.ratchet = Some(DoubleRatchet::new(
+						self.i,
+						&temp.4,
+						&self.h,
+						self.hs.get_ephemeral_keypair(),
+						self.hs.get_remote_ephemeral_public_key(),
+					));
+				}
+				self.rh = Some(Rehandshake::new(
+					self.i,
+					self.hs.get_static_keypair(),
+					self.hs.get_remote_static_public_key(),
+					self.h.clone(),
+				));
+				self.set_exporter_secret(temp.4);
 				self.hs.clear();
 			}
 		}
 		else if self.mc > 1 {
-			if self.i {
+			if let Some(ratchet) = &mut self.ratchet {
+				plaintext = ratchet.read_message(message);
+			} else if self.i {
 				if let Some(msg) = &self.cs2.read_message_regular(message) {
 					plaintext = Some(msg.to_owned());
+				} else {
+					plaintext = self.recv_rehandshake(message);
 				}
 			} else {
 				if let Some(msg) = &self.cs1.read_message_regular(message) {
 					plaintext = Some(msg.to_owned());
+				} else {
+					plaintext = self.recv_rehandshake(message);
 				}
 			}
 		}
 		self.mc += 1;
 		plaintext
 	}
+	
+	/* Sends a transport message whose authentication tag also covers ad, which the peer must pass to recv_message_with_ad(). */
+	pub fn send_message_with_ad(&mut self, ad: &[u8], message: Message) -> MessageBuffer {
+		if !self.is_handshake_finished() {
+			panic!("Handshake not complete; send_message_with_ad");
+		}
+		self.mc += 1;
+		if let Some(ratchet) = &mut self.ratchet {
+			ratchet.write_message_with_ad(ad, &message.as_bytes()[..])
+		} else if self.i {
+			self.cs1.write_message_with_ad(ad, &message.as_bytes()[..])
+		} else {
+			self.cs2.write_message_with_ad(ad, &message.as_bytes()[..])
+		}
+	}
+	
+	pub fn recv_message_with_ad(&mut self, ad: &[u8], message: &mut MessageBuffer) -> Option<Vec<u8>> {
+		if !self.is_handshake_finished() {
+			panic!("Handshake not complete; recv_message_with_ad");
+		}
+		self.mc += 1;
+		self.rehandshake_read = false;
+		if let Some(ratchet) = &mut self.ratchet {
+			return ratchet.read_message_with_ad(ad, message);
+		}
+		let plaintext: Option<Vec<u8>> = if self.i {
+			self.cs2.read_message_with_ad(ad, message)
+		} else {
+			self.cs1.read_message_with_ad(ad, message)
+		};
+		plaintext.or_else(|| self.recv_rehandshake(message))
+	}
+	
+	/* The halves cannot re-handshake: re-handshake messages from the peer fail to
+	 * decrypt in the Receiver, so a re-handshake under way must finish first. */
+	pub fn into_split(self) -> (Sender<S>, Receiver<S>) {
+		if !self.is_handshake_finished() {
+			panic!("Handshake not complete; into_split");
+		}
+		if self.ratchet.is_some() {
+			panic!("Double ratchet sessions cannot be split; into_split");
+		}
+		if self.rh.as_ref().is_some_and(|rh| rh.in_progress()) {
+			panic!("Re-handshake in progress; into_split");
+		}
+		if self.i {
+			(Sender { cs: Some(self.cs1) }, Receiver { cs: Some(self.cs2) })
+		} else {
+			(Sender { cs: Some(self.cs2) }, Receiver { cs: Some(self.cs1) })
+		}
+	}
+	
+	pub fn is_handshake_finished(&self) -> bool {
+		self.mc > 1
+	}
+	
+	/* Static keypairs the responder used before its current one, still accepted from initiators that have not yet learned the new key. */
+	pub fn set_previous_static_keys(&mut self, keys: Vec<Keypair>) {
+		if self.i || self.mc != 0 {
+			panic!("Not a fresh responder session; set_previous_static_keys");
+		}
+		self.previous_statics = keys;
+	}
+	
+	/* Sent with our static key during the handshake; the peer must have trust anchors set. */
+	pub fn set_certificate(&mut self, certificate: Certificate) {
+		self.hs.set_certificate(certificate);
+	}
+	
+	/* Requires a certificate for the peer's static key from one of these authorities. */
+	pub fn set_trust_anchors(&mut self, anchors: Vec<[u8; AUTHORITY_KEY_LENGTH]>) {
+		self.hs.set_trust_anchors(anchors);
+	}
+	
+	pub fn get_remote_certificate(&self) -> Option<Certificate> {
+		self.hs.get_remote_certificate()
+	}
+	
+	/* Reads the initiator's first message with our current static key, or failing
+	 * that with each previous one in turn, each trial starting from a copy of the
+	 * untouched handshake state. */
+	fn read_with_static_keys<T, F>(&mut self, message: &mut MessageBuffer, read: F) -> Option<T>
+	where
+		F: Fn(&mut HandshakeState<S>, &mut MessageBuffer) -> Option<T>,
+	{
+		if self.previous_statics.is_empty() {
+			let result: Option<T> = read(&mut self.hs, message);
+			self.static_key_index = result.as_ref().map(|_| 0);
+			return result;
+		}
+		let original: MessageBuffer = message.clone();
+		let mut pristine: HandshakeState<S> = self.hs.clone();
+		let mut result: Option<T> = read(&mut self.hs, message);
+		if result.is_some() {
+			self.static_key_index = Some(0);
+		}
+		for (i, s) in self.previous_statics.iter().enumerate() {
+			if result.is_some() {
+				break;
+			}
+			let mut hs: HandshakeState<S> = pristine.with_static_keypair(s.clone());
+			result = read(&mut hs, &mut original.clone());
+			if result.is_some() {
+				self.hs.clear();
+				self.hs = hs;
+				self.static_key_index = Some(i + 1);
+			} else {
+				hs.clear();
+			}
+		}
+		pristine.clear();
+		self.clear_previous_static_keys();
+		result
+	}
+	
+	/* Runs transport messages through a double ratchet seeded from the handshake; both parties must agree. */
+	pub fn set_ratchet(&mut self, ratchet: bool) {
+		self.dr = ratchet;
+	}
+	
+	/* Starts a fresh handshake inside the transport channel, KK if both parties
+	 * have static keys and NN otherwise. Application data keeps flowing while the
+	 * peer replies; each side switches keys after take_rehandshake_message().
+	 * None if the static key oracle fails, in which case the channel is left as is. */
+	pub fn rehandshake(&mut self) -> Option<MessageBuffer> {
+		if self.ratchet.is_some() {
+			panic!("Double ratchet sessions cannot re-handshake; rehandshake");
+		}
+		let rh = self.rh.as_mut().expect("Two-way handshake not complete; rehandshake");
+		if rh.in_progress() {
+			panic!("Re-handshake already in progress; rehandshake");
+		}
+		let control: Vec<u8> = rh.request()?;
+		let cs = if self.i { &mut self.cs1 } else { &mut self.cs2 };
+		Some(cs.write_message_with_ad(REHANDSHAKE_AD, &control[..]))
+	}
+	
+	/* Returns the re-handshake message owed to the peer after recv_message() consumed one. */
+	pub fn take_rehandshake_message(&mut self) -> Option<MessageBuffer> {
+		let (control, next) = self.rh.as_mut()?.take_reply()?;
+		let cs = if self.i { &mut self.cs1 } else { &mut self.cs2 };
+		let message: MessageBuffer = cs.write_message_with_ad(REHANDSHAKE_AD, &control[..]);
+		if let Some(next) = next {
+			*cs = next;
+		}
+		Some(message)
+	}
+	
+	/* True if the message last read by recv_message() or recv_message_with_ad() was a
+	 * re-handshake message, which reads as an empty payload, rather than application data. */
+	pub fn is_rehandshake_message(&self) -> bool {
+		self.rehandshake_read
+	}
+	
+	fn recv_rehandshake(&mut self, message: &MessageBuffer) -> Option<Vec<u8>> {
+		let rh = self.rh.as_mut()?;
+		let cs = if self.i { &mut self.cs2 } else { &mut self.cs1 };
+		let control: Vec<u8> = cs.read_message_with_ad(REHANDSHAKE_AD, message)?;
+		if let Some(next) = rh.read(&control[..])? {
+			*cs = next;
+		}
+		self.rehandshake_read = true;
+		Some(Vec::new())
+	}
+}
+
+/* Either half is empty for the party that cannot use it in a one-way pattern. */
+pub struct Sender<S: Symmetric = SymmetricState> {
+	cs: Option<S::Cipher>,
+}
+impl<S: Symmetric> Sender<S> {
+	pub fn send_message(&mut self, message: Message) -> MessageBuffer {
+		match &mut self.cs {
+			Some(cs) => cs.write_message_regular(&message.as_bytes()[..]),
+			None => panic!("Responder cannot send in a one-way pattern; send_message"),
+		}
+	}
+	pub fn send_message_with_ad(&mut self, ad: &[u8], message: Message) -> MessageBuffer {
+		match &mut self.cs {
+			Some(cs) => cs.write_message_with_ad(ad, &message.as_bytes()[..]),
+			None => panic!("Responder cannot send in a one-way pattern; send_message_with_ad"),
+		}
+	}
+}
+
+pub struct Receiver<S: Symmetric = SymmetricState> {
+	cs: Option<S::Cipher>,
+}
+impl<S: Symmetric> Receiver<S> {
+	pub fn recv_message(&mut self, message: &mut MessageBuffer) -> Option<Vec<u8>> {
+		self.cs.as_mut()?.read_message_regular(message)
+	}
+	pub fn recv_message_with_ad(&mut self, ad: &[u8], message: &mut MessageBuffer) -> Option<Vec<u8>> {
+		self.cs.as_mut()?.read_message_with_ad(ad, message)
+	}
 }
@@ -0,0 +1,347 @@
+/* ---------------------------------------------------------------- *
+ * ELLIGATOR                                                        *
+ * ---------------------------------------------------------------- */
+
+use crate::consts::DHLEN;
+use byteorder::{ByteOrder, LittleEndian};
+use crypto::util::fixed_time_eq;
+use rand::RngCore;
+
+const MASK: u64 = (1 << 51) - 1;
+const A: u64 = 486_662;
+/* Little-endian exponents: p - 2, (p - 1) / 2, (p + 3) / 8 and (p - 1) / 4. */
+const P_MINUS_TWO: [u8; 32] = exponent(0xeb, 0x7f);
+const P_MINUS_ONE_HALF: [u8; 32] = exponent(0xf6, 0x3f);
+const P_PLUS_THREE_EIGHTH: [u8; 32] = exponent(0xfe, 0x0f);
+const P_MINUS_ONE_QUARTER: [u8; 32] = exponent(0xfb, 0x1f);
+/* Little-endian order of the prime-order subgroup, and the u-coordinate of
+ * the base point plus a point of order 8. */
+const L: [u8; 32] = [
+	0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+	0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
+];
+const DIRTY_BASE_POINT: [u8; 32] = [
+	0xbb, 0x72, 0x31, 0x21, 0x70, 0xe8, 0x15, 0x6f, 0x7a, 0x83, 0x63, 0x13, 0xf8, 0x5b, 0xee, 0x9b,
+	0x1f, 0xdc, 0xe9, 0x26, 0xba, 0x98, 0x04, 0xa2, 0x9e, 0x8d, 0x13, 0x7e, 0xc6, 0x7f, 0x25, 0x33,
+];
+const A24: u64 = 121_665;
+
+const fn exponent(low: u8, high: u8) -> [u8; 32] {
+	let mut e = [0xffu8; 32];
+	e[0] = low;
+	e[31] = high;
+	e
+}
+
+/* Field element modulo 2^255 - 19, as five 51-bit limbs. Nothing below
+ * branches or returns early on the value of an element. */
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+	fn from_u64(n: u64) -> Fe {
+		Fe([n, 0, 0, 0, 0])
+	}
+	/* b if choice is 1, a if it is 0. */
+	fn select(a: &Fe, b: &Fe, choice: u64) -> Fe {
+		let mask: u64 = 0u64.wrapping_sub(choice);
+		let mut l: [u64; 5] = a.0;
+		for (x, y) in l.iter_mut().zip(b.0.iter()) {
+			*x ^= mask & (*x ^ y);
+		}
+		Fe(l)
+	}
+	fn from_bytes(b: &[u8; 32]) -> Fe {
+		Fe([
+			LittleEndian::read_u64(&b[0..8]) & MASK,
+			(LittleEndian::read_u64(&b[6..14]) >> 3) & MASK,
+			(LittleEndian::read_u64(&b[12..20]) >> 6) & MASK,
+			(LittleEndian::read_u64(&b[19..27]) >> 1) & MASK,
+			(LittleEndian::read_u64(&b[24..32]) >> 12) & MASK,
+		])
+	}
+	fn reduce(mut l: [u64; 5]) -> Fe {
+		let c = [l[0] >> 51, l[1] >> 51, l[2] >> 51, l[3] >> 51, l[4] >> 51];
+		for limb in l.iter_mut() {
+			*limb &= MASK;
+		}
+		l[0] += c[4] * 19;
+		l[1] += c[0];
+		l[2] += c[1];
+		l[3] += c[2];
+		l[4] += c[3];
+		Fe(l)
+	}
+	fn to_bytes(self) -> [u8; 32] {
+		let mut l = Fe::reduce(self.0).0;
+		let mut q = (l[0] + 19) >> 51;
+		q = (l[1] + q) >> 51;
+		q = (l[2] + q) >> 51;
+		q = (l[3] + q) >> 51;
+		q = (l[4] + q) >> 51;
+		l[0] += 19 * q;
+		l[1] += l[0] >> 51;
+		l[0] &= MASK;
+		l[2] += l[1] >> 51;
+		l[1] &= MASK;
+		l[3] += l[2] >> 51;
+		l[2] &= MASK;
+		l[4] += l[3] >> 51;
+		l[3] &= MASK;
+		l[4] &= MASK;
+		let mut out = [0u8; 32];
+		LittleEndian::write_u64(&mut out[0..8], l[0] | (l[1] << 51));
+		LittleEndian::write_u64(&mut out[8..16], (l[1] >> 13) | (l[2] << 38));
+		LittleEndian::write_u64(&mut out[16..24], (l[2] >> 26) | (l[3] << 25));
+		LittleEndian::write_u64(&mut out[24..32], (l[3] >> 39) | (l[4] << 12));
+		out
+	}
+	fn add(&self, b: &Fe) -> Fe {
+		let (a, b) = (&self.0, &b.0);
+		Fe::reduce([a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3], a[4] + b[4]])
+	}
+	/* Adds 2p before subtracting so that limbs never underflow. */
+	fn sub(&self, b: &Fe) -> Fe {
+		let (a, b) = (&Fe::reduce(self.0).0, &Fe::reduce(b.0).0);
+		Fe::reduce([
+			(a[0] + 0xf_ffff_ffff_ffda) - b[0],
+			(a[1] + 0xf_ffff_ffff_fffe) - b[1],
+			(a[2] + 0xf_ffff_ffff_fffe) - b[2],
+			(a[3] + 0xf_ffff_ffff_fffe) - b[3],
+			(a[4] + 0xf_ffff_ffff_fffe) - b[4],
+		])
+	}
+	fn neg(&self) -> Fe {
+		Fe::from_u64(0).sub(self)
+	}
+	fn mul(&self, b: &Fe) -> Fe {
+		let (a, b) = (&self.0, &b.0);
+		let m = |x: u64, y: u64| u128::from(x) * u128::from(y);
+		let (b1, b2, b3, b4) = (b[1] * 19, b[2] * 19, b[3] * 19, b[4] * 19);
+		let c0 = m(a[0], b[0]) + m(a[4], b1) + m(a[3], b2) + m(a[2], b3) + m(a[1], b4);
+		let mut c1 = m(a[1], b[0]) + m(a[0], b[1]) + m(a[4], b2) + m(a[3], b3) + m(a[2], b4);
+		let mut c2 = m(a[2], b[0]) + m(a[1], b[1]) + m(a[0], b[2]) + m(a[4], b3) + m(a[3], b4);
+		let mut c3 = m(a[3], b[0]) + m(a[2], b[1]) + m(a[1], b[2]) + m(a[0], b[3]) + m(a[4], b4);
+		let mut c4 = m(a[4], b[0]) + m(a[3], b[1]) + m(a[2], b[2]) + m(a[1], b[3]) + m(a[0], b[4]);
+		c1 += c0 >> 51;
+		c2 += c1 >> 51;
+		c3 += c2 >> 51;
+		c4 += c3 >> 51;
+		let mask = u128::from(MASK);
+		let mut l: [u64; 5] = [
+			(c0 & mask) as u64,
+			(c1 & mask) as u64,
+			(c2 & mask) as u64,
+			(c3 & mask) as u64,
+			(c4 & mask) as u64,
+		];
+		l[0] += ((c4 >> 51) as u64) * 19;
+		l[1] += l[0] >> 51;
+		l[0] &= MASK;
+		Fe(l)
+	}
+	fn square(&self) -> Fe {
+		self.mul(self)
+	}
+	/* Multiplies on every bit, keeping the product only where the exponent has a one. */
+	fn pow(&self, e: &[u8; 32]) -> Fe {
+		let mut result: Fe = Fe::from_u64(1);
+		for i in (0..256).rev() {
+			result = result.square();
+			let product: Fe = result.mul(self);
+			result = Fe::select(&result, &product, u64::from((e[i / 8] >> (i % 8)) & 1));
+		}
+		result
+	}
+	fn invert(&self) -> Fe {
+		self.pow(&P_MINUS_TWO)
+	}
+	fn equals(&self, b: &Fe) -> bool {
+		fixed_time_eq(&self.to_bytes()[..], &b.to_bytes()[..])
+	}
+	fn is_zero(&self) -> bool {
+		self.equals(&Fe::from_u64(0))
+	}
+	/* Zero counts as a square. */
+	fn is_square(&self) -> bool {
+		!self.pow(&P_MINUS_ONE_HALF).equals(&Fe::from_u64(1).neg())
+	}
+	/* True for the upper half (p - 1) / 2 < x < p, where 2x mod p is odd. */
+	fn is_negative(&self) -> bool {
+		self.add(self).to_bytes()[0] & 1 == 1
+	}
+	/* Both candidate roots are computed and one selected; only whether there
+	 * is a root at all decides the result. */
+	fn sqrt(&self) -> Option<Fe> {
+		let candidate: Fe = self.pow(&P_PLUS_THREE_EIGHTH);
+		let other: Fe = candidate.mul(&Fe::from_u64(2).pow(&P_MINUS_ONE_QUARTER));
+		let root: Fe = Fe::select(&other, &candidate, u64::from(candidate.square().equals(self)));
+		if root.square().equals(self) {
+			Some(root)
+		} else {
+			None
+		}
+	}
+	fn swap(a: &mut Fe, b: &mut Fe, choice: u64) {
+		let (x, y) = (Fe::select(a, b, choice), Fe::select(b, a, choice));
+		*a = x;
+		*b = y;
+	}
+}
+
+/* Montgomery ladder over all 256 bits of an unclamped scalar, returning X and
+ * Z of the projective result; Z is zero for the point at infinity. */
+fn ladder(u: &Fe, scalar: &[u8; 32]) -> (Fe, Fe) {
+	let a24: Fe = Fe::from_u64(A24);
+	let (mut x2, mut z2, mut x3, mut z3) = (Fe::from_u64(1), Fe::from_u64(0), *u, Fe::from_u64(1));
+	let mut swap: u64 = 0;
+	for i in (0..256).rev() {
+		let bit = u64::from((scalar[i / 8] >> (i % 8)) & 1);
+		swap ^= bit;
+		Fe::swap(&mut x2, &mut x3, swap);
+		Fe::swap(&mut z2, &mut z3, swap);
+		swap = bit;
+		let a: Fe = x2.add(&z2);
+		let aa: Fe = a.square();
+		let b: Fe = x2.sub(&z2);
+		let bb: Fe = b.square();
+		let e: Fe = aa.sub(&bb);
+		let da: Fe = x3.sub(&z3).mul(&a);
+		let cb: Fe = x3.add(&z3).mul(&b);
+		x3 = da.add(&cb).square();
+		z3 = u.mul(&da.sub(&cb).square());
+		x2 = aa.mul(&bb);
+		z2 = e.mul(&aa.add(&a24.mul(&e)));
+	}
+	Fe::swap(&mut x2, &mut x3, swap);
+	Fe::swap(&mut z2, &mut z3, swap);
+	(x2, z2)
+}
+
+/* The public key of a private key with a random low-order component added,
+ * [s]B + [t]T for T of order 8, as obfs4 and Monocypher do: clamped public keys
+ * all lie in the prime-order subgroup, which would tell representatives apart
+ * from random bytes. The scalar s + kL, with kL = t modulo 8, is congruent to s
+ * modulo L, so every peer, whose clamped scalar is a multiple of 8, computes the
+ * same DH result as with the clamped public key. */
+pub(crate) fn dirty_public_key(private_key: &[u8; DHLEN]) -> [u8; DHLEN] {
+	let mut random = [0u8; 1];
+	rand::thread_rng().fill_bytes(&mut random);
+	let mut s: [u8; 32] = *private_key;
+	s[0] &= 248;
+	s[31] &= 127;
+	s[31] |= 64;
+	/* L is 5 modulo 8, and 5 is its own inverse modulo 8. */
+	let k: u16 = u16::from((random[0] & 7) * 5 % 8);
+	let mut scalar = [0u8; 32];
+	let mut carry: u16 = 0;
+	for ((out, s), l) in scalar.iter_mut().zip(s.iter()).zip(L.iter()) {
+		let sum: u16 = u16::from(*s) + k * u16::from(*l) + carry;
+		*out = sum as u8;
+		carry = sum >> 8;
+	}
+	s.iter_mut().for_each(|b| *b = 0);
+	let (x, z) = ladder(&Fe::from_bytes(&DIRTY_BASE_POINT), &scalar);
+	scalar.iter_mut().for_each(|b| *b = 0);
+	x.mul(&z.invert()).to_bytes()
+}
+
+pub(crate) fn is_representable(public_key: &[u8; DHLEN]) -> bool {
+	let u: Fe = Fe::from_bytes(public_key);
+	let u_plus_a: Fe = u.add(&Fe::from_u64(A));
+	!u.is_zero() & !u_plus_a.is_zero() & u.mul(&u_plus_a).mul(&Fe::from_u64(2)).neg().is_square()
+}
+
+/* Either of the two non-negative representatives of a point maps back to it,
+ * so one is picked at random, and the two unused top bits are randomized. */
+pub(crate) fn encode(public_key: &[u8; DHLEN]) -> Option<[u8; DHLEN]> {
+	if !is_representable(public_key) {
+		return None;
+	}
+	let mut random = [0u8; 1];
+	rand::thread_rng().fill_bytes(&mut random);
+	let u: Fe = Fe::from_bytes(public_key);
+	let u_plus_a: Fe = u.add(&Fe::from_u64(A));
+	let two: Fe = Fe::from_u64(2);
+	let r_squared: Fe = Fe::select(
+		&u.neg().mul(&two.mul(&u_plus_a).invert()),
+		&u_plus_a.neg().mul(&two.mul(&u).invert()),
+		u64::from(random[0] & 1),
+	);
+	let r: Fe = r_squared.sqrt()?;
+	let r: Fe = Fe::select(&r, &r.neg(), u64::from(r.is_negative()));
+	let mut representative: [u8; DHLEN] = r.to_bytes();
+	representative[31] |= random[0] & 0xc0;
+	Some(representative)
+}
+
+pub(crate) fn decode(representative: &[u8; DHLEN]) -> [u8; DHLEN] {
+	let mut r_bytes: [u8; DHLEN] = *representative;
+	r_bytes[31] &= 0x3f;
+	let r: Fe = Fe::from_bytes(&r_bytes);
+	let a: Fe = Fe::from_u64(A);
+	let w: Fe = a.neg().mul(&Fe::from_u64(1).add(&Fe::from_u64(2).mul(&r.square())).invert());
+	let w_squared: Fe = w.square();
+	let f: Fe = w_squared.mul(&w).add(&a.mul(&w_squared)).add(&w);
+	Fe::select(&w.neg().sub(&a), &w, u64::from(f.is_square())).to_bytes()
+}
+
+#[test]
+fn elligator_test() {
+	let vectors: [(&str, &str, &str); 3] = [
+		(
+			"6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+			"87451c8b5380359490d186a4decaf0bc8e5f21bc767dd40582f7ac2db7324215",
+			"2f69a232b3f34a8b26594e95a9a4a5934d05af6f79d8624b77cca8d266b3fd3b",
+		),
+		(
+			"4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785451a",
+			"bb344e6de18d93f87c88f3bcf93de7a82f7c1c516c0bf4640602f6dc8a53ee18",
+			"23b305f79d025b3cb633fbe7ee41fbaf832a96b935186eaf31db1e06aa89b84c",
+		),
+		(
+			"dbc1b4c900ffe48d575b5da5c638040125f65db0fe3e24494b76ea986457d906",
+			"24c3b060db2112391ba79bdc0c068f97ce73d548584c3b6f97fc4b995d08760d",
+			"074ef62b9225b194fd56c260857cc36e88a771ec7a2df68f22e97eb90b073974",
+		),
+	];
+	let from_hex = |s: &str| {
+		let mut k = [0u8; DHLEN];
+		k.copy_from_slice(&hex::decode(s).unwrap()[..]);
+		k
+	};
+	for (r1, r2, u) in vectors.iter() {
+		let (r1, r2, u) = (from_hex(r1), from_hex(r2), from_hex(u));
+		let mut r1_high = r1;
+		r1_high[31] |= 0xc0;
+		assert!(decode(&r1) == u && decode(&r2) == u && decode(&r1_high) == u);
+		for _ in 0..8 {
+			let mut r: [u8; DHLEN] = encode(&u).unwrap();
+			r[31] &= 0x3f;
+			assert!(r == r1 || r == r2);
+		}
+	}
+	let not_representable: [u8; DHLEN] = from_hex("6bc3822a2aa7f4e6981d6538692b3cdf3e6df9eea6ed269eb41d93c22757b75a");
+	assert!(encode(&not_representable).is_none());
+	let base_point: [u8; DHLEN] = from_hex("0900000000000000000000000000000000000000000000000000000000000000");
+	assert!(decode(&encode(&base_point).unwrap()) == base_point);
+}
+
+#[test]
+fn elligator_dirty_key_test() {
+	use crate::types::Keypair;
+	let mut outside: usize = 0;
+	for _ in 0..128 {
+		let keypair: Keypair = Keypair::new_elligator();
+		let peer: Keypair = Keypair::new();
+		let u: [u8; DHLEN] = decode(&encode(&keypair.get_public_key().as_bytes()).unwrap());
+		assert!(u == keypair.get_public_key().as_bytes());
+		assert!(peer.dh(&u) == keypair.dh(&peer.get_public_key().as_bytes()));
+		let (_, z) = ladder(&Fe::from_bytes(&u), &L);
+		if !z.is_zero() {
+			outside += 1;
+		}
+	}
+	/* 7/8 of 128 keys are expected outside the prime-order subgroup. */
+	assert!((96..=124).contains(&outside));
+}
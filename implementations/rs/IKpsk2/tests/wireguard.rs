@@ -0,0 +1,101 @@
+#![allow(non_snake_case, non_upper_case_globals)]
+
+use noiseexplorer_ikpsk2::{
+	types::{Keypair, PrivateKey, Psk, PublicKey},
+	wireguard::{CookieChecker, Handshake, Tai64N, Transport},
+};
+
+fn decode(s: &str) -> Vec<u8> {
+	hex::decode(s).unwrap()
+}
+
+fn handshake_pair() -> (Handshake, Handshake) {
+	let init_static: PrivateKey = PrivateKey::from_str("e61ef9919cde45dd5f82166404bd08e38bceb5dfdfded0a34c8df7ed542214d1");
+	let resp_static: PrivateKey = PrivateKey::from_str("4a3acbfdb163dec651dfa3194dece676d437029c62a408b4c5ea9114246e4893");
+	let resp_static_public: PublicKey = resp_static.generate_public_key();
+	let psk: &str = "54686973206973206d7920417573747269616e20706572737065637469766521";
+	let mut initiator: Handshake = Handshake::initiator(Keypair::from_private_key(init_static), resp_static_public, Psk::from_str(psk), 0x11223344);
+	let mut responder: Handshake = Handshake::responder(Keypair::from_private_key(resp_static), Psk::from_str(psk), 0x55667788);
+	initiator.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("893e28b9dc6ca8d611ab664754b8ceb7bac5117349a4439a6b0569da977c464a")));
+	responder.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("bbdb4cdbd309f1a1f2e1456967fe288cadd6f712d65dc7b7793d5e63da6b375b")));
+	(initiator, responder)
+}
+
+fn timestamp() -> Tai64N {
+	let mut t = [0u8; 12];
+	t.copy_from_slice(&decode("400000005d2b3c4a075bcd15")[..]);
+	Tai64N::from_bytes(t)
+}
+
+fn transport_pair() -> (Transport, Transport) {
+	let (mut initiator, mut responder) = handshake_pair();
+	let initiation: Vec<u8> = initiator.create_initiation(Tai64N::now()).unwrap();
+	responder.consume_initiation(&initiation[..]).unwrap();
+	let (response, responder_transport) = responder.create_response().unwrap();
+	let initiator_transport: Transport = initiator.consume_response(&response[..]).unwrap();
+	(initiator_transport, responder_transport)
+}
+
+/* Expected packets were reproduced by a separate implementation written from the WireGuard whitepaper; they have not been compared with packets captured from wireguard-go or the kernel module. */
+#[test]
+fn noiseexplorer_test_wireguard_vectors() {
+	let (mut initiator, mut responder) = handshake_pair();
+	let initiation: Vec<u8> = initiator.create_initiation(timestamp()).unwrap();
+	assert_eq!(initiation, decode("0100000044332211ca35def5ae56cec33dc2036731ab14896bc4c75dbb07a61f879f8e3afa4c7944001e21de9f98ddd8e2ad57527207feb56253c9c94a9e496782ecfcb2a75fbcaf752d6cf9956f6a4cc1a138d6de1e87238cbd2c461513387d8393fb0c9518f53a9317ffddcef30adb3a61621080295c62b755e306c179417a0c7c59f200000000000000000000000000000000"));
+	assert!(responder.consume_initiation(&initiation[..]) == Some(timestamp()));
+	assert_eq!(responder.get_remote_index(), 0x11223344);
+	let (response, mut responder_transport) = responder.create_response().unwrap();
+	assert_eq!(response, decode("02000000887766554433221195ebc60d2b1fa672c1f46a8aa265ef51bfe38e7ccb39ec5be34069f144808843bf18b4492db7cb2fda29ddda1c603293c1a4fde15fb7d55f99ccc3ccfe45d99a00000000000000000000000000000000"));
+	let mut initiator_transport: Transport = initiator.consume_response(&response[..]).unwrap();
+	assert_eq!(initiator_transport.get_remote_index(), 0x55667788);
+	let data: Vec<u8> = initiator_transport.encapsulate(b"WireGuard transport");
+	assert_eq!(data, decode("04000000887766550000000000000000901498bc5ae915088ce2f9671dce1793b08470549c5aa4f3b2f485312e8f221097cd8453ab43fa336a8b2db1e6a8d4c3"));
+	assert_eq!(responder_transport.decapsulate(&data[..]).unwrap(), b"WireGuard transport\0\0\0\0\0\0\0\0\0\0\0\0\0".to_vec());
+	let keepalive: Vec<u8> = responder_transport.encapsulate(&[]);
+	assert_eq!(keepalive, decode("04000000443322110000000000000000e8a8e161b09cb570dd2b4bdf7af6f045"));
+	assert_eq!(initiator_transport.decapsulate(&keepalive[..]).unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn noiseexplorer_test_wireguard_cookie_reply() {
+	let (mut initiator, _) = handshake_pair();
+	initiator.create_initiation(timestamp()).unwrap();
+	let cookie_reply: Vec<u8> = decode("0300000044332211000102030405060708090a0b0c0d0e0f101112131415161738d6b6f30ab983c7a758e8b37eeb9a47b3e683e22c9c653003a9524ee2930e5c");
+	assert!(initiator.consume_cookie_reply(&cookie_reply[..]).is_some());
+
+	let (mut initiator, mut responder) = handshake_pair();
+	let resp_static: PrivateKey = PrivateKey::from_str("4a3acbfdb163dec651dfa3194dece676d437029c62a408b4c5ea9114246e4893");
+	let mut checker: CookieChecker = CookieChecker::new(resp_static.generate_public_key());
+	let source: Vec<u8> = decode("c0a80001ca6c");
+	let initiation: Vec<u8> = initiator.create_initiation(Tai64N::now()).unwrap();
+	assert!(checker.verify_mac1(&initiation[..]));
+	assert!(!checker.verify_mac2(&initiation[..], &source[..]));
+	let cookie_reply: Vec<u8> = checker.create_reply(&initiation[..], &source[..]).unwrap();
+	assert!(initiator.consume_cookie_reply(&cookie_reply[..]).is_some());
+	let initiation: Vec<u8> = initiator.create_initiation(Tai64N::now()).unwrap();
+	assert!(checker.verify_mac2(&initiation[..], &source[..]));
+	assert!(!checker.verify_mac2(&initiation[..], &decode("c0a80002ca6c")[..]));
+	assert!(responder.consume_initiation(&initiation[..]).is_some());
+}
+
+#[test]
+fn noiseexplorer_test_wireguard_rejects_invalid() {
+	let (mut initiator, mut responder) = handshake_pair();
+	let mut initiation: Vec<u8> = initiator.create_initiation(timestamp()).unwrap();
+	initiation[50] ^= 0x01;
+	assert!(responder.consume_initiation(&initiation[..]).is_none());
+	assert!(responder.consume_initiation(&initiation[..100]).is_none());
+
+	let (mut initiator_transport, mut responder_transport) = transport_pair();
+	let first: Vec<u8> = initiator_transport.encapsulate(b"first");
+	let second: Vec<u8> = initiator_transport.encapsulate(b"second");
+	let third: Vec<u8> = initiator_transport.encapsulate(b"third");
+	assert!(responder_transport.decapsulate(&third[..]).is_some());
+	assert!(responder_transport.decapsulate(&first[..]).is_some());
+	assert!(responder_transport.decapsulate(&first[..]).is_none());
+	let mut tampered: Vec<u8> = second.clone();
+	tampered[20] ^= 0x01;
+	assert!(responder_transport.decapsulate(&tampered[..]).is_none());
+	assert!(responder_transport.decapsulate(&second[..]).is_some());
+	assert!(initiator_transport.decapsulate(&second[..]).is_none());
+}
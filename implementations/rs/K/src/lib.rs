@@ -14,8 +14,18 @@ K:
 pub(crate) mod macros;
 
 pub(crate) mod consts;
+pub(crate) mod elligator;
 pub(crate) mod prims;
 pub(crate) mod state;
 
+pub mod cert;
+pub mod cookie;
+pub mod disco;
+pub mod knownpeers;
 pub mod noisesession;
+pub mod noisesocket;
+pub mod openssh;
+pub mod oracle;
+pub mod replay;
+pub mod table;
 pub mod types;
\ No newline at end of file
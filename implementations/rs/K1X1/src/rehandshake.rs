@@ -0,0 +1,181 @@
+/* ---------------------------------------------------------------- *
+ * REHANDSHAKE                                                      *
+ * ---------------------------------------------------------------- */
+
+/* A fresh KK handshake, or NN when the pattern leaves a party without a
+ * static key, carried inside transport messages under REHANDSHAKE_AD and
+ * bound to the session by using its handshake hash as prologue. Each party
+ * keeps sending on its old key until it has sent the message telling the
+ * peer to switch, so application data in flight is never lost. */
+
+use crate::{
+	consts::DHLEN,
+	state::Symmetric,
+	types::{Hash, Keypair, PublicKey},
+};
+
+pub(crate) const REHANDSHAKE_AD: &[u8] = b"NoiseExplorer rehandshake";
+const REQUEST: u8 = 1;
+const RESPONSE: u8 = 2;
+const CONFIRM: u8 = 3;
+
+fn read_public_key(body: &[u8]) -> Option<PublicKey> {
+	if body.len() < DHLEN {
+		return None;
+	}
+	let mut k = [0u8; DHLEN];
+	k.copy_from_slice(&body[..DHLEN]);
+	Some(PublicKey::from_bytes(k))
+}
+
+#[derive(Clone)]
+pub(crate) struct Rehandshake<S: Symmetric> {
+	initiator: bool,
+	s: Keypair,
+	rs: PublicKey,
+	h: Hash,
+	pending: Option<(S, Keypair)>,
+	reply: Option<Vec<u8>>,
+	next_send: Option<S::Cipher>,
+	next_recv: Option<S::Cipher>,
+}
+
+impl<S: Symmetric> Rehandshake<S> {
+	pub(crate) fn new(initiator: bool, s: Keypair, rs: PublicKey, h: Hash) -> Rehandshake<S> {
+		Rehandshake {
+			initiator,
+			s,
+			rs,
+			h,
+			pending: None,
+			reply: None,
+			next_send: None,
+			next_recv: None,
+		}
+	}
+	pub(crate) fn in_progress(&self) -> bool {
+		self.pending.is_some() || self.reply.is_some() || self.next_send.is_some() || self.next_recv.is_some()
+	}
+	fn is_kk(&self) -> bool {
+		!self.s.is_empty() && !self.rs.is_empty()
+	}
+	fn initialize(&self, requester: bool) -> S {
+		let protocol_name = format!("Noise_{}_25519_{}", if self.is_kk() { "KK" } else { "NN" }, S::CIPHER_SUITE);
+		let mut ss: S = S::initialize_symmetric(protocol_name.as_bytes());
+		ss.mix_hash(&self.h.as_bytes()[..]);
+		if self.is_kk() {
+			let (first, second) = if requester {
+				(self.s.get_public_key(), self.rs)
+			} else {
+				(self.rs, self.s.get_public_key())
+			};
+			ss.mix_hash(&first.as_bytes()[..]);
+			ss.mix_hash(&second.as_bytes()[..]);
+		}
+		ss
+	}
+	/* -> e, es, ss
+	 * None if the static key oracle fails, leaving no re-handshake in progress. */
+	pub(crate) fn request(&mut self) -> Option<Vec<u8>> {
+		let mut ss: S = self.initialize(true);
+		let e: Keypair = Keypair::new();
+		ss.mix_hash(&e.get_public_key().as_bytes()[..]);
+		if self.is_kk() {
+			ss.mix_key(&e.dh(&self.rs.as_bytes()));
+			ss.mix_key(&self.s.try_dh(&self.rs.as_bytes())?);
+		}
+		let mut control: Vec<u8> = vec![REQUEST];
+		control.extend_from_slice(&e.get_public_key().as_bytes()[..]);
+		control.extend(ss.encrypt_and_hash(&[])?);
+		self.pending = Some((ss, e));
+		Some(control)
+	}
+	/* <- e, ee, se */
+	fn read_request(&mut self, body: &[u8]) -> Option<()> {
+		let re: PublicKey = read_public_key(body)?;
+		let mut ss: S = self.initialize(false);
+		ss.mix_hash(&re.as_bytes()[..]);
+		if self.is_kk() {
+			ss.mix_key(&self.s.try_dh(&re.as_bytes())?);
+			ss.mix_key(&self.s.try_dh(&self.rs.as_bytes())?);
+		}
+		ss.decrypt_and_hash(&body[DHLEN..])?;
+		let e: Keypair = Keypair::new();
+		ss.mix_hash(&e.get_public_key().as_bytes()[..]);
+		ss.mix_key(&e.dh(&re.as_bytes()));
+		if self.is_kk() {
+			ss.mix_key(&e.dh(&self.rs.as_bytes()));
+		}
+		let mut control: Vec<u8> = vec![RESPONSE];
+		control.extend_from_slice(&e.get_public_key().as_bytes()[..]);
+		control.extend(ss.encrypt_and_hash(&[])?);
+		let (cs1, cs2) = ss.split();
+		ss.clear();
+		self.pending = None;
+		self.reply = Some(control);
+		self.next_send = Some(cs2);
+		self.next_recv = Some(cs1);
+		Some(())
+	}
+	fn read_response(&mut self, body: &[u8]) -> Option<S::Cipher> {
+		let re: PublicKey = read_public_key(body)?;
+		let (mut ss, e) = self.pending.take()?;
+		ss.mix_hash(&re.as_bytes()[..]);
+		ss.mix_key(&e.dh(&re.as_bytes()));
+		if self.is_kk() {
+			ss.mix_key(&self.s.try_dh(&re.as_bytes())?);
+		}
+		ss.decrypt_and_hash(&body[DHLEN..])?;
+		let (cs1, cs2) = ss.split();
+		ss.clear();
+		self.reply = Some(vec![CONFIRM]);
+		self.next_send = Some(cs1);
+		Some(cs2)
+	}
+	/* Returns the receiving cipher to switch to, if this message was the peer's last on the old one.
+	 * When both parties request at once, the request of the original initiator goes ahead. */
+	pub(crate) fn read(&mut self, control: &[u8]) -> Option<Option<S::Cipher>> {
+		match *control.first()? {
+			REQUEST => {
+				if self.reply.is_some() || self.next_send.is_some() || self.next_recv.is_some() {
+					return None;
+				}
+				if self.pending.is_none() || !self.initiator {
+					self.read_request(&control[1..])?;
+				}
+				Some(None)
+			}
+			RESPONSE => Some(Some(self.read_response(&control[1..])?)),
+			CONFIRM if control.len() == 1 => Some(Some(self.next_recv.take()?)),
+			_ => None,
+		}
+	}
+	/* The reply owed to the peer, and the sending cipher to switch to once it has been sent. */
+	pub(crate) fn take_reply(&mut self) -> Option<(Vec<u8>, Option<S::Cipher>)> {
+		let control: Vec<u8> = self.reply.take()?;
+		Some((control, self.next_send.take()))
+	}
+}
+
+#[test]
+fn rehandshake_collision_test() {
+	use crate::state::{Cipher, SymmetricState};
+	let (s_i, s_r): (Keypair, Keypair) = (Keypair::new(), Keypair::new());
+	let h: Hash = Hash::new([0x33u8; 32]);
+	let mut initiator: Rehandshake<SymmetricState> = Rehandshake::new(true, s_i.clone(), s_r.get_public_key(), h.clone());
+	let mut responder: Rehandshake<SymmetricState> = Rehandshake::new(false, s_r, s_i.get_public_key(), h);
+	let request_i: Vec<u8> = initiator.request().unwrap();
+	let request_r: Vec<u8> = responder.request().unwrap();
+	assert!(initiator.read(&request_r[..]).unwrap().is_none());
+	assert!(initiator.take_reply().is_none());
+	assert!(responder.read(&request_i[..]).unwrap().is_none());
+	let (response, responder_send) = responder.take_reply().unwrap();
+	let mut initiator_recv = initiator.read(&response[..]).unwrap().unwrap();
+	let (confirm, initiator_send) = initiator.take_reply().unwrap();
+	let mut responder_recv = responder.read(&confirm[..]).unwrap().unwrap();
+	let message = responder_send.unwrap().write_message_regular(b"r");
+	assert!(initiator_recv.read_message_regular(&message).unwrap() == b"r");
+	let message = initiator_send.unwrap().write_message_regular(b"i");
+	assert!(responder_recv.read_message_regular(&message).unwrap() == b"i");
+	assert!(!initiator.in_progress() && !responder.in_progress());
+}
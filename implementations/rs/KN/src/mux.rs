@@ -0,0 +1,268 @@
+/* ---------------------------------------------------------------- *
+ * MULTIPLEXER                                                      *
+ * ---------------------------------------------------------------- */
+
+/* Logical streams over one completed NoiseSession. Each transport message
+ * carries a single frame: a type byte, a big-endian stream id and a body.
+ * Streams opened by the initiator have odd ids, those of the responder even
+ * ids. Data frames are scheduled round-robin across streams, and each stream
+ * may only send as much as the peer's receive window allows. Streams opened
+ * by the peer beyond the concurrent stream limit are reset. */
+
+use crate::{
+	consts::{MAC_LENGTH, MAX_MESSAGE},
+	noisesession::NoiseSession,
+	state::{Symmetric, SymmetricState},
+	types::{Message, MessageBuffer},
+};
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::{BTreeMap, VecDeque};
+
+const FRAME_OPEN: u8 = 0;
+const FRAME_DATA: u8 = 1;
+const FRAME_WINDOW: u8 = 2;
+const FRAME_CLOSE: u8 = 3;
+const FRAME_RESET: u8 = 4;
+const FRAME_HEADER_LENGTH: usize = 5;
+pub const INITIAL_WINDOW: u32 = 262_144;
+pub const MAX_STREAMS: usize = 256;
+pub const MAX_FRAME_DATA: usize = MAX_MESSAGE - MAC_LENGTH - FRAME_HEADER_LENGTH;
+
+fn frame(kind: u8, id: u32, body: &[u8]) -> Vec<u8> {
+	let mut out: Vec<u8> = vec![kind, 0, 0, 0, 0];
+	BigEndian::write_u32(&mut out[1..FRAME_HEADER_LENGTH], id);
+	out.extend_from_slice(body);
+	out
+}
+
+struct Stream {
+	send_buffer: VecDeque<u8>,
+	send_window: u32,
+	recv_buffer: Vec<u8>,
+	recv_window: u32,
+	consumed: u32,
+	local_closed: bool,
+	close_sent: bool,
+	remote_closed: bool,
+	reset: bool,
+}
+impl Stream {
+	fn new() -> Stream {
+		Stream {
+			send_buffer: VecDeque::new(),
+			send_window: INITIAL_WINDOW,
+			recv_buffer: Vec::new(),
+			recv_window: INITIAL_WINDOW,
+			consumed: 0,
+			local_closed: false,
+			close_sent: false,
+			remote_closed: false,
+			reset: false,
+		}
+	}
+	fn is_finished(&self) -> bool {
+		self.close_sent && self.remote_closed && self.recv_buffer.is_empty()
+	}
+}
+
+pub struct Multiplexer<S: Symmetric = SymmetricState> {
+	session: NoiseSession<S>,
+	streams: BTreeMap<u32, Stream>,
+	next_id: u32,
+	remote_id: u32,
+	max_streams: usize,
+	accepted: VecDeque<u32>,
+	control: VecDeque<Vec<u8>>,
+	cursor: u32,
+}
+impl<S: Symmetric> Multiplexer<S> {
+	pub fn new(session: NoiseSession<S>) -> Multiplexer<S> {
+		assert!(session.is_handshake_finished(), "Handshake not finished");
+		let next_id: u32 = if session.is_initiator() { 1 } else { 2 };
+		Multiplexer {
+			session,
+			streams: BTreeMap::new(),
+			next_id,
+			remote_id: 0,
+			max_streams: MAX_STREAMS,
+			accepted: VecDeque::new(),
+			control: VecDeque::new(),
+			cursor: 0,
+		}
+	}
+	pub fn get_session(&self) -> &NoiseSession<S> {
+		&self.session
+	}
+	/* Limits how many streams opened by the peer may be open at once; further ones are reset. */
+	pub fn set_max_streams(&mut self, max_streams: usize) {
+		self.max_streams = max_streams;
+	}
+	pub fn open(&mut self) -> u32 {
+		let id: u32 = self.next_id;
+		self.next_id = self.next_id.checked_add(2).expect("Stream ids exhausted; open");
+		self.streams.insert(id, Stream::new());
+		self.control.push_back(frame(FRAME_OPEN, id, &[]));
+		id
+	}
+	pub fn accept(&mut self) -> Option<u32> {
+		self.accepted.pop_front()
+	}
+	/* Data written to a stream the peer has reset is discarded. */
+	pub fn write(&mut self, id: u32, data: &[u8]) {
+		let stream: &mut Stream = self.streams.get_mut(&id).expect("Unknown stream; write");
+		assert!(!stream.local_closed, "Stream closed; write");
+		if !stream.reset {
+			stream.send_buffer.extend(data);
+		}
+	}
+	/* Drains the data received so far, returning window credit to the peer
+	 * once half of the initial window has been consumed. */
+	pub fn read(&mut self, id: u32) -> Vec<u8> {
+		let stream: &mut Stream = match self.streams.get_mut(&id) {
+			Some(stream) => stream,
+			None => return Vec::new(),
+		};
+		let data: Vec<u8> = std::mem::take(&mut stream.recv_buffer);
+		stream.consumed += data.len() as u32;
+		if stream.consumed >= INITIAL_WINDOW / 2 && !stream.remote_closed {
+			let mut credit = [0u8; 4];
+			BigEndian::write_u32(&mut credit, stream.consumed);
+			stream.recv_window += stream.consumed;
+			stream.consumed = 0;
+			self.control.push_back(frame(FRAME_WINDOW, id, &credit[..]));
+		}
+		if stream.is_finished() {
+			self.streams.remove(&id);
+		}
+		data
+	}
+	/* Pending data is still sent before the close. */
+	pub fn close(&mut self, id: u32) {
+		if let Some(stream) = self.streams.get_mut(&id) {
+			stream.local_closed = true;
+			if stream.reset {
+				self.streams.remove(&id);
+			}
+		}
+	}
+	/* True once the peer has refused the stream; it stays known until closed. */
+	pub fn is_reset(&self, id: u32) -> bool {
+		matches!(self.streams.get(&id), Some(stream) if stream.reset)
+	}
+	/* True once the peer has closed the stream and all of its data has been read. */
+	pub fn is_eof(&self, id: u32) -> bool {
+		match self.streams.get(&id) {
+			Some(stream) => stream.remote_closed && stream.recv_buffer.is_empty(),
+			None => true,
+		}
+	}
+	fn next_data_frame(&mut self) -> Option<Vec<u8>> {
+		let cursor: u32 = self.cursor;
+		let ready = |stream: &Stream| {
+			if stream.send_buffer.is_empty() {
+				stream.local_closed && !stream.close_sent
+			} else {
+				stream.send_window > 0
+			}
+		};
+		let id: u32 = self
+			.streams
+			.range(cursor.saturating_add(1)..)
+			.chain(self.streams.range(..=cursor))
+			.find(|(_, stream)| ready(stream))
+			.map(|(id, _)| *id)?;
+		self.cursor = id;
+		let stream: &mut Stream = self.streams.get_mut(&id)?;
+		if stream.send_buffer.is_empty() {
+			stream.close_sent = true;
+			if stream.is_finished() {
+				self.streams.remove(&id);
+			}
+			return Some(frame(FRAME_CLOSE, id, &[]));
+		}
+		let length: usize = stream.send_buffer.len().min(stream.send_window as usize).min(MAX_FRAME_DATA);
+		let data: Vec<u8> = stream.send_buffer.drain(..length).collect();
+		stream.send_window -= length as u32;
+		Some(frame(FRAME_DATA, id, &data[..]))
+	}
+	/* Returns the next transport message to deliver to the peer, control frames first. */
+	pub fn poll_message(&mut self) -> Option<MessageBuffer> {
+		let payload: Vec<u8> = match self.control.pop_front() {
+			Some(control) => control,
+			None => self.next_data_frame()?,
+		};
+		self.session.send_message(Message::from_vec(payload))
+	}
+	/* Returns None if the message fails to decrypt or violates the framing or flow control. */
+	pub fn recv_message(&mut self, message: &mut MessageBuffer) -> Option<()> {
+		let payload: Vec<u8> = self.session.recv_message(message)?;
+		if self.session.is_rehandshake_message() {
+			return Some(());
+		}
+		if payload.len() < FRAME_HEADER_LENGTH {
+			return None;
+		}
+		let id: u32 = BigEndian::read_u32(&payload[1..FRAME_HEADER_LENGTH]);
+		let body: &[u8] = &payload[FRAME_HEADER_LENGTH..];
+		let remote: bool = id % 2 != self.next_id % 2;
+		if payload[0] == FRAME_OPEN {
+			if id == 0 || !remote || id <= self.remote_id || !body.is_empty() {
+				return None;
+			}
+			self.remote_id = id;
+			let open: usize = self.streams.keys().filter(|stream_id| *stream_id % 2 != self.next_id % 2).count();
+			if open >= self.max_streams {
+				self.control.push_back(frame(FRAME_RESET, id, &[]));
+				return Some(());
+			}
+			self.streams.insert(id, Stream::new());
+			self.accepted.push_back(id);
+			return Some(());
+		}
+		/* Credit may still arrive for a stream that has been closed on both sides,
+		 * and the peer may have sent more on a stream before learning it was reset. */
+		let stream: &mut Stream = match self.streams.get_mut(&id) {
+			Some(stream) => stream,
+			None if payload[0] == FRAME_WINDOW => return Some(()),
+			None if remote && id <= self.remote_id && payload[0] != FRAME_RESET => return Some(()),
+			None => return None,
+		};
+		match payload[0] {
+			FRAME_DATA => {
+				if stream.remote_closed || body.len() > stream.recv_window as usize {
+					return None;
+				}
+				stream.recv_window -= body.len() as u32;
+				stream.recv_buffer.extend_from_slice(body);
+			}
+			FRAME_WINDOW => {
+				if body.len() != 4 {
+					return None;
+				}
+				stream.send_window = stream.send_window.checked_add(BigEndian::read_u32(body))?;
+			}
+			FRAME_CLOSE => {
+				if !body.is_empty() {
+					return None;
+				}
+				stream.remote_closed = true;
+				if stream.is_finished() {
+					self.streams.remove(&id);
+				}
+			}
+			FRAME_RESET => {
+				if remote || !body.is_empty() {
+					return None;
+				}
+				stream.send_buffer.clear();
+				stream.remote_closed = true;
+				stream.reset = true;
+				if stream.local_closed {
+					self.streams.remove(&id);
+				}
+			}
+			_ => return None,
+		}
+		Some(())
+	}
+}
@@ -0,0 +1,182 @@
+/* ---------------------------------------------------------------- *
+ * KNOWN PEERS                                                      *
+ * ---------------------------------------------------------------- */
+
+/* A store of peer names and their static public keys, kept in a text file
+ * in the manner of SSH's known_hosts: one "name hex-key" pair per line, with
+ * blank lines and lines starting with '#' ignored. The store vets the static
+ * key a peer sends through StaticKeyVerifier, and can also supply the remote
+ * static key for patterns where it must be known in advance. */
+
+use crate::{
+	consts::DHLEN,
+	types::{HandshakeError, PublicKey, StaticKeyVerifier},
+};
+use std::{
+	collections::HashMap,
+	fs::{self, OpenOptions},
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrustMode {
+	/* Only peers on record are accepted, each with its own key. */
+	Strict,
+	/* A peer seen for the first time is recorded; afterwards its key must match. */
+	TrustOnFirstUse,
+	/* Any key on record is accepted, whatever name it is listed under. */
+	Allowlist,
+}
+
+fn parse_line(line: &str) -> io::Result<Option<(String, PublicKey)>> {
+	let line: &str = line.trim();
+	if line.is_empty() || line.starts_with('#') {
+		return Ok(None);
+	}
+	let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Malformed known peers entry");
+	let mut fields = line.split_whitespace();
+	let name: &str = fields.next().ok_or_else(invalid)?;
+	let key: Vec<u8> = hex::decode(fields.next().ok_or_else(invalid)?).map_err(|_| invalid())?;
+	if key.len() != DHLEN || fields.next().is_some() {
+		return Err(invalid());
+	}
+	let mut k = [0u8; DHLEN];
+	k.copy_from_slice(&key[..]);
+	Ok(Some((String::from(name), PublicKey::from_bytes(k))))
+}
+
+pub struct KnownPeers {
+	path: PathBuf,
+	mode: TrustMode,
+	peers: Mutex<HashMap<String, PublicKey>>,
+}
+impl KnownPeers {
+	/* A missing file is treated as an empty store and created on the first new entry. */
+	pub fn open(path: &Path, mode: TrustMode) -> io::Result<KnownPeers> {
+		let mut peers: HashMap<String, PublicKey> = HashMap::new();
+		match fs::read_to_string(path) {
+			Ok(contents) => {
+				for line in contents.lines() {
+					if let Some((name, key)) = parse_line(line)? {
+						peers.insert(name, key);
+					}
+				}
+			}
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => return Err(e),
+		}
+		Ok(KnownPeers {
+			path: PathBuf::from(path),
+			mode,
+			peers: Mutex::new(peers),
+		})
+	}
+	pub fn get(&self, name: &str) -> Option<PublicKey> {
+		self.peers.lock().ok()?.get(name).copied()
+	}
+	/* Records a peer, replacing any key it had; used to pin keys or follow a rotation.
+	 * A new peer is appended. Replacing a key rewrites the file, comments included,
+	 * into a temporary file renamed over it, so the store is never left half-written. */
+	pub fn insert(&self, name: &str, key: &PublicKey) -> io::Result<()> {
+		let mut peers = self
+			.peers
+			.lock()
+			.map_err(|_| io::Error::other("Known peers lock poisoned"))?;
+		if !peers.contains_key(name) {
+			self.append(name, key)?;
+			peers.insert(String::from(name), *key);
+			return Ok(());
+		}
+		let mut contents: String = String::new();
+		for line in fs::read_to_string(&self.path)?.lines() {
+			match parse_line(line) {
+				Ok(Some((entry, _))) if entry == name => {}
+				_ => {
+					contents.push_str(line);
+					contents.push('\n');
+				}
+			}
+		}
+		contents.push_str(&format!("{} {}\n", name, hex::encode(key.as_bytes())));
+		let mut temporary = self.path.clone().into_os_string();
+		temporary.push(".tmp");
+		let temporary: PathBuf = PathBuf::from(temporary);
+		let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(&temporary)?;
+		file.write_all(contents.as_bytes())?;
+		file.sync_all()?;
+		fs::rename(&temporary, &self.path)?;
+		peers.insert(String::from(name), *key);
+		Ok(())
+	}
+	fn append(&self, name: &str, key: &PublicKey) -> io::Result<()> {
+		let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+		writeln!(file, "{} {}", name, hex::encode(key.as_bytes()))
+	}
+	/* StaticKeyMismatch means the peer is on record with another key, which calls
+	 * for an operator to tell an attack from a key rotation. */
+	pub fn check(&self, name: &str, key: &PublicKey) -> Result<(), HandshakeError> {
+		let mut peers = self.peers.lock().map_err(|_| HandshakeError::StaticKeyRejected)?;
+		if self.mode == TrustMode::Allowlist {
+			return if peers.values().any(|known| known.as_bytes() == key.as_bytes()) {
+				Ok(())
+			} else {
+				Err(HandshakeError::StaticKeyRejected)
+			};
+		}
+		match peers.get(name) {
+			Some(known) if known.as_bytes() == key.as_bytes() => Ok(()),
+			Some(_) => Err(HandshakeError::StaticKeyMismatch),
+			None if self.mode == TrustMode::TrustOnFirstUse => {
+				self.append(name, key).map_err(|_| HandshakeError::StaticKeyRejected)?;
+				peers.insert(String::from(name), *key);
+				Ok(())
+			}
+			None => Err(HandshakeError::StaticKeyRejected),
+		}
+	}
+	/* A verifier for NoiseSession::set_static_key_verifier() expecting the peer called name. */
+	pub fn verifier(store: &Arc<KnownPeers>, name: &str) -> Arc<dyn StaticKeyVerifier> {
+		Arc::new(PeerVerifier {
+			store: Arc::clone(store),
+			name: String::from(name),
+		})
+	}
+}
+
+struct PeerVerifier {
+	store: Arc<KnownPeers>,
+	name: String,
+}
+impl StaticKeyVerifier for PeerVerifier {
+	fn verify(&self, remote_static: &PublicKey) -> Result<(), HandshakeError> {
+		self.store.check(&self.name[..], remote_static)
+	}
+}
+
+#[test]
+fn known_peers_test() {
+	use crate::types::Keypair;
+	let path: PathBuf = std::env::temp_dir().join(format!("noiseexplorer_known_peers_{}", std::process::id()));
+	fs::write(&path, "# peers\n").unwrap();
+	let (alice, mallory): (PublicKey, PublicKey) = (Keypair::new().get_public_key(), Keypair::new().get_public_key());
+	let tofu: Arc<KnownPeers> = Arc::new(KnownPeers::open(&path, TrustMode::TrustOnFirstUse).unwrap());
+	assert!(KnownPeers::verifier(&tofu, "alice").verify(&alice).is_ok());
+	assert!(tofu.check("alice", &mallory) == Err(HandshakeError::StaticKeyMismatch));
+	let strict: KnownPeers = KnownPeers::open(&path, TrustMode::Strict).unwrap();
+	assert!(strict.get("alice").unwrap().as_bytes() == alice.as_bytes());
+	assert!(strict.check("alice", &alice).is_ok());
+	assert!(strict.check("bob", &alice) == Err(HandshakeError::StaticKeyRejected));
+	let allowlist: KnownPeers = KnownPeers::open(&path, TrustMode::Allowlist).unwrap();
+	assert!(allowlist.check("bob", &alice).is_ok());
+	assert!(allowlist.check("alice", &mallory) == Err(HandshakeError::StaticKeyRejected));
+	allowlist.insert("alice", &mallory).unwrap();
+	allowlist.insert("bob", &alice).unwrap();
+	let contents: String = fs::read_to_string(&path).unwrap();
+	assert!(contents.starts_with("# peers\n") && contents.lines().count() == 3);
+	assert!(KnownPeers::open(&path, TrustMode::Strict).unwrap().check("alice", &mallory).is_ok());
+	fs::write(&path, "# comment\n\nalice 00\n").unwrap();
+	assert!(KnownPeers::open(&path, TrustMode::Strict).is_err());
+	fs::remove_file(&path).unwrap();
+}
@@ -2,9 +2,14 @@
  * TYPES                                                            *
  * ---------------------------------------------------------------- */
 
-use crate::consts::{DHLEN, EMPTY_KEY, HASHLEN, MAX_MESSAGE, MAX_NONCE, PSK_LENGTH};
+use crate::{
+	consts::{DHLEN, EMPTY_KEY, HASHLEN, MAX_MESSAGE, MAX_NONCE, PSK_LENGTH},
+	elligator,
+};
+use ed25519_dalek::{SigningKey, VerifyingKey};
 use hacl_star::curve25519;
 use rand;
+use std::sync::Arc;
 use zeroize::Zeroize;
 
 fn decode_str_32(s: &str) -> [u8; DHLEN] {
@@ -21,6 +26,35 @@ fn decode_str_32(s: &str) -> [u8; DHLEN] {
 	}
 }
 
+/* Curve25519 points of order 1, 2, 4 and 8, and the non-canonical encodings p - 1, p and p + 1. */
+const LOW_ORDER_POINTS: [[u8; DHLEN]; 7] = [
+	[0x00; DHLEN],
+	[
+		0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+	],
+	[
+		0xe0, 0xeb, 0x7a, 0x7c, 0x3b, 0x41, 0xb8, 0xae, 0x16, 0x56, 0xe3, 0xfa, 0xf1, 0x9f, 0xc4, 0x6a,
+		0xda, 0x09, 0x8d, 0xeb, 0x9c, 0x32, 0xb1, 0xfd, 0x86, 0x62, 0x05, 0x16, 0x5f, 0x49, 0xb8, 0x00,
+	],
+	[
+		0x5f, 0x9c, 0x95, 0xbc, 0xa3, 0x50, 0x8c, 0x24, 0xb1, 0xd0, 0xb1, 0x55, 0x9c, 0x83, 0xef, 0x5b,
+		0x04, 0x44, 0x5c, 0xc4, 0x58, 0x1c, 0x8e, 0x86, 0xd8, 0x22, 0x4e, 0xdd, 0xd0, 0x9f, 0x11, 0x57,
+	],
+	[
+		0xec, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+		0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f,
+	],
+	[
+		0xed, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+		0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f,
+	],
+	[
+		0xee, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+		0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f,
+	],
+];
+
 fn decode_str(s: &str) -> Vec<u8> {
 	if let Ok(x) = hex::decode(s) {
 		x
@@ -129,6 +163,11 @@ impl PrivateKey {
 	pub fn from_str(key: &str) -> PrivateKey {
 		PrivateKey::from_hacl_secret_key(curve25519::SecretKey(decode_str_32(key)))
 	}
+	/* The X25519 scalar matching an Ed25519 secret key (seed), so that an
+	 * existing Ed25519 identity can serve as a static key. */
+	pub fn from_ed25519_secret(secret: [u8; 32]) -> PrivateKey {
+		PrivateKey::from_bytes(SigningKey::from_bytes(&secret).to_scalar_bytes())
+	}
 	pub(crate) fn as_bytes(&self) -> &[u8; DHLEN] {
 		&self.k
 	}
@@ -165,12 +204,93 @@ impl PublicKey {
 	pub fn from_hacl_public_key(hacl_public: curve25519::PublicKey) -> PublicKey {
 		PublicKey { k: hacl_public.0 }
 	}
-	pub(crate) fn as_bytes(&self) -> [u8; DHLEN] {
+	/* The Curve25519 public key matching an Ed25519 one; None if the bytes are not a valid Edwards point. */
+	pub fn from_ed25519_public(public: [u8; 32]) -> Option<PublicKey> {
+		let key: VerifyingKey = VerifyingKey::from_bytes(&public).ok()?;
+		Some(PublicKey::from_bytes(key.to_montgomery().to_bytes()))
+	}
+	pub fn as_bytes(&self) -> [u8; DHLEN] {
 		self.k
 	}
 	pub fn is_empty(&self) -> bool {
 		crypto::util::fixed_time_eq(&self.k[..], &EMPTY_KEY)
 	}
+	/* True if a DH with this key yields the all-zero output, whatever the private key.
+	 * The top bit is ignored, as it is by scalar multiplication. */
+	pub fn is_low_order(&self) -> bool {
+		let mut k: [u8; DHLEN] = self.k;
+		k[DHLEN - 1] &= 0x7f;
+		LOW_ORDER_POINTS.iter().fold(false, |found, point| found | crypto::util::fixed_time_eq(&k[..], &point[..]))
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HandshakeError {
+	/* The peer sent a key of small order, which would make a shared secret all zeros. */
+	LowOrderPoint,
+	/* The peer sent our own ephemeral back to us. */
+	Reflection,
+	/* The StaticKeyVerifier refused the peer's static key. */
+	StaticKeyRejected,
+	/* The peer's static key differs from the one on record for it. */
+	StaticKeyMismatch,
+	/* The peer's certificate is missing, invalid, expired or for another key. */
+	CertificateRejected,
+	/* The StaticKeyOracle holding our static key could not perform a DH. */
+	OracleFailed,
+	/* The StaticKeySelector has no static key for the initiator's first message. */
+	NoStaticKey,
+	/* The PskResolver has no PSK for the peer. */
+	PskNotFound,
+	/* Our ephemeral key has no Elligator2 representative to send. */
+	EphemeralNotRepresentable,
+}
+
+/* Vets a static key sent by the peer as soon as it has been decrypted,
+ * before it takes part in any DH or the session encrypts anything further.
+ * Closures returning false refuse keys with StaticKeyRejected. */
+pub trait StaticKeyVerifier<P = PublicKey>: Send + Sync {
+	fn verify(&self, remote_static: &P) -> Result<(), HandshakeError>;
+}
+impl<P, F: Fn(&P) -> bool + Send + Sync> StaticKeyVerifier<P> for F {
+	fn verify(&self, remote_static: &P) -> Result<(), HandshakeError> {
+		if self(remote_static) {
+			Ok(())
+		} else {
+			Err(HandshakeError::StaticKeyRejected)
+		}
+	}
+}
+
+/* Chooses a responder's static keypair from the prologue and the payload of
+ * the initiator's first message, as a TLS server picks a certificate by SNI.
+ * Returning None refuses the message with NoStaticKey. */
+pub trait StaticKeySelector<K = Keypair>: Send + Sync {
+	fn select(&self, prologue: &[u8], payload: &[u8]) -> Option<K>;
+}
+impl<K, F: Fn(&[u8], &[u8]) -> Option<K> + Send + Sync> StaticKeySelector<K> for F {
+	fn select(&self, prologue: &[u8], payload: &[u8]) -> Option<K> {
+		self(prologue, payload)
+	}
+}
+
+/* Supplies the PSK for a peer, given its static key if already received and
+ * the payload of the last handshake message received, if any, as a hint. */
+pub trait PskResolver<P = PublicKey>: Send + Sync {
+	fn resolve(&self, remote_static: Option<&P>, hint: &[u8]) -> Option<Psk>;
+}
+impl<P, F: Fn(Option<&P>, &[u8]) -> Option<Psk> + Send + Sync> PskResolver<P> for F {
+	fn resolve(&self, remote_static: Option<&P>, hint: &[u8]) -> Option<Psk> {
+		self(remote_static, hint)
+	}
+}
+
+/* Performs DH with a static private key kept outside the session, such as in
+ * a separate key-holding process; see Keypair::from_oracle() and the oracle module. */
+pub trait StaticKeyOracle: Send + Sync {
+	fn public_key(&self) -> PublicKey;
+	/* None if the DH could not be performed. */
+	fn dh(&self, remote: &PublicKey) -> Option<[u8; DHLEN]>;
 }
 
 #[derive(Copy, Clone)]
@@ -191,10 +311,16 @@ impl Nonce {
 		self.n
 	}
 }
+impl From<u64> for Nonce {
+	fn from(n: u64) -> Nonce {
+		Nonce { n }
+	}
+}
 
+/* ne holds the ephemeral key as sent, in the encoding of the DH function. */
 #[derive(Clone)]
-pub struct MessageBuffer {
-	pub ne: [u8; DHLEN],
+pub struct MessageBuffer<E = [u8; DHLEN]> {
+	pub ne: E,
 	pub ns: Vec<u8>,
 	pub ciphertext: Vec<u8>,
 }
@@ -229,17 +355,20 @@ impl Message {
 pub struct Keypair {
 	private_key: PrivateKey,
 	public_key: PublicKey,
+	oracle: Option<Arc<dyn StaticKeyOracle>>,
 }
 
 impl Keypair {
 	pub fn clear(&mut self) {
 		self.private_key.clear();
 		self.public_key.clear();
+		self.oracle = None;
 	}
 	pub fn new_empty() -> Keypair {
 		Keypair {
 			private_key: PrivateKey::empty(),
 			public_key: PublicKey::empty(),
+			oracle: None,
 		}
 	}
 	pub fn new() -> Keypair {
@@ -248,16 +377,36 @@ impl Keypair {
 		Keypair {
 			private_key: PrivateKey::from_hacl_secret_key(hacl_keypair.0),
 			public_key: PublicKey::from_hacl_public_key(hacl_keypair.1),
+			oracle: None,
+		}
+	}
+	/* Roughly half of all public keys have an Elligator2 representative. The
+	 * public key carries a random low-order component, which leaves every DH
+	 * result unchanged; see elligator::dirty_public_key(). */
+	pub fn new_elligator() -> Keypair {
+		loop {
+			let mut keypair: Keypair = Keypair::new();
+			keypair.public_key = PublicKey::from_bytes(elligator::dirty_public_key(keypair.private_key.as_bytes()));
+			if elligator::is_representable(&keypair.public_key.as_bytes()) {
+				return keypair;
+			}
 		}
 	}
 	pub(crate) fn dh(&self, public_key: &[u8; DHLEN]) -> [u8; DHLEN] {
+		self.try_dh(public_key).expect("Static key oracle failed; dh")
+	}
+	/* Only fails for keypairs backed by an oracle. */
+	pub(crate) fn try_dh(&self, public_key: &[u8; DHLEN]) -> Option<[u8; DHLEN]> {
+		if let Some(oracle) = &self.oracle {
+			return oracle.dh(&PublicKey::from_bytes(*public_key));
+		}
 		let mut output: [u8; DHLEN] = EMPTY_KEY;
 		curve25519::scalarmult(&mut output, &self.private_key.as_bytes(), public_key);
-		output
+		Some(output)
 	}
 
 	pub fn is_empty(&self) -> bool {
-		self.private_key.is_empty()
+		self.oracle.is_none() && self.private_key.is_empty()
 	}
 
 	pub fn from_key(k: PrivateKey) -> Keypair {
@@ -265,6 +414,15 @@ impl Keypair {
 		Keypair {
 			private_key: k,
 			public_key: public_key,
+			oracle: None,
+		}
+	}
+	/* A static keypair whose private key stays with the oracle. */
+	pub fn from_oracle(oracle: Arc<dyn StaticKeyOracle>) -> Keypair {
+		Keypair {
+			private_key: PrivateKey::empty(),
+			public_key: oracle.public_key(),
+			oracle: Some(oracle),
 		}
 	}
 	pub fn from_private_key(k: PrivateKey) -> Keypair {
@@ -274,3 +432,107 @@ impl Keypair {
 		self.public_key
 	}
 }
+
+/* What HandshakeState needs of its keys, so that protocols specified over
+ * other curves, such as BOLT-8 over secp256k1, can run the generated
+ * handshake. The defaults suit curves without Elligator2 representatives
+ * or points of small order, which only Curve25519 has. */
+pub trait DhKeypair: Clone {
+	type PublicKey: DhPublicKey;
+	const DH_NAME: &'static str;
+	fn new() -> Self;
+	fn new_empty() -> Self;
+	fn new_elligator() -> Self {
+		Self::new()
+	}
+	fn is_empty(&self) -> bool;
+	fn clear(&mut self);
+	fn get_public_key(&self) -> Self::PublicKey;
+	fn try_dh(&self, public_key: &PublicKeyBytes<Self>) -> Option<[u8; DHLEN]>;
+	fn dh(&self, public_key: &PublicKeyBytes<Self>) -> [u8; DHLEN] {
+		self.try_dh(public_key).expect("Static key oracle failed; dh")
+	}
+}
+
+pub trait DhPublicKey: Copy {
+	type Bytes: AsRef<[u8]> + Copy + PartialEq;
+	fn empty() -> Self;
+	fn clear(&mut self);
+	fn is_empty(&self) -> bool;
+	fn as_bytes(&self) -> Self::Bytes;
+	/* None if the bytes do not encode a public key. */
+	fn from_slice(bytes: &[u8]) -> Option<Self>;
+	fn is_low_order(&self) -> bool {
+		false
+	}
+	fn elligator_encode(&self) -> Option<Self::Bytes> {
+		None
+	}
+	fn elligator_decode(representative: &Self::Bytes) -> Self::Bytes {
+		*representative
+	}
+}
+
+pub type PublicKeyBytes<K> = <<K as DhKeypair>::PublicKey as DhPublicKey>::Bytes;
+
+impl DhKeypair for Keypair {
+	type PublicKey = PublicKey;
+	const DH_NAME: &'static str = "25519";
+	fn new() -> Keypair {
+		Keypair::new()
+	}
+	fn new_empty() -> Keypair {
+		Keypair::new_empty()
+	}
+	fn new_elligator() -> Keypair {
+		Keypair::new_elligator()
+	}
+	fn is_empty(&self) -> bool {
+		Keypair::is_empty(self)
+	}
+	fn clear(&mut self) {
+		Keypair::clear(self)
+	}
+	fn get_public_key(&self) -> PublicKey {
+		Keypair::get_public_key(self)
+	}
+	fn try_dh(&self, public_key: &[u8; DHLEN]) -> Option<[u8; DHLEN]> {
+		Keypair::try_dh(self, public_key)
+	}
+	fn dh(&self, public_key: &[u8; DHLEN]) -> [u8; DHLEN] {
+		Keypair::dh(self, public_key)
+	}
+}
+
+impl DhPublicKey for PublicKey {
+	type Bytes = [u8; DHLEN];
+	fn empty() -> PublicKey {
+		PublicKey::empty()
+	}
+	fn clear(&mut self) {
+		PublicKey::clear(self)
+	}
+	fn is_empty(&self) -> bool {
+		PublicKey::is_empty(self)
+	}
+	fn as_bytes(&self) -> [u8; DHLEN] {
+		PublicKey::as_bytes(self)
+	}
+	fn from_slice(bytes: &[u8]) -> Option<PublicKey> {
+		if bytes.len() != DHLEN {
+			return None;
+		}
+		let mut k: [u8; DHLEN] = EMPTY_KEY;
+		k.copy_from_slice(bytes);
+		Some(PublicKey::from_bytes(k))
+	}
+	fn is_low_order(&self) -> bool {
+		PublicKey::is_low_order(self)
+	}
+	fn elligator_encode(&self) -> Option<[u8; DHLEN]> {
+		elligator::encode(&self.k)
+	}
+	fn elligator_decode(representative: &[u8; DHLEN]) -> [u8; DHLEN] {
+		elligator::decode(representative)
+	}
+}
@@ -0,0 +1,133 @@
+/* ---------------------------------------------------------------- *
+ * REPLAY                                                           *
+ * ---------------------------------------------------------------- */
+
+/* Replay protection for the initiator's first message, which may carry
+ * 0-RTT payload. The cache remembers a digest of the responder's static key
+ * and the initiator's ephemeral for one window, taken over the decoded key so
+ * that another Elligator2 representative of it counts as the same message. On its own this only catches
+ * replays within the window; with timestamps turned on, the first payload must
+ * start with the sender's clock, which is authenticated along with the rest of
+ * the payload, and messages from outside the window are refused as well. */
+
+use crate::{
+	consts::HASHLEN,
+	noisesession::NoiseSession,
+	prims::hash,
+	state::Symmetric,
+	types::{Message, MessageBuffer},
+};
+use byteorder::{BigEndian, ByteOrder};
+use std::{
+	collections::{HashSet, VecDeque},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const TIMESTAMP_LENGTH: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReplayError {
+	/* The message was seen within the window. */
+	Replayed,
+	/* The message failed to decrypt or lacks a timestamp. */
+	Invalid,
+	/* The timestamp lies outside the window. */
+	Stale,
+}
+
+fn unix_millis() -> u64 {
+	let since_epoch = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or(Duration::from_secs(0));
+	since_epoch.as_secs() * 1000 + u64::from(since_epoch.subsec_millis())
+}
+
+/* Prefixes a first payload with the current time, for responders with timestamps turned on. */
+pub fn timestamped(payload: &[u8]) -> Message {
+	let mut out: Vec<u8> = vec![0u8; TIMESTAMP_LENGTH];
+	BigEndian::write_u64(&mut out[..], unix_millis());
+	out.extend_from_slice(payload);
+	Message::from_vec(out)
+}
+
+pub struct ReplayCache {
+	window: Duration,
+	timestamps: bool,
+	seen: HashSet<[u8; HASHLEN]>,
+	expiry: VecDeque<(Instant, [u8; HASHLEN])>,
+}
+impl ReplayCache {
+	pub fn new(window: Duration) -> ReplayCache {
+		ReplayCache {
+			window,
+			timestamps: false,
+			seen: HashSet::new(),
+			expiry: VecDeque::new(),
+		}
+	}
+	/* Requires first payloads to be built with timestamped(); both parties must agree. */
+	pub fn set_timestamps(&mut self, timestamps: bool) {
+		self.timestamps = timestamps;
+	}
+	fn prune(&mut self) {
+		let now: Instant = Instant::now();
+		while let Some((deadline, digest)) = self.expiry.front() {
+			if *deadline > now {
+				break;
+			}
+			self.seen.remove(digest);
+			self.expiry.pop_front();
+		}
+	}
+	fn check_timestamp<'a>(&self, plaintext: &'a [u8]) -> Result<&'a [u8], ReplayError> {
+		if plaintext.len() < TIMESTAMP_LENGTH {
+			return Err(ReplayError::Invalid);
+		}
+		let sent: u64 = BigEndian::read_u64(&plaintext[..TIMESTAMP_LENGTH]);
+		let now: u64 = unix_millis();
+		let skew: u64 = now.abs_diff(sent);
+		if u128::from(skew) > self.window.as_millis() {
+			return Err(ReplayError::Stale);
+		}
+		Ok(&plaintext[TIMESTAMP_LENGTH..])
+	}
+	/* Reads the first message of a responder session, which is left unusable on error.
+	 * The message is only recorded once it has been authenticated, so that a corrupted
+	 * copy cannot lock out the genuine one. */
+	pub fn recv_first_message<S: Symmetric>(
+		&mut self,
+		session: &mut NoiseSession<S>,
+		message: &mut MessageBuffer,
+	) -> Result<Vec<u8>, ReplayError> {
+		if session.is_initiator() || session.get_message_count() != 0 {
+			panic!("Not a fresh responder session; recv_first_message");
+		}
+		let mut input: Vec<u8> = Vec::from(&session.get_static_public_key().as_bytes()[..]);
+		input.extend_from_slice(&session.decode_ephemeral(&message.ne)[..]);
+		let mut digest = [0u8; HASHLEN];
+		digest.copy_from_slice(&hash(&input[..])[..HASHLEN]);
+		self.prune();
+		if self.seen.contains(&digest) {
+			return Err(ReplayError::Replayed);
+		}
+		let mut plaintext: Vec<u8> = session.recv_message(message).ok_or(ReplayError::Invalid)?;
+		if self.timestamps {
+			plaintext = Vec::from(self.check_timestamp(&plaintext[..])?);
+		}
+		self.seen.insert(digest);
+		self.expiry.push_back((Instant::now() + self.window, digest));
+		Ok(plaintext)
+	}
+}
+
+#[test]
+fn replay_timestamp_test() {
+	let mut cache: ReplayCache = ReplayCache::new(Duration::from_secs(30));
+	cache.set_timestamps(true);
+	let fresh: Message = timestamped(b"payload");
+	assert!(cache.check_timestamp(&fresh.as_bytes()[..]) == Ok(&b"payload"[..]));
+	let mut old: Vec<u8> = fresh.as_bytes().clone();
+	BigEndian::write_u64(&mut old[..TIMESTAMP_LENGTH], unix_millis() - 31_000);
+	assert!(cache.check_timestamp(&old[..]) == Err(ReplayError::Stale));
+	assert!(cache.check_timestamp(&old[..4]) == Err(ReplayError::Invalid));
+}
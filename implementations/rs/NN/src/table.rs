@@ -0,0 +1,162 @@
+/* ---------------------------------------------------------------- *
+ * SESSION TABLE                                                    *
+ * ---------------------------------------------------------------- */
+
+/* Responder sessions keyed by connection ID. Handshakes in progress count
+ * against a global and a per-peer limit and are abandoned after a deadline;
+ * finished sessions move to the established set. The handshake secrets of a
+ * session are wiped as soon as it completes, fails or expires. */
+
+use crate::{
+	noisesession::NoiseSession,
+	state::{Symmetric, SymmetricState},
+	types::{Message, MessageBuffer},
+};
+use std::{
+	collections::HashMap,
+	hash::Hash,
+	time::{Duration, Instant},
+};
+
+struct HalfOpen<P, S: Symmetric> {
+	session: NoiseSession<S>,
+	peer: P,
+	deadline: Instant,
+}
+
+pub struct SessionTable<C, P, S: Symmetric = SymmetricState> {
+	half_open: HashMap<C, HalfOpen<P, S>>,
+	established: HashMap<C, NoiseSession<S>>,
+	per_peer: HashMap<P, usize>,
+	max_half_open: usize,
+	max_half_open_per_peer: usize,
+	timeout: Duration,
+}
+
+impl<C: Eq + Hash + Clone, P: Eq + Hash + Clone, S: Symmetric> SessionTable<C, P, S> {
+	pub fn new(max_half_open: usize, max_half_open_per_peer: usize, timeout: Duration) -> SessionTable<C, P, S> {
+		SessionTable {
+			half_open: HashMap::new(),
+			established: HashMap::new(),
+			per_peer: HashMap::new(),
+			max_half_open,
+			max_half_open_per_peer,
+			timeout,
+		}
+	}
+	/* Returns None if the connection ID is in use or a limit on half-open handshakes has been reached. */
+	pub fn insert(&mut self, id: C, peer: P, mut session: NoiseSession<S>) -> Option<()> {
+		self.expire();
+		let count: usize = self.per_peer.get(&peer).copied().unwrap_or(0);
+		if self.half_open.contains_key(&id)
+			|| self.established.contains_key(&id)
+			|| self.half_open.len() >= self.max_half_open
+			|| count >= self.max_half_open_per_peer
+		{
+			session.clear_handshake();
+			return None;
+		}
+		self.per_peer.insert(peer.clone(), count + 1);
+		self.half_open.insert(id, HalfOpen {
+			session,
+			peer,
+			deadline: Instant::now() + self.timeout,
+		});
+		Some(())
+	}
+	fn release(&mut self, id: &C) -> Option<NoiseSession<S>> {
+		let entry: HalfOpen<P, S> = self.half_open.remove(id)?;
+		if let Some(count) = self.per_peer.get_mut(&entry.peer) {
+			*count -= 1;
+			if *count == 0 {
+				self.per_peer.remove(&entry.peer);
+			}
+		}
+		Some(entry.session)
+	}
+	fn abandon(&mut self, id: &C) {
+		if let Some(mut session) = self.release(id) {
+			session.clear_handshake();
+		}
+	}
+	/* Takes back a half-open session that has missed its deadline, or moves it to the established set once finished. */
+	fn settle(&mut self, id: &C) {
+		let (expired, finished) = match self.half_open.get(id) {
+			Some(entry) => (Instant::now() >= entry.deadline, entry.session.is_handshake_finished()),
+			None => return,
+		};
+		if finished {
+			if let Some(session) = self.release(id) {
+				self.established.insert(id.clone(), session);
+			}
+		} else if expired {
+			self.abandon(id);
+		}
+	}
+	/* Returns None if there is no such session or its handshake has expired.
+	 * A handshake message that cannot be written ends the handshake. */
+	pub fn send_message(&mut self, id: &C, message: Message) -> Option<MessageBuffer> {
+		if let Some(session) = self.established.get_mut(id) {
+			return session.send_message(message);
+		}
+		self.settle(id);
+		match self.half_open.get_mut(id)?.session.send_message(message) {
+			Some(buffer) => {
+				self.settle(id);
+				Some(buffer)
+			}
+			None => {
+				self.abandon(id);
+				None
+			}
+		}
+	}
+	/* A handshake message that fails to decrypt ends the handshake. */
+	pub fn recv_message(&mut self, id: &C, message: &mut MessageBuffer) -> Option<Vec<u8>> {
+		if let Some(session) = self.established.get_mut(id) {
+			return session.recv_message(message);
+		}
+		self.settle(id);
+		match self.half_open.get_mut(id)?.session.recv_message(message) {
+			Some(plaintext) => {
+				self.settle(id);
+				Some(plaintext)
+			}
+			None => {
+				self.abandon(id);
+				None
+			}
+		}
+	}
+	pub fn get_session(&self, id: &C) -> Option<&NoiseSession<S>> {
+		self.established.get(id)
+	}
+	pub fn get_session_mut(&mut self, id: &C) -> Option<&mut NoiseSession<S>> {
+		self.established.get_mut(id)
+	}
+	/* Returns the session if it is established; a half-open one is wiped and dropped. */
+	pub fn remove(&mut self, id: &C) -> Option<NoiseSession<S>> {
+		self.abandon(id);
+		self.established.remove(id)
+	}
+	/* Abandons every handshake past its deadline, returning how many there were. */
+	pub fn expire(&mut self) -> usize {
+		let now: Instant = Instant::now();
+		let stale: Vec<C> = self
+			.half_open
+			.iter()
+			.filter(|(_, entry)| now >= entry.deadline)
+			.map(|(id, _)| id.clone())
+			.collect();
+		for id in stale.iter() {
+			self.abandon(id);
+		}
+		stale.len()
+	}
+	pub fn half_open(&self) -> usize {
+		self.half_open.len()
+	}
+	pub fn established(&self) -> usize {
+		self.established.len()
+	}
+}
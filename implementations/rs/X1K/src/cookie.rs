@@ -0,0 +1,210 @@
+/* ---------------------------------------------------------------- *
+ * COOKIE                                                           *
+ * ---------------------------------------------------------------- */
+
+/* Stateless retry for responders under load. Before reading a first
+ * message, which costs the responder an es or ss DH operation, the responder
+ * asks for a cookie bound to the sender's address. The initiator sends the very
+ * same first message again together with the cookie, so the Noise transcript
+ * is left untouched; only a sender able to receive at its claimed address gets
+ * through. Cookies are MACs under a secret that rotates every COOKIE_MAX_AGE.
+ * On the wire, a first message is a type byte, the cookie if it has one, and
+ * the Noise message; a cookie reply is a type byte followed by the cookie. */
+
+use crate::{
+	consts::{DHLEN, EMPTY_KEY},
+	noisesession::NoiseSession,
+	noisesocket::message_layout,
+	prims::hmac,
+	state::Symmetric,
+	types::MessageBuffer,
+};
+use rand::RngCore;
+use std::time::{Duration, Instant};
+
+pub const COOKIE_LENGTH: usize = 16;
+const COOKIE_MAX_AGE: Duration = Duration::from_secs(120);
+const TYPE_FIRST: u8 = 1;
+const TYPE_FIRST_WITH_COOKIE: u8 = 2;
+const TYPE_REPLY: u8 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Admission {
+	Accept,
+	/* The first message was not read; it must be sent again along with this cookie. */
+	Retry([u8; COOKIE_LENGTH]),
+}
+
+#[derive(Clone)]
+pub enum CookieMessage {
+	/* The initiator's first message, with the cookie of the responder's reply once it has one. */
+	First(Option<[u8; COOKIE_LENGTH]>, MessageBuffer),
+	Reply([u8; COOKIE_LENGTH]),
+}
+impl CookieMessage {
+	pub fn first(message: MessageBuffer) -> CookieMessage {
+		CookieMessage::First(None, message)
+	}
+	/* Answers a cookie reply with the same first message, now carrying the cookie. */
+	pub fn retry(&self, reply: &CookieMessage) -> Option<CookieMessage> {
+		match (self, reply) {
+			(CookieMessage::First(_, message), CookieMessage::Reply(cookie)) => {
+				Some(CookieMessage::First(Some(*cookie), message.clone()))
+			}
+			_ => None,
+		}
+	}
+	pub fn to_bytes(&self) -> Vec<u8> {
+		match self {
+			CookieMessage::First(cookie, message) => {
+				let (ne_length, _) = message_layout(0);
+				let mut out: Vec<u8> = match cookie {
+					Some(cookie) => [&[TYPE_FIRST_WITH_COOKIE], &cookie[..]].concat(),
+					None => vec![TYPE_FIRST],
+				};
+				out.extend(&message.ne[..ne_length]);
+				out.extend(&message.ns[..]);
+				out.extend(&message.ciphertext[..]);
+				out
+			}
+			CookieMessage::Reply(cookie) => [&[TYPE_REPLY], &cookie[..]].concat(),
+		}
+	}
+	pub fn from_bytes(data: &[u8]) -> Option<CookieMessage> {
+		let mut cookie = [0u8; COOKIE_LENGTH];
+		let noise_message: &[u8] = match *data.first()? {
+			TYPE_FIRST => &data[1..],
+			TYPE_FIRST_WITH_COOKIE | TYPE_REPLY => {
+				cookie.copy_from_slice(data.get(1..1 + COOKIE_LENGTH)?);
+				&data[1 + COOKIE_LENGTH..]
+			}
+			_ => return None,
+		};
+		if data[0] == TYPE_REPLY {
+			return if noise_message.is_empty() { Some(CookieMessage::Reply(cookie)) } else { None };
+		}
+		let (ne_length, ns_length) = message_layout(0);
+		if noise_message.len() < ne_length + ns_length {
+			return None;
+		}
+		let mut ne = EMPTY_KEY;
+		ne[..ne_length].copy_from_slice(&noise_message[..ne_length]);
+		let message = MessageBuffer {
+			ne,
+			ns: Vec::from(&noise_message[ne_length..ne_length + ns_length]),
+			ciphertext: Vec::from(&noise_message[ne_length + ns_length..]),
+		};
+		Some(CookieMessage::First(if data[0] == TYPE_FIRST { None } else { Some(cookie) }, message))
+	}
+}
+
+#[derive(Clone)]
+pub enum CookieError {
+	/* The responder session was left untouched; this reply goes back to the sender. */
+	Retry(CookieMessage),
+	/* The message is not a first message, or failed to decrypt. */
+	Invalid,
+}
+
+pub struct CookieGuard {
+	secret: [u8; DHLEN],
+	previous: [u8; DHLEN],
+	secret_generated: Instant,
+	under_load: bool,
+}
+impl CookieGuard {
+	pub fn new() -> CookieGuard {
+		let mut guard = CookieGuard {
+			secret: [0u8; DHLEN],
+			previous: [0u8; DHLEN],
+			secret_generated: Instant::now(),
+			under_load: false,
+		};
+		guard.rotate();
+		guard.previous = guard.secret;
+		guard
+	}
+	fn rotate(&mut self) {
+		self.previous = self.secret;
+		rand::thread_rng().fill_bytes(&mut self.secret);
+		self.secret_generated = Instant::now();
+	}
+	fn cookie(secret: &[u8; DHLEN], address: &[u8]) -> [u8; COOKIE_LENGTH] {
+		let mut out = [0u8; DHLEN];
+		hmac(&secret[..], address, &mut out[..]);
+		let mut cookie = [0u8; COOKIE_LENGTH];
+		cookie.copy_from_slice(&out[..COOKIE_LENGTH]);
+		cookie
+	}
+	pub fn set_under_load(&mut self, under_load: bool) {
+		self.under_load = under_load;
+	}
+	pub fn is_under_load(&self) -> bool {
+		self.under_load
+	}
+	/* To be called on every first message before it reaches the responder's
+	 * session. Cookies issued under the previous secret remain valid. */
+	pub fn admit(&mut self, address: &[u8], cookie: Option<&[u8]>) -> Admission {
+		if !self.under_load {
+			return Admission::Accept;
+		}
+		if self.secret_generated.elapsed() >= COOKIE_MAX_AGE {
+			self.rotate();
+		}
+		if let Some(cookie) = cookie {
+			let current = CookieGuard::cookie(&self.secret, address);
+			let previous = CookieGuard::cookie(&self.previous, address);
+			if crypto::util::fixed_time_eq(cookie, &current[..]) || crypto::util::fixed_time_eq(cookie, &previous[..]) {
+				return Admission::Accept;
+			}
+		}
+		Admission::Retry(CookieGuard::cookie(&self.secret, address))
+	}
+	/* Reads the first message of a responder session once its sender, at address, is admitted. */
+	pub fn recv_first_message<S: Symmetric>(
+		&mut self,
+		session: &mut NoiseSession<S>,
+		address: &[u8],
+		message: &mut CookieMessage,
+	) -> Result<Vec<u8>, CookieError> {
+		if session.is_initiator() || session.get_message_count() != 0 {
+			panic!("Not a fresh responder session; recv_first_message");
+		}
+		let (cookie, buffer) = match message {
+			CookieMessage::First(cookie, buffer) => (*cookie, buffer),
+			CookieMessage::Reply(_) => return Err(CookieError::Invalid),
+		};
+		match self.admit(address, cookie.as_ref().map(|cookie| &cookie[..])) {
+			Admission::Accept => session.recv_message(buffer).ok_or(CookieError::Invalid),
+			Admission::Retry(cookie) => Err(CookieError::Retry(CookieMessage::Reply(cookie))),
+		}
+	}
+}
+impl Default for CookieGuard {
+	fn default() -> CookieGuard {
+		CookieGuard::new()
+	}
+}
+
+#[test]
+fn cookie_test() {
+	let mut guard: CookieGuard = CookieGuard::new();
+	assert!(guard.admit(b"192.0.2.1:443", None) == Admission::Accept);
+	guard.set_under_load(true);
+	let cookie: [u8; COOKIE_LENGTH] = match guard.admit(b"192.0.2.1:443", None) {
+		Admission::Retry(cookie) => cookie,
+		Admission::Accept => panic!("Cookie not requested"),
+	};
+	assert!(guard.admit(b"192.0.2.1:443", Some(&cookie[..])) == Admission::Accept);
+	assert!(guard.admit(b"192.0.2.2:443", Some(&cookie[..])) != Admission::Accept);
+	assert!(guard.admit(b"192.0.2.1:443", Some(&cookie[..8])) != Admission::Accept);
+	guard.rotate();
+	assert!(guard.admit(b"192.0.2.1:443", Some(&cookie[..])) == Admission::Accept);
+	guard.rotate();
+	assert!(guard.admit(b"192.0.2.1:443", Some(&cookie[..])) != Admission::Accept);
+	let reply: Vec<u8> = CookieMessage::Reply(cookie).to_bytes();
+	assert!(reply.len() == 1 + COOKIE_LENGTH && CookieMessage::from_bytes(&reply[..]).is_some());
+	assert!(CookieMessage::from_bytes(&[&reply[..], &[0u8]].concat()[..]).is_none());
+	assert!(CookieMessage::from_bytes(&reply[..COOKIE_LENGTH]).is_none());
+	assert!(CookieMessage::from_bytes(&[]).is_none());
+}
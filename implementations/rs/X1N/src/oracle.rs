@@ -0,0 +1,160 @@
+/* ---------------------------------------------------------------- *
+ * ORACLE                                                           *
+ * ---------------------------------------------------------------- */
+
+/* Static key oracles, passed to sessions as Keypair::from_oracle(oracle).
+ * LocalKeyOracle holds the private key in this process, where one key can be
+ * shared by many sessions or served to other processes with serve().
+ * UnixSocketOracle asks a key-holding daemon over a Unix socket, so that the
+ * private key never enters the memory of the process running the sessions.
+ * Each connection carries one request, an opcode followed for DH by the
+ * remote public key, and one reply, a status byte followed on success by
+ * DHLEN bytes. */
+
+use crate::{
+	consts::DHLEN,
+	types::{Keypair, PrivateKey, PublicKey, StaticKeyOracle},
+};
+#[cfg(unix)]
+use std::{
+	io::{self, Read, Write},
+	os::unix::net::{UnixListener, UnixStream},
+	path::{Path, PathBuf},
+	sync::Arc,
+	thread,
+	time::Duration,
+};
+
+#[cfg(unix)]
+const PUBLIC_KEY_REQUEST: u8 = 1;
+#[cfg(unix)]
+const DH_REQUEST: u8 = 2;
+#[cfg(unix)]
+const OK: u8 = 0;
+#[cfg(unix)]
+const REFUSED: u8 = 1;
+#[cfg(unix)]
+const ORACLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct LocalKeyOracle {
+	keypair: Keypair,
+}
+impl LocalKeyOracle {
+	pub fn new(private_key: PrivateKey) -> LocalKeyOracle {
+		LocalKeyOracle {
+			keypair: Keypair::from_private_key(private_key),
+		}
+	}
+}
+impl StaticKeyOracle for LocalKeyOracle {
+	fn public_key(&self) -> PublicKey {
+		self.keypair.get_public_key()
+	}
+	fn dh(&self, remote: &PublicKey) -> Option<[u8; DHLEN]> {
+		self.keypair.try_dh(&remote.as_bytes())
+	}
+}
+impl Drop for LocalKeyOracle {
+	fn drop(&mut self) {
+		self.keypair.clear();
+	}
+}
+
+#[cfg(unix)]
+fn request(path: &Path, message: &[u8]) -> io::Result<[u8; DHLEN]> {
+	let mut stream: UnixStream = UnixStream::connect(path)?;
+	stream.set_read_timeout(Some(ORACLE_TIMEOUT))?;
+	stream.write_all(message)?;
+	let mut status = [0u8; 1];
+	stream.read_exact(&mut status)?;
+	if status[0] != OK {
+		return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Request refused by static key oracle"));
+	}
+	let mut out = [0u8; DHLEN];
+	stream.read_exact(&mut out)?;
+	Ok(out)
+}
+
+#[cfg(unix)]
+pub struct UnixSocketOracle {
+	path: PathBuf,
+	public_key: PublicKey,
+}
+#[cfg(unix)]
+impl UnixSocketOracle {
+	/* Fetches the daemon's public key once; every DH then opens a new connection. */
+	pub fn connect(path: &Path) -> io::Result<UnixSocketOracle> {
+		let public_key: [u8; DHLEN] = request(path, &[PUBLIC_KEY_REQUEST])?;
+		Ok(UnixSocketOracle {
+			path: PathBuf::from(path),
+			public_key: PublicKey::from_bytes(public_key),
+		})
+	}
+}
+#[cfg(unix)]
+impl StaticKeyOracle for UnixSocketOracle {
+	fn public_key(&self) -> PublicKey {
+		self.public_key
+	}
+	fn dh(&self, remote: &PublicKey) -> Option<[u8; DHLEN]> {
+		let mut message: Vec<u8> = vec![DH_REQUEST];
+		message.extend_from_slice(&remote.as_bytes()[..]);
+		request(&self.path, &message[..]).ok()
+	}
+}
+
+#[cfg(unix)]
+fn answer(stream: &mut UnixStream, oracle: &dyn StaticKeyOracle) -> io::Result<()> {
+	stream.set_read_timeout(Some(ORACLE_TIMEOUT))?;
+	let mut opcode = [0u8; 1];
+	stream.read_exact(&mut opcode)?;
+	let reply: Option<[u8; DHLEN]> = match opcode[0] {
+		PUBLIC_KEY_REQUEST => Some(oracle.public_key().as_bytes()),
+		DH_REQUEST => {
+			let mut remote = [0u8; DHLEN];
+			stream.read_exact(&mut remote)?;
+			oracle.dh(&PublicKey::from_bytes(remote))
+		}
+		_ => None,
+	};
+	match reply {
+		Some(out) => {
+			let mut message: Vec<u8> = vec![OK];
+			message.extend_from_slice(&out[..]);
+			stream.write_all(&message[..])
+		}
+		None => stream.write_all(&[REFUSED]),
+	}
+}
+
+/* The daemon side of UnixSocketOracle: answers each connection on a thread of
+ * its own, so that a slow client cannot hold up the others, until accepting one
+ * fails. Access control is left to the socket's permissions. */
+#[cfg(unix)]
+pub fn serve(listener: &UnixListener, oracle: Arc<dyn StaticKeyOracle>) -> io::Result<()> {
+	loop {
+		let (mut stream, _) = listener.accept()?;
+		let oracle: Arc<dyn StaticKeyOracle> = Arc::clone(&oracle);
+		thread::spawn(move || answer(&mut stream, &*oracle));
+	}
+}
+
+#[cfg(unix)]
+#[test]
+fn oracle_test() {
+	let oracle: LocalKeyOracle = LocalKeyOracle::new(PrivateKey::from_str(
+		"e61ef9919cde45dd5f82166404bd08e38bceb5dfdfded0a34c8df7ed542214d1",
+	));
+	let public_key: PublicKey = oracle.public_key();
+	let path: PathBuf = std::env::temp_dir().join(format!("noiseexplorer_oracle_{}", std::process::id()));
+	let _ = std::fs::remove_file(&path);
+	let listener: UnixListener = UnixListener::bind(&path).unwrap();
+	thread::spawn(move || serve(&listener, Arc::new(oracle)));
+	let keypair: Keypair = Keypair::from_oracle(Arc::new(UnixSocketOracle::connect(&path).unwrap()));
+	let peer: Keypair = Keypair::new();
+	assert!(!keypair.is_empty() && keypair.get_public_key().as_bytes() == public_key.as_bytes());
+	assert!(keypair.dh(&peer.get_public_key().as_bytes()) == peer.dh(&public_key.as_bytes()));
+	std::fs::remove_file(&path).unwrap();
+	assert!(keypair.try_dh(&peer.get_public_key().as_bytes()).is_none());
+	assert!(UnixSocketOracle::connect(&path).is_err());
+}
@@ -0,0 +1,298 @@
+/* ---------------------------------------------------------------- *
+ * BOLT-8                                                           *
+ * ---------------------------------------------------------------- */
+
+/* BOLT-8 runs Noise_XK_secp256k1_ChaChaPoly_SHA256: the acts below are
+ * produced by the generated XK HandshakeState over a SHA-256 SymmetricState,
+ * with secp256k1 keys plugged in for its DH. */
+
+use crate::{
+	consts::{DHLEN, MAC_LENGTH, ZEROLEN},
+	prims::{decrypt, encrypt, HashFunction, Sha256Hash},
+	state::{HandshakeState, SymmetricState},
+	types::{DhKeypair, DhPublicKey, Hash, MessageBuffer, Psk},
+};
+use byteorder::{BigEndian, ByteOrder};
+use rand::RngCore;
+use secp256k1::{ecdh::SharedSecret, PublicKey, Secp256k1, SecretKey};
+
+const PROLOGUE: &[u8] = b"lightning";
+const VERSION: u8 = 0;
+const ROTATE_AFTER: u64 = 1000;
+
+pub const PUBLIC_KEY_LENGTH: usize = 33;
+pub const ACT_ONE_LENGTH: usize = 1 + PUBLIC_KEY_LENGTH + MAC_LENGTH;
+pub const ACT_TWO_LENGTH: usize = 1 + PUBLIC_KEY_LENGTH + MAC_LENGTH;
+pub const ACT_THREE_LENGTH: usize = 1 + PUBLIC_KEY_LENGTH + 2 * MAC_LENGTH;
+pub const LENGTH_HEADER_LENGTH: usize = 2 + MAC_LENGTH;
+pub const MAX_MESSAGE_LENGTH: usize = 65535;
+
+fn hkdf_sha256(salt: &[u8], ikm: &[u8]) -> ([u8; 32], [u8; 32]) {
+	let mut out1 = [0u8; 32];
+	let mut out2 = [0u8; 32];
+	Sha256Hash::hkdf(salt, ikm, 2, &mut out1[..], &mut out2[..], &mut []);
+	(out1, out2)
+}
+
+fn generate_secret_key() -> SecretKey {
+	let mut bytes = [0u8; 32];
+	loop {
+		rand::thread_rng().fill_bytes(&mut bytes);
+		if let Ok(k) = SecretKey::from_slice(&bytes[..]) {
+			return k;
+		}
+	}
+}
+
+/* The transport keys are derived as by Split, from the chaining key returned
+ * with the final act, which BOLT-8 goes on using for key rotation. */
+fn split(ck: &Hash) -> (CipherState, CipherState) {
+	let ck: [u8; 32] = *ck.as_bytes();
+	let (k1, k2) = hkdf_sha256(&ck[..], &ZEROLEN[..]);
+	(CipherState::new(ck, k1), CipherState::new(ck, k2))
+}
+
+/* ---------------------------------------------------------------- *
+ * SECP256K1                                                        *
+ * ---------------------------------------------------------------- */
+
+/* Public keys are sent compressed; the all-zero encoding stands for none. */
+#[derive(Clone, Copy)]
+pub struct Secp256k1PublicKey {
+	k: [u8; PUBLIC_KEY_LENGTH],
+}
+impl DhPublicKey for Secp256k1PublicKey {
+	type Bytes = [u8; PUBLIC_KEY_LENGTH];
+	fn empty() -> Secp256k1PublicKey {
+		Secp256k1PublicKey { k: [0u8; PUBLIC_KEY_LENGTH] }
+	}
+	fn clear(&mut self) {
+		self.k = [0u8; PUBLIC_KEY_LENGTH];
+	}
+	fn is_empty(&self) -> bool {
+		self.k == [0u8; PUBLIC_KEY_LENGTH]
+	}
+	fn as_bytes(&self) -> [u8; PUBLIC_KEY_LENGTH] {
+		self.k
+	}
+	fn from_slice(bytes: &[u8]) -> Option<Secp256k1PublicKey> {
+		let public_key: PublicKey = PublicKey::from_slice(bytes).ok()?;
+		Some(Secp256k1PublicKey::from(public_key))
+	}
+}
+impl From<PublicKey> for Secp256k1PublicKey {
+	fn from(public_key: PublicKey) -> Secp256k1PublicKey {
+		Secp256k1PublicKey { k: public_key.serialize() }
+	}
+}
+
+#[derive(Clone)]
+pub struct Secp256k1Keypair {
+	secret_key: Option<SecretKey>,
+	public_key: Secp256k1PublicKey,
+}
+impl Secp256k1Keypair {
+	pub fn from_secret_key(secret_key: SecretKey) -> Secp256k1Keypair {
+		let public_key: PublicKey = PublicKey::from_secret_key(&Secp256k1::signing_only(), &secret_key);
+		Secp256k1Keypair {
+			secret_key: Some(secret_key),
+			public_key: Secp256k1PublicKey::from(public_key),
+		}
+	}
+}
+impl DhKeypair for Secp256k1Keypair {
+	type PublicKey = Secp256k1PublicKey;
+	const DH_NAME: &'static str = "secp256k1";
+	fn new() -> Secp256k1Keypair {
+		Secp256k1Keypair::from_secret_key(generate_secret_key())
+	}
+	fn new_empty() -> Secp256k1Keypair {
+		Secp256k1Keypair {
+			secret_key: None,
+			public_key: Secp256k1PublicKey::empty(),
+		}
+	}
+	fn is_empty(&self) -> bool {
+		self.secret_key.is_none()
+	}
+	fn clear(&mut self) {
+		if let Some(secret_key) = &mut self.secret_key {
+			secret_key.non_secure_erase();
+		}
+		self.secret_key = None;
+		self.public_key.clear();
+	}
+	fn get_public_key(&self) -> Secp256k1PublicKey {
+		self.public_key
+	}
+	/* The SHA-256 of the compressed shared point, as BOLT-8 specifies. */
+	fn try_dh(&self, public_key: &[u8; PUBLIC_KEY_LENGTH]) -> Option<[u8; DHLEN]> {
+		let secret_key: &SecretKey = self.secret_key.as_ref()?;
+		let public_key: PublicKey = PublicKey::from_slice(&public_key[..]).ok()?;
+		let mut out = [0u8; DHLEN];
+		out.copy_from_slice(SharedSecret::new(&public_key, secret_key).as_ref());
+		Some(out)
+	}
+}
+
+/* ---------------------------------------------------------------- *
+ * HANDSHAKE                                                        *
+ * ---------------------------------------------------------------- */
+
+pub struct Handshake {
+	hs: HandshakeState<SymmetricState<Sha256Hash>, Secp256k1Keypair>,
+	initiator: bool,
+}
+impl Handshake {
+	pub fn initiator(s: SecretKey, rs: PublicKey) -> Handshake {
+		Handshake {
+			hs: HandshakeState::initialize_initiator(
+				PROLOGUE,
+				Secp256k1Keypair::from_secret_key(s),
+				Secp256k1PublicKey::from(rs),
+				Psk::new(),
+			),
+			initiator: true,
+		}
+	}
+	pub fn responder(s: SecretKey) -> Handshake {
+		Handshake {
+			hs: HandshakeState::initialize_responder(
+				PROLOGUE,
+				Secp256k1Keypair::from_secret_key(s),
+				Secp256k1PublicKey::empty(),
+				Psk::new(),
+			),
+			initiator: false,
+		}
+	}
+	pub fn set_ephemeral_key(&mut self, e: SecretKey) {
+		self.hs.set_ephemeral_keypair(Secp256k1Keypair::from_secret_key(e));
+	}
+	pub fn get_remote_static_public_key(&self) -> Option<PublicKey> {
+		PublicKey::from_slice(&self.hs.get_remote_static_public_key().as_bytes()[..]).ok()
+	}
+	fn read_ephemeral(act: &[u8], length: usize) -> Option<MessageBuffer<[u8; PUBLIC_KEY_LENGTH]>> {
+		if act.len() != length || act[0] != VERSION {
+			return None;
+		}
+		let mut ne = [0u8; PUBLIC_KEY_LENGTH];
+		ne.copy_from_slice(&act[1..1 + PUBLIC_KEY_LENGTH]);
+		Some(MessageBuffer {
+			ne,
+			ns: Vec::new(),
+			ciphertext: act[1 + PUBLIC_KEY_LENGTH..].to_vec(),
+		})
+	}
+	fn write_ephemeral(message: MessageBuffer<[u8; PUBLIC_KEY_LENGTH]>) -> Vec<u8> {
+		let mut act: Vec<u8> = vec![VERSION];
+		act.extend(&message.ne[..]);
+		act.extend(message.ciphertext);
+		act
+	}
+	pub fn write_act_one(&mut self) -> Vec<u8> {
+		assert!(self.initiator, "Only the initiator sends act one");
+		let message = self.hs.write_message_a(&ZEROLEN[..]).expect("Act one not written; write_act_one");
+		Handshake::write_ephemeral(message)
+	}
+	pub fn read_act_one(&mut self, act: &[u8]) -> Option<()> {
+		let mut message = Handshake::read_ephemeral(act, ACT_ONE_LENGTH)?;
+		self.hs.read_message_a(&mut message)?;
+		Some(())
+	}
+	pub fn write_act_two(&mut self) -> Vec<u8> {
+		assert!(!self.initiator, "Only the responder sends act two");
+		let message = self.hs.write_message_b(&ZEROLEN[..]).expect("Act two not written; write_act_two");
+		Handshake::write_ephemeral(message)
+	}
+	pub fn read_act_two(&mut self, act: &[u8]) -> Option<()> {
+		let mut message = Handshake::read_ephemeral(act, ACT_TWO_LENGTH)?;
+		self.hs.read_message_b(&mut message)?;
+		Some(())
+	}
+	pub fn write_act_three(&mut self) -> (Vec<u8>, Transport) {
+		assert!(self.initiator, "Only the initiator sends act three");
+		let (_, message, _, _, ck) = self.hs.write_message_c(&ZEROLEN[..]).expect("Act three not written; write_act_three");
+		let mut act: Vec<u8> = vec![VERSION];
+		act.extend(message.ns);
+		act.extend(message.ciphertext);
+		let (sk, rk) = split(&ck);
+		(act, Transport { sender: sk, receiver: rk })
+	}
+	pub fn read_act_three(&mut self, act: &[u8]) -> Option<Transport> {
+		if act.len() != ACT_THREE_LENGTH || act[0] != VERSION {
+			return None;
+		}
+		let (_, _, _, _, ck) = self.hs.read_message_c(&mut MessageBuffer {
+			ne: [0u8; PUBLIC_KEY_LENGTH],
+			ns: act[1..1 + PUBLIC_KEY_LENGTH + MAC_LENGTH].to_vec(),
+			ciphertext: act[1 + PUBLIC_KEY_LENGTH + MAC_LENGTH..].to_vec(),
+		})?;
+		let (rk, sk) = split(&ck);
+		Some(Transport { sender: sk, receiver: rk })
+	}
+}
+
+/* ---------------------------------------------------------------- *
+ * TRANSPORT                                                        *
+ * ---------------------------------------------------------------- */
+
+#[derive(Clone)]
+struct CipherState {
+	ck: [u8; 32],
+	k: [u8; 32],
+	n: u64,
+}
+impl CipherState {
+	fn new(ck: [u8; 32], k: [u8; 32]) -> CipherState {
+		CipherState { ck, k, n: 0 }
+	}
+	fn used(&mut self) {
+		self.n += 1;
+		if self.n == ROTATE_AFTER {
+			let (ck, k) = hkdf_sha256(&self.ck[..], &self.k[..]);
+			self.ck = ck;
+			self.k = k;
+			self.n = 0;
+		}
+	}
+	fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8> {
+		let ciphertext: Vec<u8> = encrypt(self.k, self.n, &ZEROLEN[..], plaintext);
+		self.used();
+		ciphertext
+	}
+	fn decrypt(&mut self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+		let plaintext: Vec<u8> = decrypt(self.k, self.n, &ZEROLEN[..], ciphertext)?;
+		self.used();
+		Some(plaintext)
+	}
+}
+
+pub struct Transport {
+	sender: CipherState,
+	receiver: CipherState,
+}
+impl Transport {
+	pub fn write_message(&mut self, message: &[u8]) -> Vec<u8> {
+		assert!(message.len() <= MAX_MESSAGE_LENGTH, "Message too long");
+		let mut length = [0u8; 2];
+		BigEndian::write_u16(&mut length, message.len() as u16);
+		let mut packet: Vec<u8> = self.sender.encrypt(&length[..]);
+		packet.extend(self.sender.encrypt(message));
+		packet
+	}
+	/* Returns the length of the ciphertext that follows, including its MAC. */
+	pub fn read_length(&mut self, header: &[u8]) -> Option<usize> {
+		if header.len() != LENGTH_HEADER_LENGTH {
+			return None;
+		}
+		let length: Vec<u8> = self.receiver.decrypt(header)?;
+		Some(BigEndian::read_u16(&length[..]) as usize + MAC_LENGTH)
+	}
+	pub fn read_message(&mut self, body: &[u8]) -> Option<Vec<u8>> {
+		if body.len() < MAC_LENGTH {
+			return None;
+		}
+		self.receiver.decrypt(body)
+	}
+}
@@ -0,0 +1,99 @@
+#![allow(non_snake_case, non_upper_case_globals)]
+
+use noiseexplorer_xk::bolt8::{Handshake, Transport, LENGTH_HEADER_LENGTH};
+use secp256k1::{PublicKey, SecretKey};
+use std::str::FromStr;
+
+fn decode(s: &str) -> Vec<u8> {
+	hex::decode(s).unwrap()
+}
+
+fn handshake_pair() -> (Handshake, Handshake) {
+	let rs: PublicKey = PublicKey::from_str("028d7500dd4c12685d1f568b4c2b5048e8534b873319f3a8daa612b469132ec7f7").unwrap();
+	let mut initiator: Handshake = Handshake::initiator(
+		SecretKey::from_str("1111111111111111111111111111111111111111111111111111111111111111").unwrap(),
+		rs,
+	);
+	let mut responder: Handshake = Handshake::responder(
+		SecretKey::from_str("2121212121212121212121212121212121212121212121212121212121212121").unwrap(),
+	);
+	initiator.set_ephemeral_key(SecretKey::from_str("1212121212121212121212121212121212121212121212121212121212121212").unwrap());
+	responder.set_ephemeral_key(SecretKey::from_str("2222222222222222222222222222222222222222222222222222222222222222").unwrap());
+	(initiator, responder)
+}
+
+fn transport_pair() -> (Transport, Transport) {
+	let (mut initiator, mut responder) = handshake_pair();
+	let act_one: Vec<u8> = initiator.write_act_one();
+	responder.read_act_one(&act_one[..]).unwrap();
+	let act_two: Vec<u8> = responder.write_act_two();
+	initiator.read_act_two(&act_two[..]).unwrap();
+	let (act_three, initiator_transport) = initiator.write_act_three();
+	let responder_transport: Transport = responder.read_act_three(&act_three[..]).unwrap();
+	(initiator_transport, responder_transport)
+}
+
+/* Test vectors from BOLT #8, Appendix A. */
+#[test]
+fn noiseexplorer_test_bolt8_handshake_vectors() {
+	let (mut initiator, mut responder) = handshake_pair();
+	let act_one: Vec<u8> = initiator.write_act_one();
+	assert_eq!(act_one, decode("00036360e856310ce5d294e8be33fc807077dc56ac80d95d9cd4ddbd21325eff73f70df6086551151f58b8afe6c195782c6a"));
+	assert!(responder.read_act_one(&act_one[..]).is_some());
+	let act_two: Vec<u8> = responder.write_act_two();
+	assert_eq!(act_two, decode("0002466d7fcae563e5cb09a0d1870bb580344804617879a14949cf22285f1bae3f276e2470b93aac583c9ef6eafca3f730ae"));
+	assert!(initiator.read_act_two(&act_two[..]).is_some());
+	let (act_three, _) = initiator.write_act_three();
+	assert_eq!(act_three, decode("00b9e3a702e93e3a9948c2ed6e5fd7590a6e1c3a0344cfc9d5b57357049aa22355361aa02e55a8fc28fef5bd6d71ad0c38228dc68b1c466263b47fdf31e560e139ba"));
+	assert!(responder.read_act_three(&act_three[..]).is_some());
+	assert_eq!(
+		responder.get_remote_static_public_key().unwrap().serialize().to_vec(),
+		decode("034f355bdcb7cc0af728ef3cceb9615d90684bb5b2ca5f859ab0f0b704075871aa")
+	);
+}
+
+#[test]
+fn noiseexplorer_test_bolt8_transport_vectors() {
+	let (mut initiator, mut responder) = transport_pair();
+	for i in 0..1002 {
+		let packet: Vec<u8> = initiator.write_message(b"hello");
+		match i {
+			0 => assert_eq!(packet, decode("cf2b30ddf0cf3f80e7c35a6e6730b59fe802473180f396d88a8fb0db8cbcf25d2f214cf9ea1d95")),
+			1 => assert_eq!(packet, decode("72887022101f0b6753e0c7de21657d35a4cb2a1f5cde2650528bbc8f837d0f0d7ad833b1a256a1")),
+			500 => assert_eq!(packet, decode("178cb9d7387190fa34db9c2d50027d21793c9bc2d40b1e14dcf30ebeeeb220f48364f7a4c68bf8")),
+			501 => assert_eq!(packet, decode("1b186c57d44eb6de4c057c49940d79bb838a145cb528d6e8fd26dbe50a60ca2c104b56b60e45bd")),
+			1000 => assert_eq!(packet, decode("4a2f3cc3b5e78ddb83dcb426d9863d9d9a723b0337c89dd0b005d89f8d3c05c52b76b29b740f09")),
+			1001 => assert_eq!(packet, decode("2ecd8c8a5629d0d02ab457a0fdd0f7b90a192cd46be5ecb6ca570bfc5e268338b1a16cf4ef2d36")),
+			_ => {}
+		}
+		let length: usize = responder.read_length(&packet[..LENGTH_HEADER_LENGTH]).unwrap();
+		assert_eq!(length, packet.len() - LENGTH_HEADER_LENGTH);
+		assert_eq!(responder.read_message(&packet[LENGTH_HEADER_LENGTH..]).unwrap(), b"hello".to_vec());
+	}
+	let reply: Vec<u8> = responder.write_message(b"world");
+	let length: usize = initiator.read_length(&reply[..LENGTH_HEADER_LENGTH]).unwrap();
+	assert_eq!(initiator.read_message(&reply[LENGTH_HEADER_LENGTH..LENGTH_HEADER_LENGTH + length]).unwrap(), b"world".to_vec());
+}
+
+#[test]
+fn noiseexplorer_test_bolt8_rejects_invalid() {
+	let (mut initiator, mut responder) = handshake_pair();
+	let mut act_one: Vec<u8> = initiator.write_act_one();
+	act_one[0] = 0x01;
+	assert!(responder.read_act_one(&act_one[..]).is_none());
+
+	let (mut initiator, mut responder) = handshake_pair();
+	let mut act_one: Vec<u8> = initiator.write_act_one();
+	act_one[1] = 0x04;
+	assert!(responder.read_act_one(&act_one[..]).is_none());
+
+	let (mut initiator, mut responder) = handshake_pair();
+	let mut act_one: Vec<u8> = initiator.write_act_one();
+	act_one[49] ^= 0x01;
+	assert!(responder.read_act_one(&act_one[..]).is_none());
+
+	let (mut initiator, mut responder) = transport_pair();
+	let mut packet: Vec<u8> = initiator.write_message(b"hello");
+	packet[0] ^= 0x01;
+	assert!(responder.read_length(&packet[..LENGTH_HEADER_LENGTH]).is_none());
+}
@@ -0,0 +1,119 @@
+#![allow(non_snake_case, non_upper_case_globals)]
+
+use noiseexplorer_xk::{
+	ntcp2::{Handshake, SessionCreated, SessionRequest, Transport, LENGTH_HEADER_LENGTH, SESSION_CREATED_LENGTH, SESSION_REQUEST_LENGTH},
+	types::{Keypair, PrivateKey},
+};
+
+fn decode(s: &str) -> Vec<u8> {
+	hex::decode(s).unwrap()
+}
+
+const ROUTER_HASH: [u8; 32] = [
+	0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15,
+	0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+];
+const IV: [u8; 16] = [
+	0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff,
+];
+const SESSION_REQUEST: &str = "3156f73638b7b43d3b96bf7827a84f4138ac0ce77fde367cb3dae3b5b3a7044bc41dcd090864789cf228958a8dcdd3cbefe818c857af3903a9803d29396436ec5061646431";
+const SESSION_CREATED: &str = "5f01b2a96e2d02a6036471808c10d02051441a189f9bbe20e2e261fb57a9591b64f0eba8f211df2d96f439205b75b39b97e4935eef8f564cc4c536a077738e70506164642d322d32";
+const SESSION_CONFIRMED: &str = "2e7f1b31f2211f61baf19e7017b3512ff066e46bbb13d8608dfa3a3145be677cca9e9227a5cba7f72f244ea0df029fe010ac694800775b16b538ae05c03ca214ab38f93de4d7f8645e7ebcee";
+const ROUTER_INFO_BLOCK: &str = "02000900526f75746572496e";
+
+fn handshake_pair() -> (Handshake, Handshake) {
+	let responder_static: Keypair = Keypair::from_private_key(PrivateKey::from_str("e0e1e2e3e4e5e6e7e8e9eaebecedeeeff0f1f2f3f4f5f6f7f8f9fafbfcfdfeff"));
+	let mut initiator: Handshake = Handshake::initiator(
+		Keypair::from_private_key(PrivateKey::from_str("c0c1c2c3c4c5c6c7c8c9cacbcccdcecfd0d1d2d3d4d5d6d7d8d9dadbdcdddedf")),
+		responder_static.get_public_key(),
+		ROUTER_HASH,
+		IV,
+	);
+	let mut responder: Handshake = Handshake::responder(responder_static, ROUTER_HASH, IV);
+	initiator.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("a0a1a2a3a4a5a6a7a8a9aaabacadaeafb0b1b2b3b4b5b6b7b8b9babbbcbdbebf")));
+	responder.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("8081828384858687888990919293949596979899a0a1a2a3a4a5a6a7a8a9aaab")));
+	(initiator, responder)
+}
+
+fn read_frame(transport: &mut Transport, packet: &[u8]) -> Option<Vec<u8>> {
+	let length: usize = transport.read_length(&packet[..LENGTH_HEADER_LENGTH])?;
+	if packet.len() != LENGTH_HEADER_LENGTH + length {
+		return None;
+	}
+	transport.read_message(&packet[LENGTH_HEADER_LENGTH..])
+}
+
+/* Transcript recorded from an independent implementation of the NTCP2
+ * specification, with fixed keys, padding and timestamps; it has not been
+ * compared with a transcript recorded from i2pd or Java I2P. */
+#[test]
+fn noiseexplorer_test_ntcp2_transcript() {
+	let (mut initiator, mut responder) = handshake_pair();
+	let router_info: Vec<u8> = decode(ROUTER_INFO_BLOCK);
+	let message: Vec<u8> = initiator.write_session_request(2, (router_info.len() + 16) as u16, 1_700_000_000, b"Padd1").unwrap();
+	assert_eq!(message, decode(SESSION_REQUEST));
+	let request: SessionRequest = responder.read_session_request(&message[..SESSION_REQUEST_LENGTH]).unwrap();
+	assert_eq!(
+		request,
+		SessionRequest {
+			network_id: 2,
+			version: 2,
+			padding_length: 5,
+			message3_part2_length: 28,
+			timestamp: 1_700_000_000,
+		}
+	);
+	responder.read_padding(&message[SESSION_REQUEST_LENGTH..]).unwrap();
+	let message: Vec<u8> = responder.write_session_created(1_700_000_005, b"Padd-2-2").unwrap();
+	assert_eq!(message, decode(SESSION_CREATED));
+	let created: SessionCreated = initiator.read_session_created(&message[..SESSION_CREATED_LENGTH]).unwrap();
+	assert_eq!(
+		created,
+		SessionCreated {
+			padding_length: 8,
+			timestamp: 1_700_000_005,
+		}
+	);
+	initiator.read_padding(&message[SESSION_CREATED_LENGTH..]).unwrap();
+	let (message, mut initiator_transport) = initiator.write_session_confirmed(&router_info[..]).unwrap();
+	assert_eq!(message, decode(SESSION_CONFIRMED));
+	let (payload, mut responder_transport) = responder.read_session_confirmed(&message[..]).unwrap();
+	assert_eq!(payload, router_info);
+	assert!(!responder.get_remote_static_public_key().is_empty());
+	let frames: [(bool, &str, &str); 3] = [
+		(true, "0000046553f100", "f39ff40a0b023e61b17b5ae58ea43d5a02a8a5dbf8a4d5aa0f"),
+		(true, "fe0003000000", "563087bdc247c6d56746544b5fda2b3f5bba330627129fa3"),
+		(false, "0000046553f105", "a1619772602434b1c5c0886317a862be34fa1518d57ce6fb9e"),
+	];
+	for (from_initiator, payload, packet) in frames.iter() {
+		let (sender, receiver) = if *from_initiator {
+			(&mut initiator_transport, &mut responder_transport)
+		} else {
+			(&mut responder_transport, &mut initiator_transport)
+		};
+		let frame: Vec<u8> = sender.write_message(&decode(payload)[..]);
+		assert_eq!(frame, decode(packet));
+		assert_eq!(read_frame(receiver, &frame[..]).unwrap(), decode(payload));
+	}
+}
+
+#[test]
+fn noiseexplorer_test_ntcp2_rejects_invalid() {
+	let (mut initiator, mut responder) = handshake_pair();
+	let mut message: Vec<u8> = initiator.write_session_request(2, 28, 1_700_000_000, b"Padd1").unwrap();
+	message[0] ^= 0x01;
+	assert!(responder.read_session_request(&message[..SESSION_REQUEST_LENGTH]).is_none());
+
+	let (mut initiator, mut responder) = handshake_pair();
+	let message: Vec<u8> = initiator.write_session_request(2, 28, 1_700_000_000, b"Padd1").unwrap();
+	responder.read_session_request(&message[..SESSION_REQUEST_LENGTH]).unwrap();
+	assert!(responder.read_padding(&message[SESSION_REQUEST_LENGTH + 1..]).is_none());
+	responder.read_padding(&message[SESSION_REQUEST_LENGTH..]).unwrap();
+	let mut message: Vec<u8> = responder.write_session_created(1_700_000_005, b"Padd-2-2").unwrap();
+	let last = message.len() - 1;
+	message[last] ^= 0x01;
+	initiator.read_session_created(&message[..SESSION_CREATED_LENGTH]).unwrap();
+	initiator.read_padding(&message[SESSION_CREATED_LENGTH..]).unwrap();
+	let (message, _) = initiator.write_session_confirmed(&decode(ROUTER_INFO_BLOCK)[..]).unwrap();
+	assert!(responder.read_session_confirmed(&message[..]).is_none());
+}
@@ -0,0 +1,328 @@
+/* ---------------------------------------------------------------- *
+ * DISCO                                                            *
+ * ---------------------------------------------------------------- */
+
+/* Disco replaces the SymmetricState and CipherState of Noise with a single
+ * STROBE object, built on Keccak-f[1600] at the 128-bit security level. */
+
+use crate::{
+	consts::{EMPTY_KEY, HASHLEN, MAC_LENGTH},
+	state::{Cipher, Symmetric},
+	types::MessageBuffer,
+};
+use byteorder::{ByteOrder, LittleEndian};
+
+const STROBE_R: usize = 166;
+const FLAG_I: u8 = 1;
+const FLAG_A: u8 = 1 << 1;
+const FLAG_C: u8 = 1 << 2;
+const FLAG_T: u8 = 1 << 3;
+const FLAG_M: u8 = 1 << 4;
+const RATCHET_LENGTH: usize = 16;
+
+const ROUND_CONSTANTS: [u64; 24] = [
+	0x0000_0000_0000_0001, 0x0000_0000_0000_8082, 0x8000_0000_0000_808a, 0x8000_0000_8000_8000,
+	0x0000_0000_0000_808b, 0x0000_0000_8000_0001, 0x8000_0000_8000_8081, 0x8000_0000_0000_8009,
+	0x0000_0000_0000_008a, 0x0000_0000_0000_0088, 0x0000_0000_8000_8009, 0x0000_0000_8000_000a,
+	0x0000_0000_8000_808b, 0x8000_0000_0000_008b, 0x8000_0000_0000_8089, 0x8000_0000_0000_8003,
+	0x8000_0000_0000_8002, 0x8000_0000_0000_0080, 0x0000_0000_0000_800a, 0x8000_0000_8000_000a,
+	0x8000_0000_8000_8081, 0x8000_0000_0000_8080, 0x0000_0000_8000_0001, 0x8000_0000_8000_8008,
+];
+/* Rotation offsets for lane x + 5y. */
+const ROTATIONS: [u32; 25] = [
+	0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56, 14,
+];
+
+fn keccak_f1600(st: &mut [u8; 200]) {
+	let mut a = [0u64; 25];
+	for (lane, bytes) in a.iter_mut().zip(st.chunks(8)) {
+		*lane = LittleEndian::read_u64(bytes);
+	}
+	for rc in ROUND_CONSTANTS.iter() {
+		let mut c = [0u64; 5];
+		for (x, column) in c.iter_mut().enumerate() {
+			*column = a[x] ^ a[x + 5] ^ a[x + 10] ^ a[x + 15] ^ a[x + 20];
+		}
+		for (i, lane) in a.iter_mut().enumerate() {
+			*lane ^= c[(i + 4) % 5] ^ c[(i + 1) % 5].rotate_left(1);
+		}
+		let mut b = [0u64; 25];
+		for (i, lane) in a.iter().enumerate() {
+			let (x, y) = (i % 5, i / 5);
+			b[y + 5 * ((2 * x + 3 * y) % 5)] = lane.rotate_left(ROTATIONS[i]);
+		}
+		for (i, lane) in a.iter_mut().enumerate() {
+			let (x, y) = (i % 5, i / 5);
+			*lane = b[i] ^ (!b[(x + 1) % 5 + 5 * y] & b[(x + 2) % 5 + 5 * y]);
+		}
+		a[0] ^= rc;
+	}
+	for (lane, bytes) in a.iter().zip(st.chunks_mut(8)) {
+		LittleEndian::write_u64(bytes, *lane);
+	}
+}
+
+/* STROBE v1.0.2, without streaming operations. */
+#[derive(Clone)]
+struct Strobe {
+	st: [u8; 200],
+	pos: usize,
+	pos_begin: usize,
+	i0: Option<u8>,
+}
+impl Strobe {
+	fn new(protocol_name: &[u8]) -> Strobe {
+		let mut st = [0u8; 200];
+		st[..6].copy_from_slice(&[1, STROBE_R as u8 + 2, 1, 0, 1, 96]);
+		st[6..18].copy_from_slice(b"STROBEv1.0.2");
+		keccak_f1600(&mut st);
+		let mut strobe = Strobe {
+			st,
+			pos: 0,
+			pos_begin: 0,
+			i0: None,
+		};
+		strobe.meta_ad(protocol_name);
+		strobe
+	}
+	fn run_f(&mut self) {
+		self.st[self.pos] ^= self.pos_begin as u8;
+		self.st[self.pos + 1] ^= 0x04;
+		self.st[STROBE_R + 1] ^= 0x80;
+		keccak_f1600(&mut self.st);
+		self.pos = 0;
+		self.pos_begin = 0;
+	}
+	fn duplex(&mut self, data: &mut [u8], before: bool, after: bool, force_f: bool) {
+		for byte in data.iter_mut() {
+			if before {
+				*byte ^= self.st[self.pos];
+			}
+			self.st[self.pos] ^= *byte;
+			if after {
+				*byte = self.st[self.pos];
+			}
+			self.pos += 1;
+			if self.pos == STROBE_R {
+				self.run_f();
+			}
+		}
+		if force_f && self.pos != 0 {
+			self.run_f();
+		}
+	}
+	/* Transport operations are relative to the role of whoever sent first. */
+	fn operate(&mut self, flags: u8, data: &[u8]) -> Vec<u8> {
+		let mut marked: u8 = flags;
+		if flags & FLAG_T != 0 {
+			marked ^= *self.i0.get_or_insert(flags & FLAG_I);
+		}
+		let pos_begin = self.pos_begin as u8;
+		self.pos_begin = self.pos + 1;
+		self.duplex(&mut [pos_begin, marked], false, false, flags & FLAG_C != 0);
+		let after: bool = flags & (FLAG_C | FLAG_I | FLAG_T) == FLAG_C | FLAG_T;
+		let before: bool = flags & FLAG_C != 0 && !after;
+		let mut out: Vec<u8> = Vec::from(data);
+		self.duplex(&mut out[..], before, after, false);
+		out
+	}
+	fn ad(&mut self, data: &[u8]) {
+		self.operate(FLAG_A, data);
+	}
+	fn meta_ad(&mut self, data: &[u8]) {
+		self.operate(FLAG_M | FLAG_A, data);
+	}
+	fn prf(&mut self, length: usize) -> Vec<u8> {
+		self.operate(FLAG_I | FLAG_A | FLAG_C, &vec![0u8; length][..])
+	}
+	fn send_clr(&mut self, data: &[u8]) -> Vec<u8> {
+		self.operate(FLAG_A | FLAG_T, data)
+	}
+	fn recv_clr(&mut self, data: &[u8]) -> Vec<u8> {
+		self.operate(FLAG_I | FLAG_A | FLAG_T, data)
+	}
+	fn send_enc(&mut self, plaintext: &[u8]) -> Vec<u8> {
+		self.operate(FLAG_A | FLAG_C | FLAG_T, plaintext)
+	}
+	fn recv_enc(&mut self, ciphertext: &[u8]) -> Vec<u8> {
+		self.operate(FLAG_I | FLAG_A | FLAG_C | FLAG_T, ciphertext)
+	}
+	fn send_mac(&mut self, length: usize) -> Vec<u8> {
+		self.operate(FLAG_C | FLAG_T, &vec![0u8; length][..])
+	}
+	fn recv_mac(&mut self, mac: &[u8]) -> bool {
+		self.operate(FLAG_I | FLAG_C | FLAG_T, mac).iter().fold(0u8, |acc, b| acc | b) == 0
+	}
+	fn ratchet(&mut self, length: usize) {
+		self.operate(FLAG_C, &vec![0u8; length][..]);
+	}
+	fn send_aead(&mut self, plaintext: &[u8]) -> Vec<u8> {
+		let mut ciphertext: Vec<u8> = self.send_enc(plaintext);
+		ciphertext.extend(self.send_mac(MAC_LENGTH));
+		ciphertext
+	}
+	fn recv_aead(&mut self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+		if ciphertext.len() < MAC_LENGTH {
+			return None;
+		}
+		let (body, mac) = ciphertext.split_at(ciphertext.len() - MAC_LENGTH);
+		let plaintext: Vec<u8> = self.recv_enc(body);
+		if !self.recv_mac(mac) {
+			return None;
+		}
+		Some(plaintext)
+	}
+	fn clear(&mut self) {
+		self.st = [0u8; 200];
+		self.pos = 0;
+		self.pos_begin = 0;
+	}
+}
+
+/* ---------------------------------------------------------------- *
+ * DISCO SYMMETRIC STATE                                            *
+ * ---------------------------------------------------------------- */
+
+#[derive(Clone)]
+pub struct DiscoCipherState {
+	strobe: Option<Strobe>,
+}
+impl Cipher for DiscoCipherState {
+	fn new() -> DiscoCipherState {
+		DiscoCipherState { strobe: None }
+	}
+	fn write_message_regular(&mut self, payload: &[u8]) -> MessageBuffer {
+		let ciphertext: Vec<u8> = match &mut self.strobe {
+			Some(strobe) => strobe.send_aead(payload),
+			None => Vec::from(payload),
+		};
+		MessageBuffer {
+			ne: EMPTY_KEY,
+			ns: Vec::new(),
+			ciphertext,
+		}
+	}
+	/* A forged message must not advance the STROBE state, so a copy is used until the MAC checks out. */
+	fn read_message_regular(&mut self, message: &MessageBuffer) -> Option<Vec<u8>> {
+		match &mut self.strobe {
+			Some(strobe) => {
+				let mut next: Strobe = strobe.clone();
+				let plaintext: Vec<u8> = next.recv_aead(&message.ciphertext[..])?;
+				*strobe = next;
+				Some(plaintext)
+			}
+			None => Some(message.ciphertext.clone()),
+		}
+	}
+	fn write_message_with_ad(&mut self, ad: &[u8], payload: &[u8]) -> MessageBuffer {
+		if let Some(strobe) = &mut self.strobe {
+			strobe.ad(ad);
+		}
+		self.write_message_regular(payload)
+	}
+	fn read_message_with_ad(&mut self, ad: &[u8], message: &MessageBuffer) -> Option<Vec<u8>> {
+		match &mut self.strobe {
+			Some(strobe) => {
+				let mut next: Strobe = strobe.clone();
+				next.ad(ad);
+				let plaintext: Vec<u8> = next.recv_aead(&message.ciphertext[..])?;
+				*strobe = next;
+				Some(plaintext)
+			}
+			None => Some(message.ciphertext.clone()),
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct DiscoSymmetricState {
+	strobe: Strobe,
+	is_keyed: bool,
+}
+impl Symmetric for DiscoSymmetricState {
+	type Cipher = DiscoCipherState;
+	const CIPHER_SUITE: &'static str = "STROBEv1.0.2";
+	fn initialize_symmetric(protocol_name: &[u8]) -> DiscoSymmetricState {
+		DiscoSymmetricState {
+			strobe: Strobe::new(protocol_name),
+			is_keyed: false,
+		}
+	}
+	fn mix_key(&mut self, input_key_material: &[u8]) {
+		self.strobe.ad(input_key_material);
+		self.is_keyed = true;
+	}
+	fn mix_hash(&mut self, data: &[u8]) {
+		self.strobe.ad(data);
+	}
+	fn mix_key_and_hash(&mut self, input_key_material: &[u8]) {
+		self.strobe.ad(input_key_material);
+		self.is_keyed = true;
+	}
+	/* PRF modifies the STROBE state, so this is only called after split(). */
+	fn get_handshake_hash(&mut self) -> [u8; HASHLEN] {
+		let mut h = [0u8; HASHLEN];
+		h.copy_from_slice(&self.strobe.prf(HASHLEN)[..]);
+		h
+	}
+	/* Drawn from a copy, so that it does not affect the handshake hash. */
+	fn get_chaining_key(&self) -> [u8; HASHLEN] {
+		let mut strobe: Strobe = self.strobe.clone();
+		strobe.meta_ad(b"chaining key");
+		let mut ck = [0u8; HASHLEN];
+		ck.copy_from_slice(&strobe.prf(HASHLEN)[..]);
+		ck
+	}
+	fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Option<Vec<u8>> {
+		if self.is_keyed {
+			Some(self.strobe.send_aead(plaintext))
+		} else {
+			Some(self.strobe.send_clr(plaintext))
+		}
+	}
+	fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+		if self.is_keyed {
+			self.strobe.recv_aead(ciphertext)
+		} else {
+			Some(self.strobe.recv_clr(ciphertext))
+		}
+	}
+	fn split(&mut self) -> (DiscoCipherState, DiscoCipherState) {
+		let mut s1: Strobe = self.strobe.clone();
+		s1.meta_ad(b"initiator");
+		s1.ratchet(RATCHET_LENGTH);
+		let mut s2: Strobe = self.strobe.clone();
+		s2.meta_ad(b"responder");
+		s2.ratchet(RATCHET_LENGTH);
+		(DiscoCipherState { strobe: Some(s1) }, DiscoCipherState { strobe: Some(s2) })
+	}
+	fn clear(&mut self) {
+		self.strobe.clear();
+		self.is_keyed = false;
+	}
+}
+
+/* STROBE test vectors published with StrobeGo. */
+#[test]
+fn strobe_test() {
+	let message: &[u8] = b"hi how are you";
+	let mut strobe: Strobe = Strobe::new(b"custom string");
+	strobe.operate(FLAG_A | FLAG_C, b"010101");
+	strobe.ad(b"hello, how are you good sir?");
+	assert!(strobe.prf(16) == hex::decode("5ce86d0815c02a27d8bdd923f2cb0bd8").unwrap());
+	assert!(strobe.send_enc(message) == hex::decode("dc0c857f045d9941fed8acbfd024").unwrap());
+	assert!(strobe.recv_enc(message) == hex::decode("aad1a176d7add6ef904f1e1acf53").unwrap());
+	assert!(strobe.send_mac(16) == hex::decode("a05d94e152949460c67d648e91bc6d53").unwrap());
+	assert!(!strobe.recv_mac(message));
+	assert!(strobe.send_clr(message) == message && strobe.recv_clr(message) == message);
+	strobe.ratchet(32);
+	assert!(strobe.st[..] == hex::decode(concat!(
+		"0000000000000000000000000000000000000000000000000000000000000000",
+		"86bee3a828fc80f502b7f390dbca230c8ba1cea5cb93a958c443277dd0ab0625",
+		"745898f5ebabc9ec1800db4ea4fb7ad776a49b8aef66874397f0b8f6072df52b",
+		"2eeb9871046865aea090d503e68cd583fe65064fed4c6a9c8d6f190a9f506e80",
+		"a79d2b2a5ef56ede1fe2c575fae052505a4032221f796bd09cdc723245c572a6",
+		"eb500be48728fd0461b09d7813cbf384d479999a79168eadfd567bb20adc9c3c",
+		"b6b5e7ddf9c86d73",
+	)).unwrap()[..]);
+}
@@ -0,0 +1,184 @@
+/* ---------------------------------------------------------------- *
+ * RATCHET                                                          *
+ * ---------------------------------------------------------------- */
+
+/* Double ratchet transport seeded from the chaining key and handshake hash.
+ * The responder's handshake ephemeral serves as its first ratchet key, so it
+ * may send right away; the initiator performs the first DH ratchet step. */
+
+use crate::{
+	consts::{DHLEN, HASHLEN},
+	prims::{decrypt, encrypt, hkdf, hmac},
+	types::{Hash, Keypair, MessageBuffer, PublicKey},
+};
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashMap;
+
+/* Maximum number of message keys skipped within a single receiving chain. */
+const MAX_SKIP: u32 = 1000;
+const HEADER_LENGTH: usize = 8;
+
+fn kdf_rk(rk: &[u8; HASHLEN], dh_out: &[u8; DHLEN]) -> ([u8; HASHLEN], [u8; HASHLEN]) {
+	let mut out0 = [0u8; HASHLEN];
+	let mut out1 = [0u8; HASHLEN];
+	let mut out2 = [0u8; HASHLEN];
+	hkdf(&rk[..], &dh_out[..], 2, &mut out0[..], &mut out1[..], &mut out2[..]);
+	(out0, out1)
+}
+
+fn kdf_ck(ck: &[u8; HASHLEN]) -> ([u8; HASHLEN], [u8; HASHLEN]) {
+	let mut next = [0u8; HASHLEN];
+	let mut mk = [0u8; HASHLEN];
+	hmac(&ck[..], &[0x02], &mut next[..]);
+	hmac(&ck[..], &[0x01], &mut mk[..]);
+	(next, mk)
+}
+
+#[derive(Clone)]
+pub(crate) struct DoubleRatchet {
+	dhs: Keypair,
+	dhr: PublicKey,
+	rk: [u8; HASHLEN],
+	cks: [u8; HASHLEN],
+	ckr: Option<[u8; HASHLEN]>,
+	ns: u32,
+	nr: u32,
+	pn: u32,
+	h: [u8; HASHLEN],
+	skipped: HashMap<([u8; DHLEN], u32), [u8; HASHLEN]>,
+}
+
+impl DoubleRatchet {
+	pub(crate) fn new(initiator: bool, ck: &Hash, h: &Hash, e: Keypair, re: PublicKey) -> DoubleRatchet {
+		let (sk, responder_chain) = kdf_rk(ck.as_bytes(), h.as_bytes());
+		let mut ratchet = DoubleRatchet {
+			dhs: e,
+			dhr: PublicKey::empty(),
+			rk: sk,
+			cks: responder_chain,
+			ckr: None,
+			ns: 0,
+			nr: 0,
+			pn: 0,
+			h: *h.as_bytes(),
+			skipped: HashMap::new(),
+		};
+		if initiator {
+			ratchet.dhs = Keypair::new();
+			ratchet.dhr = re;
+			let (rk, cks) = kdf_rk(&sk, &ratchet.dhs.dh(&re.as_bytes()));
+			ratchet.rk = rk;
+			ratchet.cks = cks;
+			ratchet.ckr = Some(responder_chain);
+		}
+		ratchet
+	}
+	fn ad(&self, ne: &[u8; DHLEN], header: &[u8], extra: &[u8]) -> Vec<u8> {
+		let mut ad: Vec<u8> = Vec::from(&self.h[..]);
+		ad.extend_from_slice(&ne[..]);
+		ad.extend_from_slice(header);
+		ad.extend_from_slice(extra);
+		ad
+	}
+	fn skip_message_keys(&mut self, until: u32) -> Option<()> {
+		if until > self.nr.saturating_add(MAX_SKIP) {
+			return None;
+		}
+		if let Some(mut ckr) = self.ckr {
+			while self.nr < until {
+				let (next, mk) = kdf_ck(&ckr);
+				self.skipped.insert((self.dhr.as_bytes(), self.nr), mk);
+				ckr = next;
+				self.nr += 1;
+			}
+			self.ckr = Some(ckr);
+		}
+		Some(())
+	}
+	fn dh_ratchet(&mut self, remote: PublicKey) {
+		self.pn = self.ns;
+		self.ns = 0;
+		self.nr = 0;
+		self.dhr = remote;
+		let (rk, ckr) = kdf_rk(&self.rk, &self.dhs.dh(&self.dhr.as_bytes()));
+		self.dhs = Keypair::new();
+		let (rk, cks) = kdf_rk(&rk, &self.dhs.dh(&self.dhr.as_bytes()));
+		self.rk = rk;
+		self.ckr = Some(ckr);
+		self.cks = cks;
+	}
+	/* The ratchet public key travels in `ne`, the previous chain length and message number in `ns`. */
+	pub(crate) fn write_message(&mut self, payload: &[u8]) -> MessageBuffer {
+		self.write_message_with_ad(&[], payload)
+	}
+	pub(crate) fn write_message_with_ad(&mut self, extra: &[u8], payload: &[u8]) -> MessageBuffer {
+		let (next, mk) = kdf_ck(&self.cks);
+		self.cks = next;
+		let ne: [u8; DHLEN] = self.dhs.get_public_key().as_bytes();
+		let mut ns: Vec<u8> = vec![0u8; HEADER_LENGTH];
+		BigEndian::write_u32(&mut ns[..4], self.pn);
+		BigEndian::write_u32(&mut ns[4..], self.ns);
+		self.ns += 1;
+		let ciphertext: Vec<u8> = encrypt(mk, 0, &self.ad(&ne, &ns[..], extra)[..], payload);
+		MessageBuffer { ne, ns, ciphertext }
+	}
+	/* Works on a copy of the ratchet so that a forged message leaves no trace. */
+	pub(crate) fn read_message(&mut self, message: &MessageBuffer) -> Option<Vec<u8>> {
+		self.read_message_with_ad(&[], message)
+	}
+	pub(crate) fn read_message_with_ad(&mut self, extra: &[u8], message: &MessageBuffer) -> Option<Vec<u8>> {
+		if message.ns.len() != HEADER_LENGTH {
+			return None;
+		}
+		let pn: u32 = BigEndian::read_u32(&message.ns[..4]);
+		let n: u32 = BigEndian::read_u32(&message.ns[4..]);
+		let ad: Vec<u8> = self.ad(&message.ne, &message.ns[..], extra);
+		if let Some(mk) = self.skipped.get(&(message.ne, n)) {
+			let plaintext: Vec<u8> = decrypt(*mk, 0, &ad[..], &message.ciphertext[..])?;
+			self.skipped.remove(&(message.ne, n));
+			return Some(plaintext);
+		}
+		let mut next: DoubleRatchet = self.clone();
+		if message.ne != next.dhr.as_bytes() {
+			next.skip_message_keys(pn)?;
+			next.dh_ratchet(PublicKey::from_bytes(message.ne));
+		}
+		next.skip_message_keys(n)?;
+		let (ckr, mk) = kdf_ck(&next.ckr?);
+		next.ckr = Some(ckr);
+		next.nr += 1;
+		let plaintext: Vec<u8> = decrypt(mk, 0, &ad[..], &message.ciphertext[..])?;
+		*self = next;
+		Some(plaintext)
+	}
+}
+
+#[test]
+fn ratchet_test() {
+	let ck: Hash = Hash::new([0x11u8; HASHLEN]);
+	let h: Hash = Hash::new([0x22u8; HASHLEN]);
+	let e: Keypair = Keypair::new();
+	let mut initiator: DoubleRatchet = DoubleRatchet::new(true, &ck, &h, Keypair::new_empty(), e.get_public_key());
+	let mut responder: DoubleRatchet = DoubleRatchet::new(false, &ck, &h, e, PublicKey::empty());
+	let early: MessageBuffer = responder.write_message(b"early");
+	let first: MessageBuffer = initiator.write_message(b"one");
+	let second: MessageBuffer = initiator.write_message(b"two");
+	let third: MessageBuffer = initiator.write_message(b"three");
+	assert!(initiator.read_message(&early).unwrap() == b"early");
+	assert!(responder.read_message(&third).unwrap() == b"three");
+	assert!(responder.read_message(&first).unwrap() == b"one");
+	assert!(responder.read_message(&first).is_none());
+	let reply: MessageBuffer = responder.write_message(b"reply");
+	assert!(reply.ne != early.ne);
+	let mut forged: MessageBuffer = reply.clone();
+	forged.ciphertext[0] ^= 0x01;
+	assert!(initiator.read_message(&forged).is_none());
+	assert!(initiator.read_message(&reply).unwrap() == b"reply");
+	assert!(responder.read_message(&second).unwrap() == b"two");
+	let bound: MessageBuffer = initiator.write_message_with_ad(b"header", b"bound");
+	assert!(responder.read_message(&bound).is_none());
+	assert!(responder.read_message_with_ad(b"header", &bound).unwrap() == b"bound");
+	let mut far: MessageBuffer = initiator.write_message(b"far");
+	BigEndian::write_u32(&mut far.ns[4..], MAX_SKIP + 1);
+	assert!(responder.read_message(&far).is_none());
+}
@@ -147,6 +147,21 @@ const GORENDER = (pattern, parsedGo) => {
 	return go;
 };
 
+const RSEXTENSIONS = {
+	IKpsk2: ['wireguard'],
+	XK: ['bolt8', 'ntcp2'],
+	XX: ['libp2p']
+};
+
+const RSEXTENSIONDEPENDENCIES = {
+	bolt8: ['secp256k1 = ">=0.20.3, <0.30"'],
+	libp2p: ['secp256k1 = ">=0.28.0, <0.30"'],
+	ntcp2: ['siphasher = ">=1.0.1, <2"']
+};
+
+/* Extensions whose protocols hash with SHA-256 rather than BLAKE2s. */
+const RSEXTENSIONSSHA256 = ['bolt8', 'libp2p', 'ntcp2'];
+
 const RSRENDER = (pattern, parsedRs) => {
 	let rs = [
 		READFILE('rs/0params.rs'),
@@ -155,13 +170,38 @@ const RSRENDER = (pattern, parsedRs) => {
 		READFILE('rs/3utils.rs'),
 		READFILE('rs/4prims.rs'),
 		READFILE('rs/5state.rs'),
-		READFILE('rs/6processes.rs')
+		READFILE('rs/6processes.rs'),
+		READFILE('rs/7socket.rs'),
+		READFILE('rs/8elligator.rs'),
+		READFILE('rs/9disco.rs'),
+		READFILE('rs/10ratchet.rs'),
+		READFILE('rs/11rehandshake.rs'),
+		READFILE('rs/12mux.rs'),
+		READFILE('rs/13table.rs'),
+		READFILE('rs/14cookie.rs'),
+		READFILE('rs/15replay.rs'),
+		READFILE('rs/16knownpeers.rs'),
+		READFILE('rs/17cert.rs'),
+		READFILE('rs/18openssh.rs'),
+		READFILE('rs/19oracle.rs'),
+		READFILE('rs/20certpayload.rs'),
+		READFILE('rs/21sha256.rs')
 	];
+	const lines = (items) => {
+		return items.map((item) => `${item}\n`).join('');
+	};
 	rs[0] = rs[0].replace('/* $NOISE2RS_N$ */', `/*\n${pattern}\n*/`);
+	rs[5] = rs[5].replace('/* $NOISE2RS_J$ */\n', lines(parsedRs.j));
+	rs[5] = rs[5].replace('/* $NOISE2RS_F$ */\n', lines(parsedRs.f));
+	rs[5] = rs[5].replace('/* $NOISE2RS_H$ */\n', lines(parsedRs.h));
 	rs[5] = rs[5].replace('/* $NOISE2RS_I$ */', parsedRs.i);
 	rs[5] = rs[5].replace('/* $NOISE2RS_W$ */', parsedRs.w);
 	rs[5] = rs[5].replace('/* $NOISE2RS_R$ */', parsedRs.r);
+	rs[6] = rs[6].replace('/* $NOISE2RS_C$ */', parsedRs.c);
+	rs[6] = rs[6].replace('/* $NOISE2RS_M$ */\n', lines(parsedRs.m));
+	rs[6] = rs[6].replace('/* $NOISE2RS_D$ */\n', lines(parsedRs.d));
 	rs[6] = rs[6].replace('/* $NOISE2RS_P$ */', parsedRs.p);
+	rs[7] = rs[7].replace('/* $NOISE2RS_L$ */', parsedRs.l);
 	return rs;
 };
 
@@ -229,20 +269,40 @@ if (
 ) {
 	let pattern = READFILE(ARGV.pattern);
 	let json = NOISEPARSER.parse(pattern);
-	let psk = '';
-	json.messages.forEach((message, i) => {
-		if (message.tokens.indexOf('psk') >= 0) {
-			psk = ', Psk';
-		}
+	/* The double ratchet, re-handshake and stream multiplexer need keys in both directions. */
+	let twoWay = (json.messages.length > 1);
+	/* Certificates only travel with static keys sent in handshake messages. */
+	let sendsStatic = json.messages.some((message) => {
+		return message.tokens.indexOf('s') >= 0;
 	});
 	let parsedRs = NOISE2RS.parse(json);
 	let output = RSRENDER(pattern, parsedRs);
+	/* Cookies only spare the responder DH work when the first message carries es or ss. */
+	let cookie = json.messages[0].tokens.some((token) => {
+		return (token === 'es') || (token === 'ss');
+	});
+	if (!cookie) {
+		output[0] = output[0].replace('pub mod cookie;\n', '');
+	}
+	output[17] = output[17].replace('/* $NOISE2RS_O$ */\n', sendsStatic ? output[20] : '');
+	let sha256 = (RSEXTENSIONS[json.name] || []).some((extension) => {
+		return RSEXTENSIONSSHA256.indexOf(extension) >= 0;
+	});
+	output[4] = output[4].replace('/* $NOISE2RS_Q$ */\n', sha256 ? `\n${output[21]}` : '');
+	if (!twoWay) {
+		output[0] = output[0]
+			.replace('pub(crate) mod ratchet;\n', '')
+			.replace('pub(crate) mod rehandshake;\n', '')
+			.replace('pub mod mux;\n', '');
+	}
 	let cargo = READFILE('rs/Cargo.toml')
 		.replace("$NOISE2RS_N$", json.name.toLowerCase());
 	let testGen = NOISE2RSTESTGEN.generate(json, output);
 	let test = READFILE('rs/test.rs')
-		.replace("$NOISE2RS_S$", psk)
-		.replace("$NOISE2RS_T$", testGen)
+		.replace("$NOISE2RS_S$", testGen[1])
+		.replace("$NOISE2RS_U$", testGen[2])
+		.replace("$NOISE2RS_T$", testGen[0])
+		.replace("$NOISE2RS_V$", testGen[3])
 		.replace(/\$NOISE2RS_N\$/g, json.name.toLowerCase());
 	if (!FS.existsSync(`../implementations/rs/${json.name}`)) {
 		FS.mkdirSync(`../implementations/rs/${json.name}`);
@@ -256,8 +316,41 @@ if (
 	WRITEFILE(`../implementations/rs/${json.name}/src/prims.rs`, output[4]);
 	WRITEFILE(`../implementations/rs/${json.name}/src/state.rs`, output[5]);
 	WRITEFILE(`../implementations/rs/${json.name}/src/noisesession.rs`, output[6]);
+	WRITEFILE(`../implementations/rs/${json.name}/src/noisesocket.rs`, output[7]);
+	WRITEFILE(`../implementations/rs/${json.name}/src/elligator.rs`, output[8]);
+	WRITEFILE(`../implementations/rs/${json.name}/src/disco.rs`, output[9]);
+	if (twoWay) {
+		WRITEFILE(`../implementations/rs/${json.name}/src/ratchet.rs`, output[10]);
+		WRITEFILE(`../implementations/rs/${json.name}/src/rehandshake.rs`, output[11]);
+		WRITEFILE(`../implementations/rs/${json.name}/src/mux.rs`, output[12]);
+	}
+	WRITEFILE(`../implementations/rs/${json.name}/src/table.rs`, output[13]);
+	if (cookie) {
+		WRITEFILE(`../implementations/rs/${json.name}/src/cookie.rs`, output[14]);
+	}
+	WRITEFILE(`../implementations/rs/${json.name}/src/replay.rs`, output[15]);
+	WRITEFILE(`../implementations/rs/${json.name}/src/knownpeers.rs`, output[16]);
+	WRITEFILE(`../implementations/rs/${json.name}/src/cert.rs`, output[17]);
+	WRITEFILE(`../implementations/rs/${json.name}/src/openssh.rs`, output[18]);
+	WRITEFILE(`../implementations/rs/${json.name}/src/oracle.rs`, output[19]);
 	WRITEFILE(`../implementations/rs/${json.name}/Cargo.toml`, cargo);
 	WRITEFILE(`../implementations/rs/${json.name}/tests/handshake.rs`, test);
+	if (RSEXTENSIONS.hasOwnProperty(json.name)) {
+		RSEXTENSIONS[json.name].forEach((extension) => {
+			output[0] = `${output[0]}\npub mod ${extension};`;
+			let extensionTest = READFILE(`rs/ext/${extension}_test.rs`)
+				.replace(/\$NOISE2RS_N\$/g, json.name.toLowerCase());
+			WRITEFILE(`../implementations/rs/${json.name}/src/${extension}.rs`, READFILE(`rs/ext/${extension}.rs`));
+			WRITEFILE(`../implementations/rs/${json.name}/tests/${extension}.rs`, extensionTest);
+			if (RSEXTENSIONDEPENDENCIES.hasOwnProperty(extension)) {
+				RSEXTENSIONDEPENDENCIES[extension].forEach((dependency) => {
+					cargo = `${cargo}\n${dependency}`;
+				});
+			}
+		});
+		WRITEFILE(`../implementations/rs/${json.name}/src/lib.rs`, output[0]);
+		WRITEFILE(`../implementations/rs/${json.name}/Cargo.toml`, cargo);
+	}
 	process.exit();
 }
 
@@ -9,8 +9,7 @@ const NOISE2RS = {
 	};
 
 	const util = {
-		emptyKey: 'EMPTY_KEY',
-		emptyKeyPair: 'Keypair::new_empty()',
+		emptyKeyPair: 'K::new_empty()',
 		abc: ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i'],
 	};
 
@@ -129,20 +128,24 @@ const NOISE2RS = {
 	const initializeFun = (pattern, initiator, suffix) => {
 		let preMessageTokenParsers = {
 			send: {
-				e: `ss.mix_hash(&self.e.get_public_key().as_bytes());`,
-				s: `ss.mix_hash(&s.get_public_key().as_bytes()[..]);`,
-				'e, s': `ss.mix_hash(&self.e.get_public_key().as_bytes()); ss.mix_hash(&s.get_public_key().as_bytes()[..]);`
+				e: `ss.mix_hash(self.e.get_public_key().as_bytes().as_ref());`,
+				s: `ss.mix_hash(s.get_public_key().as_bytes().as_ref());`,
+				'e, s': `ss.mix_hash(self.e.get_public_key().as_bytes().as_ref()); ss.mix_hash(s.get_public_key().as_bytes().as_ref());`
 			},
 			recv: {
-				e: `ss.mix_hash(&self.re.as_bytes()[..DHLEN]);`,
-				s: `ss.mix_hash(&rs.as_bytes()[..]);`,
-				'e, s': `ss.mix_hash(&self.re.as_bytes()[..DHLEN]); ss.mix_hash(&rs.as_bytes()[..]);`
+				e: `ss.mix_hash(self.re.as_bytes().as_ref());`,
+				s: `ss.mix_hash(rs.as_bytes().as_ref());`,
+				'e, s': `ss.mix_hash(self.re.as_bytes().as_ref()); ss.mix_hash(rs.as_bytes().as_ref());`
 			}
 		};
+		let defaultFun = [
+			`\tpub(crate) fn initialize_${suffix}(prologue: &[u8], s: K, rs: K::PublicKey, psk: Psk) -> HandshakeState<S, K> {`,
+			`let protocol_name = format!("Noise_${pattern.name}_{}_{}", K::DH_NAME, S::CIPHER_SUITE);`,
+			`HandshakeState::initialize_${suffix}_with_name(protocol_name.as_bytes(), prologue, s, rs, psk)`
+		];
 		let initFun = [
-			`\tpub(crate) fn initialize_${suffix}(prologue: &[u8], s: Keypair, rs: PublicKey, psk: Psk) -> HandshakeState {`,
-			`let protocol_name = b"Noise_${pattern.name}_25519_ChaChaPoly_BLAKE2s";`,
-			`let mut ss: SymmetricState = SymmetricState::initialize_symmetric(&protocol_name[..]);`,
+			`\tpub(crate) fn initialize_${suffix}_with_name(protocol_name: &[u8], prologue: &[u8], s: K, rs: K::PublicKey, psk: Psk) -> HandshakeState<S, K> {`,
+			`let mut ss: S = S::initialize_symmetric(protocol_name);`,
 			`ss.mix_hash(prologue);`
 		];
 		pattern.preMessages.forEach((preMessage) => {
@@ -152,8 +155,19 @@ const NOISE2RS = {
 			}
 			initFun.push(preMessageTokenParsers[dir][preMessage.tokens]);
 		});
-		initFun.push(`HandshakeState{ss, s, e: ${util.emptyKeyPair}, rs, re: PublicKey::empty(), psk}`);
-		return `${initFun.join('\n\t\t')}\n\t}`;
+		let fields = [
+			...(messagesStatic(pattern) ? ['remote_certificate: None', 'certificate: None', 'anchors: Vec::new()'] : []),
+			...((staticKeySelectable(pattern) || staticKeyRotatable(pattern)) ? ['prologue: Vec::from(prologue)'] : []),
+			...(staticKeySelectable(pattern) ? ['selector: None'] : []),
+			...((messagesPsk(pattern) >= 0) ? ['psk_resolver: None'] : [])
+		].map((field) => {
+			return `${field}, `;
+		}).join('');
+		initFun.push(`HandshakeState{ss, s, e: ${util.emptyKeyPair}, rs, re: K::PublicKey::empty(), psk, elligator: false, reject_low_order: true, verifier: None, ${fields}hint: Vec::new(), error: None}`);
+		return [
+			`${defaultFun.join('\n\t\t')}\n\t}`,
+			`\t/* For protocols that run this pattern under a protocol name of their own. */\n${initFun.join('\n\t\t')}\n\t}`
+		].join('\n\n');
 	};
 
 	const initializeFuns = (pattern) => {
@@ -165,36 +179,37 @@ const NOISE2RS = {
 
 	const writeMessageFun = (message, hasPsk, initiator, isFinal, suffix) => {
 		let ePskFill = hasPsk ?
-			`self.ss.mix_key(&self.e.get_public_key().as_bytes());` : `/* No PSK, so skipping mixKey */`;
+			`self.ss.mix_key(self.e.get_public_key().as_bytes().as_ref());` : `/* No PSK, so skipping mixKey */`;
 		let esInitiatorFill = initiator ?
-			`self.ss.mix_key(&self.e.dh(&self.rs.as_bytes()));` : `self.ss.mix_key(&self.s.dh(&self.re.as_bytes()));`;
+			`self.ss.mix_key(&self.e.dh(&self.rs.as_bytes()));` : `self.mix_static_dh(&self.re.as_bytes())?;`;
 		let seInitiatorFill = initiator ?
-			`self.ss.mix_key(&self.s.dh(&self.re.as_bytes()));` : `self.ss.mix_key(&self.e.dh(&self.rs.as_bytes()));`;
+			`self.mix_static_dh(&self.re.as_bytes())?;` : `self.ss.mix_key(&self.e.dh(&self.rs.as_bytes()));`;
 		let finalFill = isFinal ? [
-			`let h: Hash = Hash::new(from_slice_hashlen(self.ss.h.as_bytes()));`,
+			`let ck: Hash = Hash::new(self.ss.get_chaining_key());`,
 			`let (cs1, cs2) = self.ss.split();`,
+			`let h: Hash = Hash::new(self.ss.get_handshake_hash());`,
 			`self.ss.clear();`,
 			`let messagebuffer = MessageBuffer { ne, ns, ciphertext };`,
-			`(h, messagebuffer, cs1, cs2)`
+			`Some((h, messagebuffer, cs1, cs2, ck))`
 		] : [
-			`MessageBuffer { ne, ns, ciphertext }`
+			`Some(MessageBuffer { ne, ns, ciphertext })`
 		];
 		let isBeyondFinal = (message.tokens.length === 0);
 		if (isBeyondFinal) {
 			return ``;
 		}
-		let writeFunDeclaration = `\tpub(crate) fn write_message_${suffix}(&mut self, payload: &[u8]) -> (${isFinal? `(Hash, MessageBuffer, CipherState, CipherState)` : `MessageBuffer`}) {`;
+		let writeFunDeclaration = `\tpub(crate) fn write_message_${suffix}(&mut self, payload: &[u8]) -> (${isFinal? `Option<(Hash, MessageBuffer<PublicKeyBytes<K>>, S::Cipher, S::Cipher, Hash)>` : `Option<MessageBuffer<PublicKeyBytes<K>>>`}) {`;
 		let messageTokenParsers = {
 			e: [
 				`if self.e.is_empty() {`,
-				`\tself.e = Keypair::new();`,
+				`\tself.e = if self.elligator { K::new_elligator() } else { K::new() };`,
 				`}`,
-				`ne = self.e.get_public_key().as_bytes();`,
-				`self.ss.mix_hash(&ne[..]);`,
+				`ne = self.encode_ephemeral()?;`,
+				`self.ss.mix_hash(self.e.get_public_key().as_bytes().as_ref());`,
 				ePskFill
 			].join(`\n\t\t`),
 			s: [
-				`if let Some(x) = self.ss.encrypt_and_hash(&self.s.get_public_key().as_bytes()[..]) {`,
+				`if let Some(x) = self.ss.encrypt_and_hash(self.s.get_public_key().as_bytes().as_ref()) {`,
 				`\tns.clone_from(&x);`,
 				`}`
 			].join(`\n\t\t`),
@@ -208,23 +223,29 @@ const NOISE2RS = {
 				seInitiatorFill
 			].join(`\n\t\t`),
 			ss: [
-				`self.ss.mix_key(&self.s.dh(&self.rs.as_bytes())[..]);`
+				`self.mix_static_dh(&self.rs.as_bytes())?;`
 			].join(`\n\t\t`),
 			psk: [
+				`self.resolve_psk()?;`,
 				`self.ss.mix_key_and_hash(&self.psk.as_bytes());`
 			].join(`\n\t\t`),
 		};
 		let writeFun = [
 			writeFunDeclaration,
+			`if self.error.is_some() {`,
+			`\treturn None;`,
+			`}`,
 			`let ${(message.tokens.indexOf('s') >= 0)? 'mut ': ''}ns: Vec<u8> = Vec::new();`,
-			`let ne: [u8; DHLEN]${(message.tokens.indexOf('e') >= 0)? '': ` = ${util.emptyKey}`};`
+			`let ne: PublicKeyBytes<K>${(message.tokens.indexOf('e') >= 0)? '': ' = K::PublicKey::empty().as_bytes()'};`
 		];
 		message.tokens.forEach((token) => {
 			writeFun.push(messageTokenParsers[token]);
 		});
+		let payload = (message.tokens.indexOf('s') >= 0) ?
+			`&self.attach_certificate(payload)[..]` : `payload`;
 		writeFun = writeFun.concat([
 			`let mut ciphertext: Vec<u8> = Vec::new();`,
-			`if let Some(x) = self.ss.encrypt_and_hash(payload) {`,
+			`if let Some(x) = self.ss.encrypt_and_hash(${payload}) {`,
 			`\tciphertext.clone_from(&x);`,
 			`}`
 		]);
@@ -250,18 +271,50 @@ const NOISE2RS = {
 		return writeFuns;
 	};
 
-	const readMessageFun = (message, hasPsk, initiator, isFinal, suffix) => {
+	const staticKeySelectable = (pattern) => {
+		return !preMessagesRecvStatic(pattern) && (messagesRecvStatic(pattern) >= 0);
+	};
+
+	const staticKeyRotatable = (pattern) => {
+		return preMessagesRecvStatic(pattern) && pattern.messages[0].tokens.some((token) => {
+			return (token === 'es') || (token === 'ss');
+		});
+	};
+
+	const messagesStatic = (pattern) => {
+		return pattern.messages.some((message) => {
+			return message.tokens.indexOf('s') >= 0;
+		});
+	};
+
+	const messagesStaticDh = (pattern) => {
+		return pattern.messages.some((message) => {
+			return message.tokens.some((token) => {
+				return ['es', 'se', 'ss'].indexOf(token) >= 0;
+			});
+		});
+	};
+
+	const hasStatics = (pattern) => {
+		return (
+			(preMessagesSendStatic(pattern) || (messagesSendStatic(pattern) >= 0)) &&
+			(preMessagesRecvStatic(pattern) || (messagesRecvStatic(pattern) >= 0))
+		);
+	};
+
+	const readMessageFun = (message, hasPsk, initiator, isFinal, suffix, selectStatic, keepHint, resolveAhead) => {
 		let ePskFill = hasPsk ?
-			`self.ss.mix_key(&self.re.as_bytes());` : `/* No PSK, so skipping mixKey */`;
+			`self.ss.mix_key(self.re.as_bytes().as_ref());` : `/* No PSK, so skipping mixKey */`;
 		let esInitiatorFill = initiator ?
-			`self.ss.mix_key(&self.e.dh(&self.rs.as_bytes()));` : `self.ss.mix_key(&self.s.dh(&self.re.as_bytes()));`;
+			`self.ss.mix_key(&self.e.dh(&self.rs.as_bytes()));` : `self.mix_static_dh(&self.re.as_bytes())?;`;
 		let seInitiatorFill = initiator ?
-			`self.ss.mix_key(&self.s.dh(&self.re.as_bytes()));` : `self.ss.mix_key(&self.e.dh(&self.rs.as_bytes()));`;
+			`self.mix_static_dh(&self.re.as_bytes())?;` : `self.ss.mix_key(&self.e.dh(&self.rs.as_bytes()));`;
 		let finalFill = isFinal ? [
-			`\tlet h: Hash = Hash::new(from_slice_hashlen(self.ss.h.as_bytes()));`,
+			`\tlet ck: Hash = Hash::new(self.ss.get_chaining_key());`,
 			`\tlet (cs1, cs2) = self.ss.split();`,
+			`\tlet h: Hash = Hash::new(self.ss.get_handshake_hash());`,
 			`\tself.ss.clear();`,
-			`\treturn Some((h, plaintext, cs1, cs2));`
+			`\treturn Some((h, plaintext, cs1, cs2, ck));`
 		] : [
 			`\treturn Some(plaintext);`
 		];
@@ -269,19 +322,18 @@ const NOISE2RS = {
 		if (isBeyondFinal) {
 			return ``;
 		}
-		let readFunDeclaration = `\tpub(crate) fn read_message_${suffix}(&mut self, message: &mut MessageBuffer) -> (${isFinal? ` Option<(Hash, Vec<u8>, CipherState, CipherState)>` : `Option<Vec<u8>>`}) {`;
+		let readFunDeclaration = `\tpub(crate) fn read_message_${suffix}(&mut self, message: &mut MessageBuffer<PublicKeyBytes<K>>) -> (${isFinal? ` Option<(Hash, Vec<u8>, S::Cipher, S::Cipher, Hash)>` : `Option<Vec<u8>>`}) {`;
 		let messageTokenParsers = {
 			e: [
-				`self.re = PublicKey::from_bytes(message.ne);`,
-				`self.ss.mix_hash(&self.re.as_bytes()[..DHLEN]);`,
+				`self.re = K::PublicKey::from_slice(self.decode_ephemeral(&message.ne).as_ref())?;`,
+				`self.check_remote_key(self.re, true)?;`,
+				`self.ss.mix_hash(self.re.as_bytes().as_ref());`,
 				ePskFill
 			].join(`\n\t\t`),
 			s: [
 				`if let Some(x) = self.ss.decrypt_and_hash(&message.ns) {`,
-				`\tif x.len() != DHLEN {`,
-				`\t\treturn None`,
-				`\t}`,
-				`\tself.rs = PublicKey::from_bytes(from_slice_hashlen(&x[..]));`,
+				`\tself.rs = K::PublicKey::from_slice(&x[..])?;`,
+				`\tself.check_remote_key(self.rs, false)?;`,
 				`} else { return None }`,
 			].join(`\n\t\t`),
 			ee: [
@@ -294,9 +346,10 @@ const NOISE2RS = {
 				seInitiatorFill
 			].join(`\n\t\t`),
 			ss: [
-				`self.ss.mix_key(&self.s.dh(&self.rs.as_bytes()));`
+				`self.mix_static_dh(&self.rs.as_bytes())?;`
 			].join(`\n\t\t`),
 			psk: [
+				`self.resolve_psk()?;`,
 				`self.ss.mix_key_and_hash(&self.psk.as_bytes());`
 			].join(`\n\t\t`)
 		};
@@ -308,6 +361,10 @@ const NOISE2RS = {
 		});
 		readFun = readFun.concat([
 			`if let Some(plaintext) = self.ss.decrypt_and_hash(&message.ciphertext) {`,
+			...((message.tokens.indexOf('s') >= 0) ? [`\tlet plaintext: Vec<u8> = self.check_certificate(plaintext)?;`] : []),
+			...(selectStatic ? [`\tself.select_static_key(&plaintext[..])?;`] : []),
+			...(keepHint ? [`\tself.hint.clone_from(&plaintext);`] : []),
+			...(resolveAhead ? [`\tself.resolve_psk()?;`] : []),
 			`${finalFill.join('\n\t\t')}`,
 			`}`,
 			`None`
@@ -324,7 +381,14 @@ const NOISE2RS = {
 			let initiator = (message.dir === 'recv');
 			let isFinal = (i === finalKex);
 			readFuns.push(
-				readMessageFun(message, hasPsk, initiator, isFinal, util.abc[i])
+				readMessageFun(
+					message, hasPsk, initiator, isFinal, util.abc[i],
+					(i === 0) && staticKeySelectable(pattern),
+					pattern.messages.slice(i + 1).some((later) => {
+						return later.tokens.indexOf('psk') >= 0;
+					}),
+					(i + 1 < pattern.messages.length) && (pattern.messages[i + 1].tokens.indexOf('psk') >= 0)
+				)
 			);
 			if (i > finalKex) {
 				break;
@@ -333,6 +397,170 @@ const NOISE2RS = {
 		return readFuns;
 	};
 
+	/* HandshakeState items that only some patterns use: certificates travel with
+	 * static keys sent in handshake messages, and the rest follow the features of
+	 * NoiseSession that the pattern supports. */
+	const typeImports = (types) => {
+		types = types.slice().sort();
+		return [
+			...((types.length === 1) ? [`use crate::types::${types[0]};`] : []),
+			...((types.length > 1) ? [`use crate::types::{${types.join(', ')}};`] : [])
+		];
+	};
+
+	const stateImports = (pattern) => {
+		return [
+			...(messagesStatic(pattern) ? [`use crate::cert::{self, Certificate, AUTHORITY_KEY_LENGTH};`] : []),
+			...typeImports([
+				...(staticKeySelectable(pattern) ? ['StaticKeySelector'] : []),
+				...((messagesPsk(pattern) >= 0) ? ['PskResolver'] : [])
+			])
+		];
+	};
+
+	const stateFields = (pattern) => {
+		return [
+			...(messagesStatic(pattern) ? [
+				`\tremote_certificate: Option<Certificate>,`,
+				`\tcertificate: Option<Certificate>,`,
+				`\tanchors: Vec<[u8; AUTHORITY_KEY_LENGTH]>,`
+			] : []),
+			...((staticKeySelectable(pattern) || staticKeyRotatable(pattern)) ? [`\tprologue: Vec<u8>,`] : []),
+			...(staticKeySelectable(pattern) ? [`\tselector: Option<Arc<dyn StaticKeySelector<K>>>,`] : []),
+			...((messagesPsk(pattern) >= 0) ? [`\tpsk_resolver: Option<Arc<dyn PskResolver<K::PublicKey>>>,`] : [])
+		];
+	};
+
+	const stateHelperFuns = (pattern, isOneWayPattern) => {
+		return [
+			...(messagesStatic(pattern) ? [
+				`\tpub(crate) fn get_remote_certificate(&self) -> Option<Certificate> {`,
+				`\t\tself.remote_certificate.clone()`,
+				`\t}`,
+				`\tpub(crate) fn set_certificate(&mut self, certificate: Certificate) {`,
+				`\t\tself.certificate = Some(certificate);`,
+				`\t}`,
+				`\tpub(crate) fn set_trust_anchors(&mut self, anchors: Vec<[u8; AUTHORITY_KEY_LENGTH]>) {`,
+				`\t\tself.anchors = anchors;`,
+				`\t}`,
+				`\t/* Called by the write functions of messages carrying our static key. */`,
+				`\tfn attach_certificate(&self, payload: &[u8]) -> Vec<u8> {`,
+				`\t\tmatch &self.certificate {`,
+				`\t\t\tSome(certificate) => cert::attach(certificate, payload),`,
+				`\t\t\tNone => Vec::from(payload),`,
+				`\t\t}`,
+				`\t}`,
+				`\t/* Called by the read functions of messages carrying the peer's static key,`,
+				`\t * before the handshake may complete. */`,
+				`\tfn check_certificate(&mut self, plaintext: Vec<u8>) -> Option<Vec<u8>> {`,
+				`\t\tif self.anchors.is_empty() {`,
+				`\t\t\treturn Some(plaintext);`,
+				`\t\t}`,
+				`\t\tmatch cert::detach(&plaintext[..]) {`,
+				`\t\t\tSome((certificate, payload))`,
+				`\t\t\t\tif certificate.verify(&self.anchors[..], cert::unix_time())`,
+				`\t\t\t\t\t&& certificate.get_static_key().as_bytes()[..] == *self.rs.as_bytes().as_ref() =>`,
+				`\t\t\t{`,
+				`\t\t\t\tself.remote_certificate = Some(certificate);`,
+				`\t\t\t\tSome(payload)`,
+				`\t\t\t}`,
+				`\t\t\t_ => {`,
+				`\t\t\t\tself.error = Some(HandshakeError::CertificateRejected);`,
+				`\t\t\t\tNone`,
+				`\t\t\t}`,
+				`\t\t}`,
+				`\t}`
+			] : []),
+			...(staticKeySelectable(pattern) ? [
+				`\tpub(crate) fn set_static_key_selector(&mut self, selector: Arc<dyn StaticKeySelector<K>>) {`,
+				`\t\tself.selector = Some(selector);`,
+				`\t}`,
+				`\t/* Called by the responder's first read function, once the payload is authenticated. */`,
+				`\tfn select_static_key(&mut self, payload: &[u8]) -> Option<()> {`,
+				`\t\tlet selector: Arc<dyn StaticKeySelector<K>> = match &self.selector {`,
+				`\t\t\tSome(selector) => Arc::clone(selector),`,
+				`\t\t\tNone => return Some(()),`,
+				`\t\t};`,
+				`\t\tmatch selector.select(&self.prologue[..], payload) {`,
+				`\t\t\tSome(s) => {`,
+				`\t\t\t\tself.s.clear();`,
+				`\t\t\t\tself.s = s;`,
+				`\t\t\t\tSome(())`,
+				`\t\t\t}`,
+				`\t\t\tNone => {`,
+				`\t\t\t\tself.error = Some(HandshakeError::NoStaticKey);`,
+				`\t\t\t\tNone`,
+				`\t\t\t}`,
+				`\t\t}`,
+				`\t}`
+			] : []),
+			...((messagesPsk(pattern) >= 0) ? [
+				`\tpub(crate) fn set_psk_resolver(&mut self, resolver: Arc<dyn PskResolver<K::PublicKey>>) {`,
+				`\t\tself.psk_resolver = Some(resolver);`,
+				`\t}`,
+				`\t/* Called before every psk token, and by read functions followed by a message`,
+				`\t * of ours with one; the resolver is consulted only once per handshake. */`,
+				`\tfn resolve_psk(&mut self) -> Option<()> {`,
+				`\t\tlet resolver: Arc<dyn PskResolver<K::PublicKey>> = match self.psk_resolver.take() {`,
+				`\t\t\tSome(resolver) => resolver,`,
+				`\t\t\tNone => return Some(()),`,
+				`\t\t};`,
+				`\t\tlet remote_static: Option<&K::PublicKey> = if self.rs.is_empty() { None } else { Some(&self.rs) };`,
+				`\t\tmatch resolver.resolve(remote_static, &self.hint[..]) {`,
+				`\t\t\tSome(psk) => {`,
+				`\t\t\t\tself.psk.clear();`,
+				`\t\t\t\tself.psk = psk;`,
+				`\t\t\t\tSome(())`,
+				`\t\t\t}`,
+				`\t\t\tNone => {`,
+				`\t\t\t\tself.error = Some(HandshakeError::PskNotFound);`,
+				`\t\t\t\tNone`,
+				`\t\t\t}`,
+				`\t\t}`,
+				`\t}`
+			] : []),
+			...(staticKeyRotatable(pattern) ? [
+				`\t/* A copy of this responder state, not yet used, for another static keypair. */`,
+				`\tpub(crate) fn with_static_keypair(&self, s: K) -> HandshakeState<S, K> {`,
+				`\t\tlet fresh: HandshakeState<S, K> = HandshakeState::initialize_responder(&self.prologue[..], s, self.rs, self.psk.clone());`,
+				`\t\tlet mut hs: HandshakeState<S, K> = self.clone();`,
+				`\t\ths.ss = fresh.ss;`,
+				`\t\ths.s = fresh.s;`,
+				`\t\ths`,
+				`\t}`
+			] : []),
+			...(messagesStaticDh(pattern) ? [
+				`\t/* Called by the read and write functions for DH with our static key, which`,
+				`\t * fails the message rather than the process if the key is held by an oracle. */`,
+				`\tfn mix_static_dh(&mut self, public_key: &PublicKeyBytes<K>) -> Option<()> {`,
+				`\t\tmatch self.s.try_dh(public_key) {`,
+				`\t\t\tSome(shared) => {`,
+				`\t\t\t\tself.ss.mix_key(&shared);`,
+				`\t\t\t\tSome(())`,
+				`\t\t\t}`,
+				`\t\t\tNone => {`,
+				`\t\t\t\tself.error = Some(HandshakeError::OracleFailed);`,
+				`\t\t\t\tNone`,
+				`\t\t\t}`,
+				`\t\t}`,
+				`\t}`
+			] : []),
+			...(!isOneWayPattern && hasStatics(pattern) ? [
+				`\tpub(crate) fn get_remote_static_public_key(&self) -> K::PublicKey {`,
+				`\t\tself.rs`,
+				`\t}`
+			] : []),
+			...(!isOneWayPattern ? [
+				`\tpub(crate) fn get_ephemeral_keypair(&self) -> K {`,
+				`\t\tself.e.clone()`,
+				`\t}`,
+				`\tpub(crate) fn get_remote_ephemeral_public_key(&self) -> K::PublicKey {`,
+				`\t\tself.re`,
+				`\t}`
+			] : [])
+		];
+	};
+
 	const events = (pattern) => {
 		return [];
 	};
@@ -357,102 +585,197 @@ const NOISE2RS = {
 		return [];
 	};
 
+	/* Imports for the NoiseSession items that only some patterns get. */
+	const sessionImports = (pattern, isOneWayPattern) => {
+		return [
+			...(messagesStatic(pattern) ? [`use crate::cert::{Certificate, AUTHORITY_KEY_LENGTH};`] : []),
+			...(isOneWayPattern ? [] : [
+				`use crate::{`,
+				`    ratchet::DoubleRatchet,`,
+				`    rehandshake::{Rehandshake, REHANDSHAKE_AD},`,
+				`};`
+			]),
+			...typeImports([
+				...(staticKeySelectable(pattern) ? ['StaticKeySelector'] : []),
+				...((messagesPsk(pattern) >= 0) ? ['PskResolver'] : [])
+			])
+		];
+	};
+
 	const processFuns = (pattern, isOneWayPattern) => {
 		let hasPsk = messagesPsk(pattern) >= 0;
 		let finalKex = finalKeyExchangeMessage(pattern);
+		let twoWayFields = isOneWayPattern ? [] : [
+			`\t\t\tdr: false,`,
+			`\t\t\tratchet: None,`,
+			`\t\t\trh: None,`,
+			`\t\t\trehandshake_read: false,`
+		];
 		let initSession = [
-			`\tpub fn init_session(initiator: bool, prologue: Message, s: Keypair, rs: PublicKey${hasPsk? ', psk: Psk' : ''}) -> NoiseSession {`,
+			`\tfn init(initiator: bool, prologue: Message, s: Keypair, rs: PublicKey${hasPsk? ', psk: Psk' : ''}) -> NoiseSession<S> {`,
 			`\tif initiator {`,
 			`\t\tNoiseSession{`,
 			`\t\t\ths: HandshakeState::initialize_initiator(prologue.as_bytes(), s, rs, ${hasPsk? 'psk' : 'Psk::new()'}),`,
 			`\t\t\tmc: 0,`,
 			`\t\t\ti: initiator,`,
-			`\t\t\tcs1: CipherState::new(),`,
-			`\t\t\tcs2: CipherState::new(),`,
+			`\t\t\tcs1: S::Cipher::new(),`,
+			`\t\t\tcs2: S::Cipher::new(),`,
 			`\t\t\th: Hash::empty(),`,
+			`\t\t\texporter: Hash::empty(),`,
+			...twoWayFields,
+			`\t\t\tprevious_statics: Vec::new(),`,
+			`\t\t\tstatic_key_index: None,`,
 			`\t\t}`,
 			`\t} else {`,
 			`\t\tNoiseSession {`,
 			`\t\t\ths: HandshakeState::initialize_responder(prologue.as_bytes(), s, rs, ${hasPsk? 'psk' : 'Psk::new()'}),`,
 			`\t\t\tmc: 0,`,
 			`\t\t\ti: initiator,`,
-			`\t\t\tcs1: CipherState::new(),`,
-			`\t\t\tcs2: CipherState::new(),`,
+			`\t\t\tcs1: S::Cipher::new(),`,
+			`\t\t\tcs2: S::Cipher::new(),`,
 			`\t\t\th: Hash::empty(),`,
+			`\t\t\texporter: Hash::empty(),`,
+			...twoWayFields,
+			`\t\t\tprevious_statics: Vec::new(),`,
+			`\t\t\tstatic_key_index: None,`,
 			`\t\t}`,
 			`\t}`,
 			`}`
 		];
+		let transportFill = isOneWayPattern ? [] : [
+			`if self.dr {`,
+			`\tself.ratchet = Some(DoubleRatchet::new(`,
+			`\t\tself.i,`,
+			`\t\t&temp.4,`,
+			`\t\t&self.h,`,
+			`\t\tself.hs.get_ephemeral_keypair(),`,
+			`\t\tself.hs.get_remote_ephemeral_public_key(),`,
+			`\t));`,
+			`}`,
+			`self.rh = Some(Rehandshake::new(`,
+			`\tself.i,`,
+			`\t${hasStatics(pattern)? 'self.hs.get_static_keypair()' : 'Keypair::new_empty()'},`,
+			`\t${hasStatics(pattern)? 'self.hs.get_remote_static_public_key()' : 'PublicKey::empty()'},`,
+			`\tself.h.clone(),`,
+			`));`
+		];
 		let sendMessage = [
-			`\n\tpub fn send_message(&mut self, message: Message) -> MessageBuffer {`
+			`\n\t/* Returns None if a handshake message could not be written, for the reason given by`,
+			` * get_handshake_error(); the handshake cannot go on. */`,
+			`pub fn send_message(&mut self, message: Message) -> Option<MessageBuffer> {`
 		];
 		let recvMessage = [
 			`\n\tpub fn recv_message(&mut self, message: &mut MessageBuffer) -> Option<Vec<u8>> {`,
-			`\tlet mut plaintext: Option<Vec<u8>> = None;`
+			`\tlet mut plaintext: Option<Vec<u8>> = None;`,
+			...(isOneWayPattern ? [] : [`\tself.rehandshake_read = false;`])
 		];
+		let rotatable = staticKeyRotatable(pattern);
+		const readCall = (i) => {
+			return (rotatable && (i === 0)) ?
+				`self.read_with_static_keys(message, HandshakeState::read_message_${util.abc[i]})` :
+				`self.hs.read_message_${util.abc[i]}(message)`;
+		};
 		for (let i = 0; i < pattern.messages.length; i++) {
 			if (i < finalKex) {
 				sendMessage = sendMessage.concat([
 					`\t${i > 0? 'else ' : ''}if self.mc == ${i} {`,
-					`\t\tself.mc += 1;`,
-					`\t\tself.hs.write_message_${util.abc[i]}(&message.as_bytes()[..])`,
+					`\t\tmatch self.hs.write_message_${util.abc[i]}(&message.as_bytes()[..]) {`,
+					`\t\t\tSome(buffer) => {`,
+					`\t\t\t\tself.mc += 1;`,
+					`\t\t\t\tSome(buffer)`,
+					`\t\t\t}`,
+					`\t\t\tNone => {`,
+					`\t\t\t\tself.clear_handshake();`,
+					`\t\t\t\tNone`,
+					`\t\t\t}`,
+					`\t\t}`,
 					`\t}`
 				]);
 				recvMessage = recvMessage.concat([
 					`\t${i > 0? 'else ' : ''}if self.mc == ${i} {`,
-					`\t\tplaintext = self.hs.read_message_${util.abc[i]}(message);`,
+					`\t\tplaintext = ${readCall(i)};`,
 					`\t}`
 				]);
 			} else if (i == finalKex) {
 				sendMessage = sendMessage.concat([
 					`\t${!isOneWayPattern? 'else ' : ''}if self.mc == ${i} {`,
-					`\t\tlet temp = self.hs.write_message_${util.abc[i]}(&message.as_bytes()[..]);`,
+					`\t\tlet temp = match self.hs.write_message_${util.abc[i]}(&message.as_bytes()[..]) {`,
+					`\t\t\tSome(temp) => temp,`,
+					`\t\t\tNone => {`,
+					`\t\t\t\tself.clear_handshake();`,
+					`\t\t\t\treturn None;`,
+					`\t\t\t}`,
+					`\t\t};`,
 					`\t\tself.h = temp.0;`,
 					`\t\tself.cs1 = temp.2;`,
-					`\t\tself.cs2 = ${isOneWayPattern? 'CipherState::new()' : 'temp.3'};`,
+					`\t\tself.cs2 = ${isOneWayPattern? 'S::Cipher::new()' : 'temp.3'};`,
+					...(isOneWayPattern ? [] : [`\t\t${transportFill.join('\n\t\t\t')}`]),
+					`\t\tself.set_exporter_secret(temp.4);`,
 					`\t\tself.hs.clear();`,
 					`\t\tself.mc += 1;`,
-					`\t\ttemp.1`,
+					`\t\tSome(temp.1)`,
 					`\t}`
 				]);
 				recvMessage = recvMessage.concat([
 					`\t${!isOneWayPattern? 'else ' : ''}if self.mc == ${i} {`,
-					`\t\tif let Some(temp) = self.hs.read_message_${util.abc[i]}(message) {`,
+					`\t\tif let Some(temp) = ${readCall(i)} {`,
 					`\t\t\tself.h = temp.0;`,
 					`\t\t\tplaintext = Some(temp.1);`,
 					`\t\t\tself.cs1 = temp.2;`,
-					`\t\t\tself.cs2 = ${isOneWayPattern? 'CipherState::new()' : 'temp.3'};`,
+					`\t\t\tself.cs2 = ${isOneWayPattern? 'S::Cipher::new()' : 'temp.3'};`,
+					...(isOneWayPattern ? [] : [`\t\t\t${transportFill.join('\n\t\t\t\t')}`]),
+					`\t\t\tself.set_exporter_secret(temp.4);`,
 					`\t\t\tself.hs.clear();`,
 					`\t\t}`,
 					`\t}`
 				]);
 			} else {
-				sendMessage = sendMessage.concat([
+				sendMessage = sendMessage.concat(isOneWayPattern ? [
 					`\telse if self.i {`,
+				] : [
+					`\telse if let Some(ratchet) = &mut self.ratchet {`,
+					`\t\tself.mc += 1;`,
+					`\t\tSome(ratchet.write_message(&message.as_bytes()[..]))`,
+					`\t} else if self.i {`,
+				]).concat([
 					`\t\tlet buffer = self.cs1.write_message_regular(&message.as_bytes()[..]);`,
 					`\t\tself.mc += 1;`,
-					`\t\tbuffer`,
+					`\t\tSome(buffer)`,
 					`\t} else {`,
 					`\t\tlet buffer = self.${isOneWayPattern? 'cs1' : 'cs2'}.write_message_regular(&message.as_bytes()[..]);`,
 					`\t\tself.mc += 1;`,
-					`\t\tbuffer`,
+					`\t\tSome(buffer)`,
 					`\t}`,
 				]);
 				recvMessage = recvMessage.concat([
 					`\telse if self.mc > ${finalKex} {`,
+				].concat(isOneWayPattern ? [
 					`\t\tif self.i {`,
+				] : [
+					`\t\tif let Some(ratchet) = &mut self.ratchet {`,
+					`\t\t\tplaintext = ratchet.read_message(message);`,
+					`\t\t} else if self.i {`,
+				]).concat([
 					`\t\t\tif let Some(msg) = &self.${isOneWayPattern? 'cs1' : 'cs2'}.read_message_regular(message) {`,
 					`\t\t\t\tplaintext = Some(msg.to_owned());`,
+				], isOneWayPattern ? [] : [
+					`\t\t\t} else {`,
+					`\t\t\t\tplaintext = self.recv_rehandshake(message);`
+				], [
 					`\t\t\t}`,
 					`\t\t} else {`,
 					`\t\t\tif let Some(msg) = &self.cs1.read_message_regular(message) {`,
 					`\t\t\t\tplaintext = Some(msg.to_owned());`,
+				], isOneWayPattern ? [] : [
+					`\t\t\t} else {`,
+					`\t\t\t\tplaintext = self.recv_rehandshake(message);`
+				], [
 					`\t\t\t}`,
 					`\t\t}`,
 					`\t}`,
 					`\tself.mc += 1;`,
 					`\tplaintext`
-				]);
+				]));
 				break;
 			}
 		}
@@ -462,7 +785,273 @@ const NOISE2RS = {
 		recvMessage = recvMessage.concat([
 			`}`
 		]);
-		return initSession.concat(sendMessage).concat(recvMessage);
+		let sendMessageWithAd = [
+			`\n\t/* Sends a transport message whose authentication tag also covers ad, which the peer must pass to recv_message_with_ad(). */`,
+			`pub fn send_message_with_ad(&mut self, ad: &[u8], message: Message) -> MessageBuffer {`,
+			`\tif !self.is_handshake_finished() {`,
+			`\t\tpanic!("Handshake not complete; send_message_with_ad");`,
+			`\t}`,
+			`\tself.mc += 1;`,
+		].concat(isOneWayPattern ? [
+			`\tself.cs1.write_message_with_ad(ad, &message.as_bytes()[..])`,
+		] : [
+			`\tif let Some(ratchet) = &mut self.ratchet {`,
+			`\t\tratchet.write_message_with_ad(ad, &message.as_bytes()[..])`,
+			`\t} else if self.i {`,
+			`\t\tself.cs1.write_message_with_ad(ad, &message.as_bytes()[..])`,
+			`\t} else {`,
+			`\t\tself.cs2.write_message_with_ad(ad, &message.as_bytes()[..])`,
+			`\t}`,
+		]).concat([
+			`}`
+		]);
+		let recvMessageWithAd = [
+			`\n\tpub fn recv_message_with_ad(&mut self, ad: &[u8], message: &mut MessageBuffer) -> Option<Vec<u8>> {`,
+			`\tif !self.is_handshake_finished() {`,
+			`\t\tpanic!("Handshake not complete; recv_message_with_ad");`,
+			`\t}`,
+			`\tself.mc += 1;`,
+		].concat(isOneWayPattern ? [
+			`\tself.cs1.read_message_with_ad(ad, message)`
+		] : [
+			`\tself.rehandshake_read = false;`,
+			`\tif let Some(ratchet) = &mut self.ratchet {`,
+			`\t\treturn ratchet.read_message_with_ad(ad, message);`,
+			`\t}`,
+			`\tlet plaintext: Option<Vec<u8>> = if self.i {`,
+			`\t\tself.cs2.read_message_with_ad(ad, message)`,
+			`\t} else {`,
+			`\t\tself.cs1.read_message_with_ad(ad, message)`,
+			`\t};`,
+			`\tplaintext.or_else(|| self.recv_rehandshake(message))`
+		]).concat([
+			`}`
+		]);
+		let intoSplit = (isOneWayPattern ? [
+			`\n\tpub fn into_split(self) -> (Sender<S>, Receiver<S>) {`
+		] : [
+			`\n\t/* The halves cannot re-handshake: re-handshake messages from the peer fail to`,
+			` * decrypt in the Receiver, so a re-handshake under way must finish first. */`,
+			`pub fn into_split(self) -> (Sender<S>, Receiver<S>) {`
+		]).concat([
+			`\tif !self.is_handshake_finished() {`,
+			`\t\tpanic!("Handshake not complete; into_split");`,
+			`\t}`
+		]).concat(isOneWayPattern ? [] : [
+			`\tif self.ratchet.is_some() {`,
+			`\t\tpanic!("Double ratchet sessions cannot be split; into_split");`,
+			`\t}`,
+			`\tif self.rh.as_ref().is_some_and(|rh| rh.in_progress()) {`,
+			`\t\tpanic!("Re-handshake in progress; into_split");`,
+			`\t}`
+		]);
+		if (isOneWayPattern) {
+			intoSplit = intoSplit.concat([
+				`\tif self.i {`,
+				`\t\t(Sender { cs: Some(self.cs1) }, Receiver { cs: None })`,
+				`\t} else {`,
+				`\t\t(Sender { cs: None }, Receiver { cs: Some(self.cs1) })`,
+				`\t}`
+			]);
+		} else {
+			intoSplit = intoSplit.concat([
+				`\tif self.i {`,
+				`\t\t(Sender { cs: Some(self.cs1) }, Receiver { cs: Some(self.cs2) })`,
+				`\t} else {`,
+				`\t\t(Sender { cs: Some(self.cs2) }, Receiver { cs: Some(self.cs1) })`,
+				`\t}`
+			]);
+		}
+		intoSplit = intoSplit.concat([
+			`}`
+		]);
+		let isHandshakeFinished = [
+			`\n\tpub fn is_handshake_finished(&self) -> bool {`,
+			`\tself.mc > ${finalKex}`,
+			`}`
+		];
+		let setStaticKeySelector = staticKeySelectable(pattern) ? [
+			`\n\t/* Lets a responder choose its static keypair after reading the first message, in patterns that do not use it sooner. */`,
+			`pub fn set_static_key_selector(&mut self, selector: Arc<dyn StaticKeySelector>) {`,
+			`\tif self.i || self.mc != 0 {`,
+			`\t\tpanic!("Not a fresh responder session; set_static_key_selector");`,
+			`\t}`,
+			`\tself.hs.set_static_key_selector(selector);`,
+			`}`
+		] : [];
+		let setPskResolver = hasPsk ? [
+			`\n\t/* Supplies the PSK once the peer's static key or the payload before the psk token is known. */`,
+			`pub fn set_psk_resolver(&mut self, resolver: Arc<dyn PskResolver>) {`,
+			`\tself.hs.set_psk_resolver(resolver);`,
+			`}`
+		] : [];
+		let setPreviousStaticKeys = rotatable ? [
+			`\n\t/* Static keypairs the responder used before its current one, still accepted from initiators that have not yet learned the new key. */`,
+			`pub fn set_previous_static_keys(&mut self, keys: Vec<Keypair>) {`,
+			`\tif self.i || self.mc != 0 {`,
+			`\t\tpanic!("Not a fresh responder session; set_previous_static_keys");`,
+			`\t}`,
+			`\tself.previous_statics = keys;`,
+			`}`
+		] : [];
+		let certificates = messagesStatic(pattern) ? [
+			`\n\t/* Sent with our static key during the handshake; the peer must have trust anchors set. */`,
+			`pub fn set_certificate(&mut self, certificate: Certificate) {`,
+			`\tself.hs.set_certificate(certificate);`,
+			`}`,
+			`\n\t/* Requires a certificate for the peer's static key from one of these authorities. */`,
+			`pub fn set_trust_anchors(&mut self, anchors: Vec<[u8; AUTHORITY_KEY_LENGTH]>) {`,
+			`\tself.hs.set_trust_anchors(anchors);`,
+			`}`,
+			`\n\tpub fn get_remote_certificate(&self) -> Option<Certificate> {`,
+			`\tself.hs.get_remote_certificate()`,
+			`}`
+		] : [];
+		let readWithStaticKeys = rotatable ? [
+			`\n\t/* Reads the initiator's first message with our current static key, or failing`,
+			` * that with each previous one in turn, each trial starting from a copy of the`,
+			` * untouched handshake state. */`,
+			`fn read_with_static_keys<T, F>(&mut self, message: &mut MessageBuffer, read: F) -> Option<T>`,
+			`where`,
+			`\tF: Fn(&mut HandshakeState<S>, &mut MessageBuffer) -> Option<T>,`,
+			`{`,
+			`\tif self.previous_statics.is_empty() {`,
+			`\t\tlet result: Option<T> = read(&mut self.hs, message);`,
+			`\t\tself.static_key_index = result.as_ref().map(|_| 0);`,
+			`\t\treturn result;`,
+			`\t}`,
+			`\tlet original: MessageBuffer = message.clone();`,
+			`\tlet mut pristine: HandshakeState<S> = self.hs.clone();`,
+			`\tlet mut result: Option<T> = read(&mut self.hs, message);`,
+			`\tif result.is_some() {`,
+			`\t\tself.static_key_index = Some(0);`,
+			`\t}`,
+			`\tfor (i, s) in self.previous_statics.iter().enumerate() {`,
+			`\t\tif result.is_some() {`,
+			`\t\t\tbreak;`,
+			`\t\t}`,
+			`\t\tlet mut hs: HandshakeState<S> = pristine.with_static_keypair(s.clone());`,
+			`\t\tresult = read(&mut hs, &mut original.clone());`,
+			`\t\tif result.is_some() {`,
+			`\t\t\tself.hs.clear();`,
+			`\t\t\tself.hs = hs;`,
+			`\t\t\tself.static_key_index = Some(i + 1);`,
+			`\t\t} else {`,
+			`\t\t\ths.clear();`,
+			`\t\t}`,
+			`\t}`,
+			`\tpristine.clear();`,
+			`\tself.clear_previous_static_keys();`,
+			`\tresult`,
+			`}`
+		] : [];
+		let setRatchet = isOneWayPattern ? [] : [
+			`\n\t/* Runs transport messages through a double ratchet seeded from the handshake; both parties must agree. */`,
+			`pub fn set_ratchet(&mut self, ratchet: bool) {`,
+			`\tself.dr = ratchet;`,
+			`}`
+		];
+		let rehandshake = isOneWayPattern ? [] : [
+			`\n\t/* Starts a fresh handshake inside the transport channel, KK if both parties`,
+			` * have static keys and NN otherwise. Application data keeps flowing while the`,
+			` * peer replies; each side switches keys after take_rehandshake_message().`,
+			` * None if the static key oracle fails, in which case the channel is left as is. */`,
+			`pub fn rehandshake(&mut self) -> Option<MessageBuffer> {`,
+			`\tif self.ratchet.is_some() {`,
+			`\t\tpanic!("Double ratchet sessions cannot re-handshake; rehandshake");`,
+			`\t}`,
+			`\tlet rh = self.rh.as_mut().expect("Two-way handshake not complete; rehandshake");`,
+			`\tif rh.in_progress() {`,
+			`\t\tpanic!("Re-handshake already in progress; rehandshake");`,
+			`\t}`,
+			`\tlet control: Vec<u8> = rh.request()?;`,
+			`\tlet cs = if self.i { &mut self.cs1 } else { &mut self.cs2 };`,
+			`\tSome(cs.write_message_with_ad(REHANDSHAKE_AD, &control[..]))`,
+			`}`
+		];
+		let takeRehandshakeMessage = isOneWayPattern ? [] : [
+			`\n\t/* Returns the re-handshake message owed to the peer after recv_message() consumed one. */`,
+			`pub fn take_rehandshake_message(&mut self) -> Option<MessageBuffer> {`,
+			`\tlet (control, next) = self.rh.as_mut()?.take_reply()?;`,
+			`\tlet cs = if self.i { &mut self.cs1 } else { &mut self.cs2 };`,
+			`\tlet message: MessageBuffer = cs.write_message_with_ad(REHANDSHAKE_AD, &control[..]);`,
+			`\tif let Some(next) = next {`,
+			`\t\t*cs = next;`,
+			`\t}`,
+			`\tSome(message)`,
+			`}`
+		];
+		let isRehandshakeMessage = isOneWayPattern ? [] : [
+			`\n\t/* True if the message last read by recv_message() or recv_message_with_ad() was a`,
+			` * re-handshake message, which reads as an empty payload, rather than application data. */`,
+			`pub fn is_rehandshake_message(&self) -> bool {`,
+			`\tself.rehandshake_read`,
+			`}`
+		];
+		let recvRehandshake = isOneWayPattern ? [] : [
+			`\n\tfn recv_rehandshake(&mut self, message: &MessageBuffer) -> Option<Vec<u8>> {`,
+			`\tlet rh = self.rh.as_mut()?;`,
+			`\tlet cs = if self.i { &mut self.cs2 } else { &mut self.cs1 };`,
+			`\tlet control: Vec<u8> = cs.read_message_with_ad(REHANDSHAKE_AD, message)?;`,
+			`\tif let Some(next) = rh.read(&control[..])? {`,
+			`\t\t*cs = next;`,
+			`\t}`,
+			`\tself.rehandshake_read = true;`,
+			`\tSome(Vec::new())`,
+			`}`
+		];
+		return initSession.concat(sendMessage).concat(recvMessage).concat(sendMessageWithAd).concat(recvMessageWithAd).concat(intoSplit).concat(isHandshakeFinished).concat(setStaticKeySelector).concat(setPskResolver).concat(setPreviousStaticKeys).concat(certificates).concat(readWithStaticKeys).concat(setRatchet).concat(rehandshake).concat(takeRehandshakeMessage).concat(isRehandshakeMessage).concat(recvRehandshake);
+	};
+
+	const sessionFuns = (pattern) => {
+		let hasPsk = messagesPsk(pattern) >= 0;
+		let params = `initiator: bool, prologue: Message, s: Keypair, rs: PublicKey${hasPsk? ', psk: Psk' : ''}`;
+		let args = `initiator, prologue, s, rs${hasPsk? ', psk' : ''}`;
+		return [
+			`impl NoiseSession {`,
+			`\tpub fn init_session(${params}) -> NoiseSession {`,
+			`\t\tNoiseSession::init(${args})`,
+			`\t}`,
+			`}`,
+			``,
+			`impl NoiseSession<DiscoSymmetricState> {`,
+			`\tpub fn init_disco_session(${params}) -> NoiseSession<DiscoSymmetricState> {`,
+			`\t\tNoiseSession::init(${args})`,
+			`\t}`,
+			`}`
+		];
+	};
+
+	const socketFuns = (pattern) => {
+		let hasPsk = messagesPsk(pattern) >= 0;
+		let finalKex = finalKeyExchangeMessage(pattern);
+		let hasKey = false;
+		let layout = [];
+		for (let i = 0; i <= finalKex; i++) {
+			let message = pattern.messages[i];
+			let neLength = (message.tokens.indexOf('e') >= 0)? 'DHLEN' : '0';
+			let nsLength = '0';
+			message.tokens.forEach((token) => {
+				if (token === 's') {
+					nsLength = hasKey? 'DHLEN + MAC_LENGTH' : 'DHLEN';
+				} else if ((token !== 'e') || hasPsk) {
+					hasKey = true;
+				}
+			});
+			layout.push(`\t\t${i} => (${neLength}, ${nsLength}),`);
+		}
+		return [
+			`pub const PROTOCOL_NAME: &[u8] = b"Noise_${pattern.name}_25519_ChaChaPoly_BLAKE2s";`,
+			`const HANDSHAKE_LENGTH: u32 = ${finalKex + 1};`,
+			``,
+			`/* Lengths of the ne and ns fields of each handshake message. */`,
+			`pub(crate) fn message_layout(mc: u32) -> (usize, usize) {`,
+			`\tmatch mc {`
+		].concat(layout).concat([
+			`\t\t_ => (0, 0),`,
+			`\t}`,
+			`}`
+		]);
 	};
 
 	const parse = (pattern) => {
@@ -485,7 +1074,19 @@ const NOISE2RS = {
 		let b = responderFun(pattern).join('\n\t');
 		let k = repeatingKeysQueryFun(pattern).join('\n\t');
 		let p = processFuns(pattern, isOneWayPattern).join('\n\t');
+		let c = sessionFuns(pattern).join('\n');
+		let l = socketFuns(pattern).join('\n');
 		let q = queries(pattern).join('\n');
+		let j = stateImports(pattern);
+		let f = stateFields(pattern);
+		let h = stateHelperFuns(pattern, isOneWayPattern);
+		let m = sessionImports(pattern, isOneWayPattern);
+		let d = isOneWayPattern ? [] : [
+			`    dr: bool,`,
+			`    ratchet: Option<DoubleRatchet>,`,
+			`    rh: Option<Rehandshake<S>>,`,
+			`    rehandshake_read: bool,`
+		];
 		let parsed = {
 			t,
 			s,
@@ -498,7 +1099,14 @@ const NOISE2RS = {
 			a,
 			b,
 			k,
-			p
+			p,
+			c,
+			l,
+			j,
+			f,
+			h,
+			m,
+			d
 		};
 		return parsed;
 	};
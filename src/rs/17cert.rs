@@ -0,0 +1,143 @@
+/* ---------------------------------------------------------------- *
+ * CERTIFICATES                                                     *
+ * ---------------------------------------------------------------- */
+
+/* Static key certificates: an Ed25519 authority vouches that a Curve25519
+ * static key belongs to a name until an expiry time, in seconds since the UNIX
+ * epoch. The session carries its certificate at the start of the payload of
+ * each message that sends its static key. Encoded as: name length (1 byte),
+ * name, static key, expiry (8 bytes, big-endian), issuer key, signature. */
+
+use crate::{consts::DHLEN, types::PublicKey};
+use byteorder::{BigEndian, ByteOrder};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+const CERTIFICATE_CONTEXT: &[u8] = b"NoiseExplorer static key certificate";
+pub const AUTHORITY_KEY_LENGTH: usize = 32;
+const SIGNATURE_LENGTH: usize = 64;
+const MAX_NAME_LENGTH: usize = 255;
+
+pub struct CertificateAuthority {
+	key: SigningKey,
+}
+impl CertificateAuthority {
+	pub fn from_bytes(secret: [u8; 32]) -> CertificateAuthority {
+		CertificateAuthority {
+			key: SigningKey::from_bytes(&secret),
+		}
+	}
+	pub fn get_public_key(&self) -> [u8; AUTHORITY_KEY_LENGTH] {
+		self.key.verifying_key().to_bytes()
+	}
+	pub fn issue(&self, name: &str, static_key: &PublicKey, expiry: u64) -> Certificate {
+		assert!(name.len() <= MAX_NAME_LENGTH, "Name too long; issue");
+		let mut certificate = Certificate {
+			name: String::from(name),
+			static_key: *static_key,
+			expiry,
+			issuer: self.get_public_key(),
+			signature: [0u8; SIGNATURE_LENGTH],
+		};
+		certificate.signature = self.key.sign(&certificate.signed_bytes()[..]).to_bytes();
+		certificate
+	}
+}
+
+#[derive(Clone)]
+pub struct Certificate {
+	name: String,
+	static_key: PublicKey,
+	expiry: u64,
+	issuer: [u8; AUTHORITY_KEY_LENGTH],
+	signature: [u8; SIGNATURE_LENGTH],
+}
+impl Certificate {
+	pub fn get_name(&self) -> &str {
+		&self.name[..]
+	}
+	pub fn get_static_key(&self) -> PublicKey {
+		self.static_key
+	}
+	pub fn get_expiry(&self) -> u64 {
+		self.expiry
+	}
+	pub fn get_issuer(&self) -> [u8; AUTHORITY_KEY_LENGTH] {
+		self.issuer
+	}
+	fn body(&self) -> Vec<u8> {
+		let mut out: Vec<u8> = vec![self.name.len() as u8];
+		out.extend_from_slice(self.name.as_bytes());
+		out.extend_from_slice(&self.static_key.as_bytes()[..]);
+		let mut expiry = [0u8; 8];
+		BigEndian::write_u64(&mut expiry, self.expiry);
+		out.extend_from_slice(&expiry[..]);
+		out.extend_from_slice(&self.issuer[..]);
+		out
+	}
+	fn signed_bytes(&self) -> Vec<u8> {
+		let mut out: Vec<u8> = Vec::from(CERTIFICATE_CONTEXT);
+		out.extend(self.body());
+		out
+	}
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut out: Vec<u8> = self.body();
+		out.extend_from_slice(&self.signature[..]);
+		out
+	}
+	pub fn from_bytes(data: &[u8]) -> Option<Certificate> {
+		let name_length: usize = *data.first()? as usize;
+		if data.len() != 1 + name_length + DHLEN + 8 + AUTHORITY_KEY_LENGTH + SIGNATURE_LENGTH {
+			return None;
+		}
+		let name: String = String::from_utf8(Vec::from(&data[1..1 + name_length])).ok()?;
+		let mut offset: usize = 1 + name_length;
+		let mut static_key = [0u8; DHLEN];
+		static_key.copy_from_slice(&data[offset..offset + DHLEN]);
+		offset += DHLEN;
+		let expiry: u64 = BigEndian::read_u64(&data[offset..offset + 8]);
+		offset += 8;
+		let mut issuer = [0u8; AUTHORITY_KEY_LENGTH];
+		issuer.copy_from_slice(&data[offset..offset + AUTHORITY_KEY_LENGTH]);
+		let mut signature = [0u8; SIGNATURE_LENGTH];
+		signature.copy_from_slice(&data[offset + AUTHORITY_KEY_LENGTH..]);
+		Some(Certificate {
+			name,
+			static_key: PublicKey::from_bytes(static_key),
+			expiry,
+			issuer,
+			signature,
+		})
+	}
+	/* True if the certificate was signed by one of the anchors and has not expired at now. */
+	pub fn verify(&self, anchors: &[[u8; AUTHORITY_KEY_LENGTH]], now: u64) -> bool {
+		if now >= self.expiry || !anchors.contains(&self.issuer) {
+			return false;
+		}
+		match VerifyingKey::from_bytes(&self.issuer) {
+			Ok(issuer) => issuer
+				.verify_strict(&self.signed_bytes()[..], &Signature::from_bytes(&self.signature))
+				.is_ok(),
+			Err(_) => false,
+		}
+	}
+}
+
+/* $NOISE2RS_O$ */
+#[test]
+fn certificate_test() {
+	use crate::types::Keypair;
+	let authority: CertificateAuthority = CertificateAuthority::from_bytes([0x42u8; 32]);
+	let anchors: [[u8; AUTHORITY_KEY_LENGTH]; 1] = [authority.get_public_key()];
+	let device: PublicKey = Keypair::new().get_public_key();
+	let certificate: Certificate = authority.issue("device", &device, 2_000_000_000);
+	let decoded: Certificate = Certificate::from_bytes(&certificate.to_bytes()[..]).unwrap();
+	assert!(decoded.get_name() == "device");
+	assert!(decoded.get_static_key().as_bytes() == device.as_bytes());
+	assert!(decoded.verify(&anchors[..], 1_900_000_000));
+	assert!(!decoded.verify(&anchors[..], 2_000_000_000));
+	assert!(!decoded.verify(&[CertificateAuthority::from_bytes([0x43u8; 32]).get_public_key()], 1_900_000_000));
+	let mut forged: Vec<u8> = certificate.to_bytes();
+	forged[1] ^= 0x01;
+	assert!(!Certificate::from_bytes(&forged[..]).unwrap().verify(&anchors[..], 1_900_000_000));
+	assert!(Certificate::from_bytes(&forged[1..]).is_none());
+}
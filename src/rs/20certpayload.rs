@@ -0,0 +1,36 @@
+/* Payloads sent alongside a static key start with the length-prefixed certificate;
+ * only patterns that send a static key in a handshake message use these. */
+
+const LENGTH_FIELD: usize = 2;
+
+pub(crate) fn unix_time() -> u64 {
+	use std::time::{SystemTime, UNIX_EPOCH};
+	SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+pub(crate) fn attach(certificate: &Certificate, payload: &[u8]) -> Vec<u8> {
+	let encoded: Vec<u8> = certificate.to_bytes();
+	let mut out: Vec<u8> = vec![0u8; LENGTH_FIELD];
+	BigEndian::write_u16(&mut out[..], encoded.len() as u16);
+	out.extend(encoded);
+	out.extend_from_slice(payload);
+	out
+}
+
+pub(crate) fn detach(plaintext: &[u8]) -> Option<(Certificate, Vec<u8>)> {
+	let length: usize = BigEndian::read_u16(plaintext.get(..LENGTH_FIELD)?) as usize;
+	let certificate: Certificate = Certificate::from_bytes(plaintext.get(LENGTH_FIELD..LENGTH_FIELD + length)?)?;
+	Some((certificate, Vec::from(&plaintext[LENGTH_FIELD + length..])))
+}
+
+#[test]
+fn certificate_payload_test() {
+	use crate::types::Keypair;
+	let authority: CertificateAuthority = CertificateAuthority::from_bytes([0x42u8; 32]);
+	let device: PublicKey = Keypair::new().get_public_key();
+	let certificate: Certificate = authority.issue("device", &device, 2_000_000_000);
+	let (decoded, payload) = detach(&attach(&certificate, b"hello")[..]).unwrap();
+	assert!(payload == b"hello" && decoded.get_name() == "device");
+	assert!(detach(&attach(&certificate, b"hello")[1..]).is_none());
+}
+
@@ -0,0 +1,14 @@
+/* SHA-256 for the protocols built on this pattern that specify it;
+ * only crates with such an extension use it. */
+
+use crypto::sha2::Sha256;
+
+#[derive(Clone)]
+pub struct Sha256Hash;
+impl HashFunction for Sha256Hash {
+	type Digest = Sha256;
+	const CIPHER_SUITE: &'static str = "ChaChaPoly_SHA256";
+	fn digest() -> Sha256 {
+		Sha256::new()
+	}
+}
@@ -29,6 +29,9 @@ pub fn encrypt(k: [u8; DHLEN], n: u64, ad: &[u8], plaintext: &[u8]) -> Vec<u8> {
 }
 
 pub fn decrypt(k: [u8; DHLEN], n: u64, ad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+	if ciphertext.len() < MAC_LENGTH {
+		return None;
+	}
 	let temp = Vec::from(ciphertext);
 	let (x, y) = temp.split_at(temp.len() - MAC_LENGTH);
 	let mut in_out = x.to_owned();
@@ -47,32 +50,83 @@ pub fn decrypt(k: [u8; DHLEN], n: u64, ad: &[u8], ciphertext: &[u8]) -> Option<V
 	}
 }
 
+/* The hash function of the cipher suite; SymmetricState is generic over it
+ * so that protocols specified over SHA-256 can run the generated handshake. */
+pub trait HashFunction: Clone {
+	type Digest: Digest;
+	const CIPHER_SUITE: &'static str;
+	fn digest() -> Self::Digest;
+	fn hash(data: &[u8]) -> [u8; HASHLEN] {
+		let mut digest = Self::digest();
+		digest.input(data);
+		let mut digest_res = [0u8; HASHLEN];
+		digest.result(&mut digest_res);
+		digest.reset();
+		digest_res
+	}
+	fn hmac(key: &[u8], data: &[u8], out: &mut [u8]) {
+		let mut digest = Self::digest();
+		let mut ipad = [0x36u8; BLOCKLEN];
+		let mut opad = [0x5cu8; BLOCKLEN];
+		for count in 0..key.len() {
+			ipad[count] ^= key[count];
+			opad[count] ^= key[count];
+		}
+		digest.reset();
+		digest.input(&ipad[..BLOCKLEN]);
+		digest.input(data);
+		let mut inner_output = [0u8; HASHLEN];
+		digest.result(&mut inner_output);
+		digest.reset();
+		digest.input(&opad[..BLOCKLEN]);
+		digest.input(&inner_output[..HASHLEN]);
+		digest.result(out);
+	}
+	fn hkdf(
+		chaining_key: &[u8],
+		input_key_material: &[u8],
+		outputs: usize,
+		out1: &mut [u8],
+		out2: &mut [u8],
+		out3: &mut [u8],
+	) {
+		let mut temp_key = [0u8; HASHLEN];
+		Self::hmac(chaining_key, input_key_material, &mut temp_key);
+		Self::hmac(&temp_key, &[1u8], out1);
+		if outputs == 1 {
+			return;
+		}
+		let mut in2 = [0u8; HASHLEN + 1];
+		copy_slices!(&out1[0..HASHLEN], &mut in2);
+		in2[HASHLEN] = 2;
+		Self::hmac(&temp_key, &in2[..=HASHLEN], out2);
+		if outputs == 2 {
+			return;
+		}
+		let mut in3 = [0u8; HASHLEN + 1];
+		copy_slices!(&out2[0..HASHLEN], &mut in3);
+		in3[HASHLEN] = 3;
+		Self::hmac(&temp_key, &in3[..=HASHLEN], out3);
+	}
+}
+
+#[derive(Clone)]
+pub struct Blake2sHash;
+impl HashFunction for Blake2sHash {
+	type Digest = Blake2s;
+	const CIPHER_SUITE: &'static str = "ChaChaPoly_BLAKE2s";
+	fn digest() -> Blake2s {
+		Blake2s::new(HASHLEN)
+	}
+}
+/* $NOISE2RS_Q$ */
+
 pub fn hash(data: &[u8]) -> [u8; HASHLEN] {
-	let mut blake2s: Blake2s = Blake2s::new(HASHLEN);
-	blake2s.input(&data[..]);
-	let mut digest_res = [0u8; HASHLEN];
-	blake2s.result(&mut digest_res);
-	blake2s.reset();
-	digest_res
+	Blake2sHash::hash(data)
 }
 
 pub fn hmac(key: &[u8], data: &[u8], out: &mut [u8]) {
-	let mut blake2s: Blake2s = Blake2s::new(HASHLEN);
-	let mut ipad = [0x36u8; BLOCKLEN];
-	let mut opad = [0x5cu8; BLOCKLEN];
-	for count in 0..key.len() {
-		ipad[count] ^= key[count];
-		opad[count] ^= key[count];
-	}
-	blake2s.reset();
-	blake2s.input(&ipad[..BLOCKLEN]);
-	blake2s.input(data);
-	let mut inner_output = [0u8; HASHLEN];
-	blake2s.result(&mut inner_output);
-	blake2s.reset();
-	blake2s.input(&opad[..BLOCKLEN]);
-	blake2s.input(&inner_output[..HASHLEN]);
-	blake2s.result(out);
+	Blake2sHash::hmac(key, data, out)
 }
 
 pub fn hkdf(
@@ -83,21 +137,5 @@ pub fn hkdf(
 	out2: &mut [u8],
 	out3: &mut [u8],
 ) {
-	let mut temp_key = [0u8; HASHLEN];
-	hmac(chaining_key, input_key_material, &mut temp_key);
-	hmac(&temp_key, &[1u8], out1);
-	if outputs == 1 {
-		return;
-	}
-	let mut in2 = [0u8; HASHLEN + 1];
-	copy_slices!(&out1[0..HASHLEN], &mut in2);
-	in2[HASHLEN] = 2;
-	hmac(&temp_key, &in2[..=HASHLEN], out2);
-	if outputs == 2 {
-		return;
-	}
-	let mut in3 = [0u8; HASHLEN + 1];
-	copy_slices!(&out2[0..HASHLEN], &mut in3);
-	in3[HASHLEN] = 3;
-	hmac(&temp_key, &in3[..=HASHLEN], out3);
+	Blake2sHash::hkdf(chaining_key, input_key_material, outputs, out1, out2, out3)
 }
@@ -0,0 +1,427 @@
+/* ---------------------------------------------------------------- *
+ * LIBP2P                                                           *
+ * ---------------------------------------------------------------- */
+
+/* libp2p runs Noise_XX_25519_ChaChaPoly_SHA256: the messages below are
+ * produced by the generated XX HandshakeState over a SHA-256 SymmetricState,
+ * carrying signed identity payloads in length-prefixed frames. */
+
+use crate::{
+	consts::{DHLEN, MAC_LENGTH, ZEROLEN},
+	prims::{HashFunction, Sha256Hash},
+	state::{CipherState, HandshakeState, SymmetricState},
+	types::{Keypair, MessageBuffer, Psk, PublicKey},
+};
+use byteorder::{BigEndian, ByteOrder};
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use std::fmt;
+
+const STATIC_KEY_DOMAIN: &[u8] = b"noise-libp2p-static-key:";
+const KEY_TYPE_ED25519: u64 = 1;
+const KEY_TYPE_SECP256K1: u64 = 2;
+const MAX_INLINE_KEY_LENGTH: usize = 42;
+const MULTIHASH_IDENTITY: u8 = 0x00;
+const MULTIHASH_SHA256: u8 = 0x12;
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+pub const MAX_MESSAGE_LENGTH: usize = 65535;
+pub const MAX_PAYLOAD_LENGTH: usize = MAX_MESSAGE_LENGTH - MAC_LENGTH;
+
+/* ---------------------------------------------------------------- *
+ * PROTOBUF                                                         *
+ * ---------------------------------------------------------------- */
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+	while n >= 0x80 {
+		out.push((n as u8) | 0x80);
+		n >>= 7;
+	}
+	out.push(n as u8);
+}
+
+fn read_varint(data: &[u8], offset: &mut usize) -> Option<u64> {
+	let mut n: u64 = 0;
+	for shift in (0..64).step_by(7) {
+		let byte = *data.get(*offset)?;
+		*offset += 1;
+		n |= u64::from(byte & 0x7f) << shift;
+		if byte & 0x80 == 0 {
+			return Some(n);
+		}
+	}
+	None
+}
+
+fn write_bytes_field(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+	write_varint(out, field << 3 | 2);
+	write_varint(out, bytes.len() as u64);
+	out.extend(bytes);
+}
+
+/* Returns (field, wire type, value), with varint values stored big-endian. */
+fn read_fields(data: &[u8]) -> Option<Vec<(u64, u64, Vec<u8>)>> {
+	let mut fields: Vec<(u64, u64, Vec<u8>)> = Vec::new();
+	let mut offset: usize = 0;
+	while offset < data.len() {
+		let key = read_varint(data, &mut offset)?;
+		match key & 7 {
+			0 => {
+				let mut value = [0u8; 8];
+				BigEndian::write_u64(&mut value, read_varint(data, &mut offset)?);
+				fields.push((key >> 3, 0, value.to_vec()));
+			}
+			2 => {
+				let length = read_varint(data, &mut offset)? as usize;
+				let end = offset.checked_add(length)?;
+				fields.push((key >> 3, 2, data.get(offset..end)?.to_vec()));
+				offset = end;
+			}
+			_ => return None,
+		}
+	}
+	Some(fields)
+}
+
+/* ---------------------------------------------------------------- *
+ * IDENTITIES                                                       *
+ * ---------------------------------------------------------------- */
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PeerId {
+	multihash: Vec<u8>,
+}
+impl PeerId {
+	pub fn from_public_key(public_key: &IdentityPublicKey) -> PeerId {
+		let encoded: Vec<u8> = public_key.encode_protobuf();
+		let mut multihash: Vec<u8> = Vec::new();
+		if encoded.len() <= MAX_INLINE_KEY_LENGTH {
+			multihash.push(MULTIHASH_IDENTITY);
+			write_varint(&mut multihash, encoded.len() as u64);
+			multihash.extend(encoded);
+		} else {
+			multihash.push(MULTIHASH_SHA256);
+			multihash.push(32);
+			multihash.extend(&Sha256Hash::hash(&encoded[..])[..]);
+		}
+		PeerId { multihash }
+	}
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.multihash[..]
+	}
+	pub fn to_base58(&self) -> String {
+		let mut digits: Vec<u8> = Vec::new();
+		for byte in &self.multihash {
+			let mut carry = u32::from(*byte);
+			for digit in digits.iter_mut() {
+				carry += u32::from(*digit) << 8;
+				*digit = (carry % 58) as u8;
+				carry /= 58;
+			}
+			while carry > 0 {
+				digits.push((carry % 58) as u8);
+				carry /= 58;
+			}
+		}
+		let zeros = self.multihash.iter().take_while(|b| **b == 0).count();
+		let mut out: String = "1".repeat(zeros);
+		out.extend(digits.iter().rev().map(|d| BASE58_ALPHABET[*d as usize] as char));
+		out
+	}
+}
+impl fmt::Display for PeerId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.to_base58())
+	}
+}
+impl fmt::Debug for PeerId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "PeerId({})", self.to_base58())
+	}
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum IdentityPublicKey {
+	Ed25519(VerifyingKey),
+	Secp256k1(secp256k1::PublicKey),
+}
+impl IdentityPublicKey {
+	pub fn encode_protobuf(&self) -> Vec<u8> {
+		let (key_type, data): (u64, Vec<u8>) = match self {
+			IdentityPublicKey::Ed25519(k) => (KEY_TYPE_ED25519, k.to_bytes().to_vec()),
+			IdentityPublicKey::Secp256k1(k) => (KEY_TYPE_SECP256K1, k.serialize().to_vec()),
+		};
+		let mut out: Vec<u8> = Vec::new();
+		write_varint(&mut out, 1 << 3);
+		write_varint(&mut out, key_type);
+		write_bytes_field(&mut out, 2, &data[..]);
+		out
+	}
+	pub fn decode_protobuf(encoded: &[u8]) -> Option<IdentityPublicKey> {
+		let mut key_type: Option<u64> = None;
+		let mut data: Option<Vec<u8>> = None;
+		for (field, wire_type, value) in read_fields(encoded)? {
+			match (field, wire_type) {
+				(1, 0) => key_type = Some(BigEndian::read_u64(&value[..])),
+				(2, 2) => data = Some(value),
+				_ => return None,
+			}
+		}
+		let data: Vec<u8> = data?;
+		match key_type? {
+			KEY_TYPE_ED25519 => {
+				let mut k = [0u8; 32];
+				if data.len() != k.len() {
+					return None;
+				}
+				k.copy_from_slice(&data[..]);
+				Some(IdentityPublicKey::Ed25519(VerifyingKey::from_bytes(&k).ok()?))
+			}
+			KEY_TYPE_SECP256K1 => Some(IdentityPublicKey::Secp256k1(
+				secp256k1::PublicKey::from_slice(&data[..]).ok()?,
+			)),
+			_ => None,
+		}
+	}
+	pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+		match self {
+			IdentityPublicKey::Ed25519(k) => match ed25519_dalek::Signature::from_slice(signature) {
+				Ok(signature) => k.verify(message, &signature).is_ok(),
+				Err(_) => false,
+			},
+			IdentityPublicKey::Secp256k1(k) => {
+				let digest = secp256k1::Message::from_digest_slice(&Sha256Hash::hash(message)[..]).unwrap();
+				match secp256k1::ecdsa::Signature::from_der(signature) {
+					Ok(mut signature) => {
+						signature.normalize_s();
+						secp256k1::Secp256k1::verification_only()
+							.verify_ecdsa(&digest, &signature, k)
+							.is_ok()
+					}
+					Err(_) => false,
+				}
+			}
+		}
+	}
+	pub fn to_peer_id(&self) -> PeerId {
+		PeerId::from_public_key(self)
+	}
+}
+
+#[derive(Clone)]
+pub enum IdentityKeypair {
+	Ed25519(SigningKey),
+	Secp256k1(secp256k1::SecretKey),
+}
+impl IdentityKeypair {
+	pub fn ed25519_from_bytes(secret: [u8; 32]) -> IdentityKeypair {
+		IdentityKeypair::Ed25519(SigningKey::from_bytes(&secret))
+	}
+	pub fn secp256k1_from_bytes(secret: [u8; 32]) -> Option<IdentityKeypair> {
+		Some(IdentityKeypair::Secp256k1(
+			secp256k1::SecretKey::from_slice(&secret[..]).ok()?,
+		))
+	}
+	pub fn public(&self) -> IdentityPublicKey {
+		match self {
+			IdentityKeypair::Ed25519(k) => IdentityPublicKey::Ed25519(k.verifying_key()),
+			IdentityKeypair::Secp256k1(k) => IdentityPublicKey::Secp256k1(
+				secp256k1::PublicKey::from_secret_key(&secp256k1::Secp256k1::signing_only(), k),
+			),
+		}
+	}
+	pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+		match self {
+			IdentityKeypair::Ed25519(k) => k.sign(message).to_bytes().to_vec(),
+			IdentityKeypair::Secp256k1(k) => {
+				let digest = secp256k1::Message::from_digest_slice(&Sha256Hash::hash(message)[..]).unwrap();
+				secp256k1::Secp256k1::signing_only()
+					.sign_ecdsa(&digest, k)
+					.serialize_der()
+					.to_vec()
+			}
+		}
+	}
+}
+
+fn static_key_message(static_public_key: &PublicKey) -> Vec<u8> {
+	let mut message: Vec<u8> = Vec::from(STATIC_KEY_DOMAIN);
+	message.extend(&static_public_key.as_bytes()[..]);
+	message
+}
+
+fn encode_payload(identity: &IdentityKeypair, static_public_key: &PublicKey) -> Vec<u8> {
+	let mut payload: Vec<u8> = Vec::new();
+	write_bytes_field(&mut payload, 1, &identity.public().encode_protobuf()[..]);
+	write_bytes_field(&mut payload, 2, &identity.sign(&static_key_message(static_public_key)[..])[..]);
+	payload
+}
+
+/* Unknown fields, such as NoiseExtensions, are skipped. */
+fn verify_payload(payload: &[u8], static_public_key: &PublicKey) -> Option<IdentityPublicKey> {
+	let mut identity_key: Option<Vec<u8>> = None;
+	let mut identity_sig: Option<Vec<u8>> = None;
+	for (field, wire_type, value) in read_fields(payload)? {
+		match (field, wire_type) {
+			(1, 2) => identity_key = Some(value),
+			(2, 2) => identity_sig = Some(value),
+			_ => {}
+		}
+	}
+	let identity: IdentityPublicKey = IdentityPublicKey::decode_protobuf(&identity_key?[..])?;
+	if !identity.verify(&static_key_message(static_public_key)[..], &identity_sig?[..]) {
+		return None;
+	}
+	Some(identity)
+}
+
+/* ---------------------------------------------------------------- *
+ * HANDSHAKE                                                        *
+ * ---------------------------------------------------------------- */
+
+fn frame(message: &[u8]) -> Vec<u8> {
+	let mut length = [0u8; 2];
+	BigEndian::write_u16(&mut length, message.len() as u16);
+	let mut out: Vec<u8> = length.to_vec();
+	out.extend(message);
+	out
+}
+
+fn unframe(packet: &[u8]) -> Option<&[u8]> {
+	if packet.len() < 2 || BigEndian::read_u16(&packet[..2]) as usize != packet.len() - 2 {
+		return None;
+	}
+	Some(&packet[2..])
+}
+
+fn read_key(bytes: &[u8]) -> [u8; DHLEN] {
+	let mut k = [0u8; DHLEN];
+	k.copy_from_slice(bytes);
+	k
+}
+
+pub struct Handshake {
+	hs: HandshakeState<SymmetricState<Sha256Hash>>,
+	identity: IdentityKeypair,
+	rs: PublicKey,
+	remote_identity: Option<IdentityPublicKey>,
+}
+impl Handshake {
+	fn new(hs: HandshakeState<SymmetricState<Sha256Hash>>, identity: IdentityKeypair) -> Handshake {
+		Handshake {
+			hs,
+			identity,
+			rs: PublicKey::empty(),
+			remote_identity: None,
+		}
+	}
+	pub fn initiator(prologue: &[u8], s: Keypair, identity: IdentityKeypair) -> Handshake {
+		Handshake::new(HandshakeState::initialize_initiator(prologue, s, PublicKey::empty(), Psk::new()), identity)
+	}
+	pub fn responder(prologue: &[u8], s: Keypair, identity: IdentityKeypair) -> Handshake {
+		Handshake::new(HandshakeState::initialize_responder(prologue, s, PublicKey::empty(), Psk::new()), identity)
+	}
+	pub fn set_ephemeral_keypair(&mut self, e: Keypair) {
+		self.hs.set_ephemeral_keypair(e);
+	}
+	pub fn get_remote_static_public_key(&self) -> PublicKey {
+		self.rs
+	}
+	pub fn get_remote_identity(&self) -> Option<IdentityPublicKey> {
+		self.remote_identity.clone()
+	}
+	pub fn get_remote_peer_id(&self) -> Option<PeerId> {
+		self.remote_identity.as_ref().map(IdentityPublicKey::to_peer_id)
+	}
+	fn payload(&self) -> Vec<u8> {
+		encode_payload(&self.identity, &self.hs.get_static_keypair().get_public_key())
+	}
+	fn read_identity(&mut self, payload: &[u8]) -> Option<PeerId> {
+		self.rs = self.hs.get_remote_static_public_key();
+		let identity: IdentityPublicKey = verify_payload(payload, &self.rs)?;
+		let peer_id: PeerId = identity.to_peer_id();
+		self.remote_identity = Some(identity);
+		Some(peer_id)
+	}
+	/* The write functions return None if our static key is held by an oracle that fails. */
+	pub fn write_message_a(&mut self) -> Option<Vec<u8>> {
+		let message: MessageBuffer = self.hs.write_message_a(&ZEROLEN[..])?;
+		Some(frame(&message.ne[..]))
+	}
+	pub fn read_message_a(&mut self, packet: &[u8]) -> Option<()> {
+		let message: &[u8] = unframe(packet)?;
+		if message.len() != DHLEN {
+			return None;
+		}
+		self.hs.read_message_a(&mut MessageBuffer {
+			ne: read_key(message),
+			ns: Vec::new(),
+			ciphertext: Vec::new(),
+		})?;
+		Some(())
+	}
+	pub fn write_message_b(&mut self) -> Option<Vec<u8>> {
+		let payload: Vec<u8> = self.payload();
+		let message: MessageBuffer = self.hs.write_message_b(&payload[..])?;
+		let mut out: Vec<u8> = message.ne.to_vec();
+		out.extend(message.ns);
+		out.extend(message.ciphertext);
+		Some(frame(&out[..]))
+	}
+	pub fn read_message_b(&mut self, packet: &[u8]) -> Option<PeerId> {
+		let message: &[u8] = unframe(packet)?;
+		if message.len() < 2 * DHLEN + 2 * MAC_LENGTH {
+			return None;
+		}
+		let payload: Vec<u8> = self.hs.read_message_b(&mut MessageBuffer {
+			ne: read_key(&message[..DHLEN]),
+			ns: message[DHLEN..2 * DHLEN + MAC_LENGTH].to_vec(),
+			ciphertext: message[2 * DHLEN + MAC_LENGTH..].to_vec(),
+		})?;
+		self.read_identity(&payload[..])
+	}
+	pub fn write_message_c(&mut self) -> Option<(Vec<u8>, Transport)> {
+		let payload: Vec<u8> = self.payload();
+		let written = self.hs.write_message_c(&payload[..]);
+		self.hs.clear();
+		let (_, message, cs1, cs2, _) = written?;
+		let mut out: Vec<u8> = message.ns;
+		out.extend(message.ciphertext);
+		Some((frame(&out[..]), Transport { sender: cs1, receiver: cs2 }))
+	}
+	pub fn read_message_c(&mut self, packet: &[u8]) -> Option<(PeerId, Transport)> {
+		let message: &[u8] = unframe(packet)?;
+		if message.len() < DHLEN + 2 * MAC_LENGTH {
+			return None;
+		}
+		let (_, payload, cs1, cs2, _) = self.hs.read_message_c(&mut MessageBuffer {
+			ne: [0u8; DHLEN],
+			ns: message[..DHLEN + MAC_LENGTH].to_vec(),
+			ciphertext: message[DHLEN + MAC_LENGTH..].to_vec(),
+		})?;
+		let peer_id: Option<PeerId> = self.read_identity(&payload[..]);
+		self.hs.clear();
+		Some((peer_id?, Transport { sender: cs2, receiver: cs1 }))
+	}
+}
+
+/* ---------------------------------------------------------------- *
+ * TRANSPORT                                                        *
+ * ---------------------------------------------------------------- */
+
+pub struct Transport {
+	sender: CipherState,
+	receiver: CipherState,
+}
+impl Transport {
+	pub fn write_message(&mut self, payload: &[u8]) -> Vec<u8> {
+		assert!(payload.len() <= MAX_PAYLOAD_LENGTH, "Payload too long");
+		frame(&self.sender.encrypt_with_ad(&ZEROLEN[..], payload)[..])
+	}
+	pub fn read_message(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+		let message: &[u8] = unframe(packet)?;
+		if message.len() < MAC_LENGTH {
+			return None;
+		}
+		self.receiver.decrypt_with_ad(&ZEROLEN[..], message)
+	}
+}
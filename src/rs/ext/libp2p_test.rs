@@ -0,0 +1,100 @@
+#![allow(non_snake_case, non_upper_case_globals)]
+
+use noiseexplorer_$NOISE2RS_N$::{
+	libp2p::{Handshake, IdentityKeypair, PeerId, Transport},
+	types::{Keypair, PrivateKey},
+};
+
+fn decode(s: &str) -> Vec<u8> {
+	hex::decode(s).unwrap()
+}
+
+fn secret(s: &str) -> [u8; 32] {
+	let mut k = [0u8; 32];
+	k.copy_from_slice(&decode(s)[..]);
+	k
+}
+
+fn handshake_pair(responder_identity: IdentityKeypair) -> (Handshake, Handshake) {
+	let initiator_identity: IdentityKeypair = IdentityKeypair::ed25519_from_bytes(secret("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60"));
+	let mut initiator: Handshake = Handshake::initiator(&[], Keypair::from_private_key(PrivateKey::from_str("c0c1c2c3c4c5c6c7c8c9cacbcccdcecfd0d1d2d3d4d5d6d7d8d9dadbdcdddedf")), initiator_identity);
+	let mut responder: Handshake = Handshake::responder(&[], Keypair::from_private_key(PrivateKey::from_str("e0e1e2e3e4e5e6e7e8e9eaebecedeeeff0f1f2f3f4f5f6f7f8f9fafbfcfdfeff")), responder_identity);
+	initiator.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("a0a1a2a3a4a5a6a7a8a9aaabacadaeafb0b1b2b3b4b5b6b7b8b9babbbcbdbebf")));
+	responder.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("8081828384858687888990919293949596979899a0a1a2a3a4a5a6a7a8a9aaab")));
+	(initiator, responder)
+}
+
+fn ed25519_responder() -> IdentityKeypair {
+	IdentityKeypair::ed25519_from_bytes(secret("4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb"))
+}
+
+fn secp256k1_responder() -> IdentityKeypair {
+	IdentityKeypair::secp256k1_from_bytes(secret("0101010101010101010101010101010101010101010101010101010101010101")).unwrap()
+}
+
+fn run_transcript(responder_identity: IdentityKeypair, message_b: &str, message_c: &str, responder_peer_id: &str) {
+	let (mut initiator, mut responder) = handshake_pair(responder_identity);
+	let message_a: Vec<u8> = initiator.write_message_a().unwrap();
+	assert_eq!(message_a, decode("0020605a725d2a4adfeeb1a29e17edd621c1b7593ee8cdbc44ac6c4ab6e2f805d23c"));
+	assert!(responder.read_message_a(&message_a[..]).is_some());
+	let packet: Vec<u8> = responder.write_message_b().unwrap();
+	assert_eq!(packet, decode(message_b));
+	let peer_id: PeerId = initiator.read_message_b(&packet[..]).unwrap();
+	assert_eq!(peer_id.to_base58(), responder_peer_id);
+	let (packet, mut initiator_transport) = initiator.write_message_c().unwrap();
+	assert_eq!(packet, decode(message_c));
+	let (peer_id, mut responder_transport) = responder.read_message_c(&packet[..]).unwrap();
+	assert_eq!(peer_id.to_base58(), "12D3KooWQK1wnefoLrcVHbbnf5tLzbopUd3K3bFAoJpA7YJgL5pV");
+	assert_eq!(responder.get_remote_peer_id(), Some(peer_id));
+	let packet: Vec<u8> = initiator_transport.write_message(b"/yamux/1.0.0\n");
+	assert_eq!(packet, decode("001dcaf06c5789f59fde9d4d559e4308de3804e362c968b42b28c10e489776"));
+	assert_eq!(responder_transport.read_message(&packet[..]).unwrap(), b"/yamux/1.0.0\n".to_vec());
+	let packet: Vec<u8> = responder_transport.write_message(b"/yamux/1.0.0\n");
+	assert_eq!(packet, decode("001dd59e004a6d1a3a05a543851cbc89d544a4448bdd5184c318c629e3a113"));
+	assert_eq!(initiator_transport.read_message(&packet[..]).unwrap(), b"/yamux/1.0.0\n".to_vec());
+}
+
+/* Transcripts recorded from snow with libp2p's handshake parameters, with
+ * identity keys, signatures and peer IDs produced by libp2p-identity. */
+#[test]
+fn noiseexplorer_test_libp2p_ed25519_transcript() {
+	run_transcript(
+		ed25519_responder(),
+		"00c854588d6342b67f4b724114d7ee9e382c9fe8335abb79328a4e64c6c62658b85c39b4f93863c01576343809279750537d939bb8a23917a5acc716198fe6bee9e64845092f3c2e58c83687834f9388f66fa8ec4027d72ed29ccc29bad7f98e02ab01c26915317a20018aaab69fb65cb9a23bfa22abf0ddb8f216ebbd7a36ac632e9ccce3959972daaaabc77b7b516000ef2122fc2488d9053ecea75c0111af97b4bd5fc4ad888c2fb4a72c825ba48c87cac939981ced3121facc5ead99ecf7ec98b890320aac6d5f84",
+		"00a8d71a778c3cdd957128eeb3b7d623eef98ac8d322d179f231cba94a999a0877d33709d52ed1d72e7c6d3b28190ab56688c5a8ec4f115d7a488ab85ea2c565bb35517d06535d79cca2b5c68b242b3c25ddb55f95e938c6a0bd8fc88ec5490c75fc2e721eaa6c6547811bdd61656368a7feef6334a15c7889842f8be75672217c1e716a227c43a73632a68c8a7de23ceb82f4ce0572a62882aa43ac14a19f663006b3bfa897737dc399",
+		"12D3KooWDwTirQce1RRKnasT5fPVFgzXCy6SiRgSwrwPGLC7zE91",
+	);
+}
+
+#[test]
+fn noiseexplorer_test_libp2p_secp256k1_transcript() {
+	run_transcript(
+		secp256k1_responder(),
+		"00d054588d6342b67f4b724114d7ee9e382c9fe8335abb79328a4e64c6c62658b85c39b4f93863c01576343809279750537d939bb8a23917a5acc716198fe6bee9e64845092f3c2e58c83687834f9388f66fa8ed4024d72fecc75f2f04ef62b0d085565bf1a4f5c3d94eb87dac3b68c986f379fee18a4bd625a02f9817774a93538b8887bc016b73ccbc03df4cfc141c0f44b50e2a6742c3526c8d404d58db7bc1d2e6dc1057bf72650b127b3f8c1255d950be6ea3f2a1600927e7148fdd53de9577e1d644d881adbabf80f4e1364578f719",
+		"00a8d71a778c3cdd957128eeb3b7d623eef98ac8d322d179f231cba94a999a0877d32ff9ccc630cab92ffed87d38f57af85cc5a8ec4f115d7a488ab85ea2c565bb35517d06535d79cca2b5c68b242b3c25ddb55f95e938c6a0bd8fc88ec5490c75fc2e721eaa6c6547811bdd61656368a7feef6334a15c7889842f8be75672217c1e716a227c43a73632a68c8a7de23ceb82f4ce0572a62882aa68d85efac352a6490ba72c56516d3465",
+		"16Uiu2HAmEWQnHq2jLKJypwVnVoQeFCULuyop6atvq2eWjYSUjzNi",
+	);
+}
+
+#[test]
+fn noiseexplorer_test_libp2p_rejects_invalid() {
+	let (mut initiator, mut responder) = handshake_pair(ed25519_responder());
+	let message_a: Vec<u8> = initiator.write_message_a().unwrap();
+	responder.read_message_a(&message_a[..]).unwrap();
+	let mut packet: Vec<u8> = responder.write_message_b().unwrap();
+	let last = packet.len() - 1;
+	packet[last] ^= 0x01;
+	assert!(initiator.read_message_b(&packet[..]).is_none());
+
+	let (mut initiator, mut responder) = handshake_pair(ed25519_responder());
+	let message_a: Vec<u8> = initiator.write_message_a().unwrap();
+	assert!(responder.read_message_a(&message_a[1..]).is_none());
+	responder.read_message_a(&message_a[..]).unwrap();
+	let packet: Vec<u8> = responder.write_message_b().unwrap();
+	initiator.read_message_b(&packet[..]).unwrap();
+	let (packet, mut initiator_transport): (Vec<u8>, Transport) = initiator.write_message_c().unwrap();
+	let (_, mut responder_transport) = responder.read_message_c(&packet[..]).unwrap();
+	let mut packet: Vec<u8> = initiator_transport.write_message(b"hello");
+	packet[2] ^= 0x01;
+	assert!(responder_transport.read_message(&packet[..]).is_none());
+}
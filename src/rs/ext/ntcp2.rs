@@ -0,0 +1,344 @@
+/* ---------------------------------------------------------------- *
+ * NTCP2                                                            *
+ * ---------------------------------------------------------------- */
+
+/* NTCP2 runs Noise_XKaesobfse+hs2+hs3_25519_ChaChaPoly_SHA256: the messages
+ * below are produced by the generated XK HandshakeState over a SHA-256
+ * SymmetricState, with AES-obfuscated ephemeral keys and padding mixed into
+ * the handshake hash of the following message. */
+
+use crate::{
+	consts::{DHLEN, HASHLEN, MAC_LENGTH, ZEROLEN},
+	prims::{HashFunction, Sha256Hash},
+	state::{CipherState, HandshakeState, Symmetric, SymmetricState},
+	types::{Keypair, MessageBuffer, Psk, PublicKey},
+};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use crypto::{
+	aessafe::{AesSafe256Decryptor, AesSafe256Encryptor},
+	symmetriccipher::{BlockDecryptor, BlockEncryptor},
+};
+use siphasher::sip::SipHasher24;
+
+const PROTOCOL_NAME: &[u8] = b"Noise_XKaesobfse+hs2+hs3_25519_ChaChaPoly_SHA256";
+const VERSION: u8 = 2;
+const OPTIONS_LENGTH: usize = 16;
+const AES_BLOCK_LENGTH: usize = 16;
+
+pub const SESSION_REQUEST_LENGTH: usize = DHLEN + OPTIONS_LENGTH + MAC_LENGTH;
+pub const SESSION_CREATED_LENGTH: usize = DHLEN + OPTIONS_LENGTH + MAC_LENGTH;
+pub const SESSION_CONFIRMED_PART_ONE_LENGTH: usize = DHLEN + MAC_LENGTH;
+pub const LENGTH_HEADER_LENGTH: usize = 2;
+pub const MAX_FRAME_LENGTH: usize = 65535;
+pub const MAX_PAYLOAD_LENGTH: usize = MAX_FRAME_LENGTH - MAC_LENGTH;
+
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> [u8; HASHLEN] {
+	let mut out = [0u8; HASHLEN];
+	Sha256Hash::hmac(key, &data.concat()[..], &mut out[..]);
+	out
+}
+
+/* AES-256-CBC over the 32-byte ephemeral key, returning the final block
+ * as the chaining value for the next message. */
+fn aes_encrypt(key: &[u8; 32], iv: &[u8; AES_BLOCK_LENGTH], plaintext: &[u8; DHLEN]) -> ([u8; DHLEN], [u8; AES_BLOCK_LENGTH]) {
+	let aes: AesSafe256Encryptor = AesSafe256Encryptor::new(&key[..]);
+	let mut ciphertext = [0u8; DHLEN];
+	let mut previous: [u8; AES_BLOCK_LENGTH] = *iv;
+	for (input, output) in plaintext.chunks(AES_BLOCK_LENGTH).zip(ciphertext.chunks_mut(AES_BLOCK_LENGTH)) {
+		let mut block = [0u8; AES_BLOCK_LENGTH];
+		for ((b, i), p) in block.iter_mut().zip(input).zip(&previous) {
+			*b = i ^ p;
+		}
+		aes.encrypt_block(&block[..], output);
+		previous.copy_from_slice(output);
+	}
+	(ciphertext, previous)
+}
+
+fn aes_decrypt(key: &[u8; 32], iv: &[u8; AES_BLOCK_LENGTH], ciphertext: &[u8]) -> ([u8; DHLEN], [u8; AES_BLOCK_LENGTH]) {
+	let aes: AesSafe256Decryptor = AesSafe256Decryptor::new(&key[..]);
+	let mut plaintext = [0u8; DHLEN];
+	let mut previous: [u8; AES_BLOCK_LENGTH] = *iv;
+	for (input, output) in ciphertext[..DHLEN].chunks(AES_BLOCK_LENGTH).zip(plaintext.chunks_mut(AES_BLOCK_LENGTH)) {
+		aes.decrypt_block(input, output);
+		for (o, p) in output.iter_mut().zip(&previous) {
+			*o ^= p;
+		}
+		previous.copy_from_slice(input);
+	}
+	(plaintext, previous)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SessionRequest {
+	pub network_id: u8,
+	pub version: u8,
+	pub padding_length: u16,
+	pub message3_part2_length: u16,
+	pub timestamp: u32,
+}
+impl SessionRequest {
+	fn to_bytes(self) -> [u8; OPTIONS_LENGTH] {
+		let mut options = [0u8; OPTIONS_LENGTH];
+		options[0] = self.network_id;
+		options[1] = self.version;
+		BigEndian::write_u16(&mut options[2..4], self.padding_length);
+		BigEndian::write_u16(&mut options[4..6], self.message3_part2_length);
+		BigEndian::write_u32(&mut options[8..12], self.timestamp);
+		options
+	}
+	fn from_bytes(options: &[u8]) -> SessionRequest {
+		SessionRequest {
+			network_id: options[0],
+			version: options[1],
+			padding_length: BigEndian::read_u16(&options[2..4]),
+			message3_part2_length: BigEndian::read_u16(&options[4..6]),
+			timestamp: BigEndian::read_u32(&options[8..12]),
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SessionCreated {
+	pub padding_length: u16,
+	pub timestamp: u32,
+}
+impl SessionCreated {
+	fn to_bytes(self) -> [u8; OPTIONS_LENGTH] {
+		let mut options = [0u8; OPTIONS_LENGTH];
+		BigEndian::write_u16(&mut options[2..4], self.padding_length);
+		BigEndian::write_u32(&mut options[8..12], self.timestamp);
+		options
+	}
+	fn from_bytes(options: &[u8]) -> SessionCreated {
+		SessionCreated {
+			padding_length: BigEndian::read_u16(&options[2..4]),
+			timestamp: BigEndian::read_u32(&options[8..12]),
+		}
+	}
+}
+
+/* ---------------------------------------------------------------- *
+ * HANDSHAKE                                                        *
+ * ---------------------------------------------------------------- */
+
+/* The additional SipHash key derivation from the final chaining key and
+ * handshake hash, following Split. */
+fn transport(ck: &[u8], h: &[u8], cs1: CipherState, cs2: CipherState) -> (Direction, Direction) {
+	let temp_key: [u8; HASHLEN] = hmac_sha256(ck, &[&ZEROLEN[..]]);
+	let ask_master: [u8; HASHLEN] = hmac_sha256(&temp_key[..], &[b"ask", &[0x01]]);
+	let temp_key: [u8; HASHLEN] = hmac_sha256(&ask_master[..], &[h, b"siphash"]);
+	let sip_master: [u8; HASHLEN] = hmac_sha256(&temp_key[..], &[&[0x01]]);
+	let temp_key: [u8; HASHLEN] = hmac_sha256(&sip_master[..], &[&ZEROLEN[..]]);
+	let sipkeys_ab: [u8; HASHLEN] = hmac_sha256(&temp_key[..], &[&[0x01]]);
+	let sipkeys_ba: [u8; HASHLEN] = hmac_sha256(&temp_key[..], &[&sipkeys_ab[..], &[0x02]]);
+	(Direction::new(cs1, &sipkeys_ab), Direction::new(cs2, &sipkeys_ba))
+}
+
+/* router_hash and iv are the responder's router hash and the AES IV it
+ * publishes alongside its static key. */
+pub struct Handshake {
+	hs: HandshakeState<SymmetricState<Sha256Hash>>,
+	rs: PublicKey,
+	router_hash: [u8; 32],
+	aes_iv: [u8; AES_BLOCK_LENGTH],
+	padding_length: usize,
+	message3_part2_length: usize,
+}
+impl Handshake {
+	fn new(hs: HandshakeState<SymmetricState<Sha256Hash>>, rs: PublicKey, router_hash: [u8; 32], iv: [u8; AES_BLOCK_LENGTH]) -> Handshake {
+		Handshake {
+			hs,
+			rs,
+			router_hash,
+			aes_iv: iv,
+			padding_length: 0,
+			message3_part2_length: 0,
+		}
+	}
+	pub fn initiator(s: Keypair, rs: PublicKey, router_hash: [u8; 32], iv: [u8; AES_BLOCK_LENGTH]) -> Handshake {
+		let hs = HandshakeState::initialize_initiator_with_name(PROTOCOL_NAME, &ZEROLEN[..], s, rs, Psk::new());
+		Handshake::new(hs, rs, router_hash, iv)
+	}
+	pub fn responder(s: Keypair, router_hash: [u8; 32], iv: [u8; AES_BLOCK_LENGTH]) -> Handshake {
+		let hs = HandshakeState::initialize_responder_with_name(PROTOCOL_NAME, &ZEROLEN[..], s, PublicKey::empty(), Psk::new());
+		Handshake::new(hs, PublicKey::empty(), router_hash, iv)
+	}
+	pub fn set_ephemeral_keypair(&mut self, e: Keypair) {
+		self.hs.set_ephemeral_keypair(e);
+	}
+	pub fn get_remote_static_public_key(&self) -> PublicKey {
+		self.rs
+	}
+	fn obfuscate(&mut self, ne: &[u8; DHLEN]) -> [u8; DHLEN] {
+		let (obfuscated, aes_iv) = aes_encrypt(&self.router_hash, &self.aes_iv, ne);
+		self.aes_iv = aes_iv;
+		obfuscated
+	}
+	fn deobfuscate(&mut self, message: &[u8]) -> [u8; DHLEN] {
+		let (re, aes_iv) = aes_decrypt(&self.router_hash, &self.aes_iv, message);
+		self.aes_iv = aes_iv;
+		re
+	}
+	fn write_padding(&mut self, message: MessageBuffer, padding: &[u8]) -> Vec<u8> {
+		let mut out: Vec<u8> = self.obfuscate(&message.ne).to_vec();
+		out.extend(message.ciphertext);
+		out.extend(padding);
+		self.hs.ss.mix_hash(padding);
+		out
+	}
+	/* The padding is sent in the clear and mixed into h by the next message.
+	 * The write functions return None if our static key is held by an oracle that fails. */
+	pub fn write_session_request(&mut self, network_id: u8, message3_part2_length: u16, timestamp: u32, padding: &[u8]) -> Option<Vec<u8>> {
+		assert!(padding.len() <= MAX_FRAME_LENGTH, "Padding too long");
+		let options = SessionRequest {
+			network_id,
+			version: VERSION,
+			padding_length: padding.len() as u16,
+			message3_part2_length,
+			timestamp,
+		};
+		let message: MessageBuffer = self.hs.write_message_a(&options.to_bytes()[..])?;
+		self.message3_part2_length = message3_part2_length as usize;
+		Some(self.write_padding(message, padding))
+	}
+	/* Takes the first SESSION_REQUEST_LENGTH bytes; the padding that
+	 * follows must then be passed to read_padding. */
+	pub fn read_session_request(&mut self, message: &[u8]) -> Option<SessionRequest> {
+		if message.len() != SESSION_REQUEST_LENGTH {
+			return None;
+		}
+		let ne: [u8; DHLEN] = self.deobfuscate(message);
+		let options: SessionRequest = SessionRequest::from_bytes(&self.hs.read_message_a(&mut MessageBuffer {
+			ne,
+			ns: Vec::new(),
+			ciphertext: message[DHLEN..].to_vec(),
+		})?[..]);
+		if options.version != VERSION || (options.message3_part2_length as usize) < MAC_LENGTH {
+			return None;
+		}
+		self.padding_length = options.padding_length as usize;
+		self.message3_part2_length = options.message3_part2_length as usize;
+		Some(options)
+	}
+	pub fn read_padding(&mut self, padding: &[u8]) -> Option<()> {
+		if padding.len() != self.padding_length {
+			return None;
+		}
+		self.hs.ss.mix_hash(padding);
+		self.padding_length = 0;
+		Some(())
+	}
+	pub fn write_session_created(&mut self, timestamp: u32, padding: &[u8]) -> Option<Vec<u8>> {
+		assert!(self.padding_length == 0, "Session request padding not read");
+		assert!(padding.len() <= MAX_FRAME_LENGTH, "Padding too long");
+		let options = SessionCreated {
+			padding_length: padding.len() as u16,
+			timestamp,
+		};
+		let message: MessageBuffer = self.hs.write_message_b(&options.to_bytes()[..])?;
+		Some(self.write_padding(message, padding))
+	}
+	/* Takes the first SESSION_CREATED_LENGTH bytes, as for read_session_request. */
+	pub fn read_session_created(&mut self, message: &[u8]) -> Option<SessionCreated> {
+		if message.len() != SESSION_CREATED_LENGTH {
+			return None;
+		}
+		let ne: [u8; DHLEN] = self.deobfuscate(message);
+		let options: SessionCreated = SessionCreated::from_bytes(&self.hs.read_message_b(&mut MessageBuffer {
+			ne,
+			ns: Vec::new(),
+			ciphertext: message[DHLEN..].to_vec(),
+		})?[..]);
+		self.padding_length = options.padding_length as usize;
+		Some(options)
+	}
+	/* The payload, typically a RouterInfo block, must match the
+	 * message3_part2_length announced in the session request. */
+	pub fn write_session_confirmed(&mut self, payload: &[u8]) -> Option<(Vec<u8>, Transport)> {
+		assert!(self.padding_length == 0, "Session created padding not read");
+		assert!(payload.len() + MAC_LENGTH == self.message3_part2_length, "Payload length mismatch");
+		let written = self.hs.write_message_c(payload);
+		self.hs.clear();
+		let (h, message, cs1, cs2, ck) = written?;
+		let (ab, ba) = transport(&ck.as_bytes()[..], &h.as_bytes()[..], cs1, cs2);
+		let mut out: Vec<u8> = message.ns;
+		out.extend(message.ciphertext);
+		Some((out, Transport { sender: ab, receiver: ba }))
+	}
+	pub fn read_session_confirmed(&mut self, message: &[u8]) -> Option<(Vec<u8>, Transport)> {
+		if message.len() != SESSION_CONFIRMED_PART_ONE_LENGTH + self.message3_part2_length {
+			return None;
+		}
+		let (h, payload, cs1, cs2, ck) = self.hs.read_message_c(&mut MessageBuffer {
+			ne: [0u8; DHLEN],
+			ns: message[..SESSION_CONFIRMED_PART_ONE_LENGTH].to_vec(),
+			ciphertext: message[SESSION_CONFIRMED_PART_ONE_LENGTH..].to_vec(),
+		})?;
+		self.rs = self.hs.get_remote_static_public_key();
+		self.hs.clear();
+		let (ab, ba) = transport(&ck.as_bytes()[..], &h.as_bytes()[..], cs1, cs2);
+		Some((payload, Transport { sender: ba, receiver: ab }))
+	}
+}
+
+/* ---------------------------------------------------------------- *
+ * TRANSPORT                                                        *
+ * ---------------------------------------------------------------- */
+
+struct Direction {
+	cs: CipherState,
+	sip: SipHasher24,
+	iv: [u8; 8],
+}
+impl Direction {
+	fn new(cs: CipherState, sipkeys: &[u8; 32]) -> Direction {
+		let mut key = [0u8; 16];
+		key.copy_from_slice(&sipkeys[..16]);
+		let mut iv = [0u8; 8];
+		iv.copy_from_slice(&sipkeys[16..24]);
+		Direction {
+			cs,
+			sip: SipHasher24::new_with_key(&key),
+			iv,
+		}
+	}
+	/* Each frame length is masked with the first two bytes of the next IV in the SipHash chain. */
+	fn next_mask(&mut self) -> u16 {
+		let iv: u64 = self.sip.hash(&self.iv[..]);
+		LittleEndian::write_u64(&mut self.iv, iv);
+		LittleEndian::read_u16(&self.iv[..2])
+	}
+}
+
+pub struct Transport {
+	sender: Direction,
+	receiver: Direction,
+}
+impl Transport {
+	pub fn write_message(&mut self, payload: &[u8]) -> Vec<u8> {
+		assert!(payload.len() <= MAX_PAYLOAD_LENGTH, "Payload too long");
+		let ciphertext: Vec<u8> = self.sender.cs.encrypt_with_ad(&ZEROLEN[..], payload);
+		let mut out = vec![0u8; LENGTH_HEADER_LENGTH];
+		BigEndian::write_u16(&mut out[..], ciphertext.len() as u16 ^ self.sender.next_mask());
+		out.extend(ciphertext);
+		out
+	}
+	/* Returns the length of the frame that follows the header. */
+	pub fn read_length(&mut self, header: &[u8]) -> Option<usize> {
+		if header.len() != LENGTH_HEADER_LENGTH {
+			return None;
+		}
+		let length = (BigEndian::read_u16(header) ^ self.receiver.next_mask()) as usize;
+		if length < MAC_LENGTH {
+			return None;
+		}
+		Some(length)
+	}
+	pub fn read_message(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+		if frame.len() < MAC_LENGTH {
+			return None;
+		}
+		self.receiver.cs.decrypt_with_ad(&ZEROLEN[..], frame)
+	}
+}
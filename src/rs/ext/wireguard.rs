@@ -0,0 +1,469 @@
+/* ---------------------------------------------------------------- *
+ * WIREGUARD                                                        *
+ * ---------------------------------------------------------------- */
+
+use crate::{
+	consts::{DHLEN, MAC_LENGTH, ZEROLEN},
+	prims::{decrypt, encrypt, hash},
+	state::{CipherState, HandshakeState},
+	types::{Keypair, MessageBuffer, Nonce, Psk, PublicKey},
+};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use crypto::{blake2s::Blake2s, digest::Digest};
+use rand::RngCore;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const IDENTIFIER: &[u8] = b"WireGuard v1 zx2c4 Jason@zx2c4.com";
+const LABEL_MAC1: &[u8] = b"mac1----";
+const LABEL_COOKIE: &[u8] = b"cookie--";
+
+pub const MESSAGE_HANDSHAKE_INITIATION: u8 = 1;
+pub const MESSAGE_HANDSHAKE_RESPONSE: u8 = 2;
+pub const MESSAGE_HANDSHAKE_COOKIE: u8 = 3;
+pub const MESSAGE_TRANSPORT_DATA: u8 = 4;
+
+pub const HANDSHAKE_INITIATION_LENGTH: usize = 148;
+pub const HANDSHAKE_RESPONSE_LENGTH: usize = 92;
+pub const COOKIE_REPLY_LENGTH: usize = 64;
+pub const TRANSPORT_HEADER_LENGTH: usize = 16;
+
+const TIMESTAMP_LENGTH: usize = 12;
+const COOKIE_LENGTH: usize = 16;
+const COOKIE_NONCE_LENGTH: usize = 24;
+const PADDING_MULTIPLE: usize = 16;
+const TAI64_EPOCH: u64 = 0x4000_0000_0000_000a;
+const COOKIE_MAX_AGE: Duration = Duration::from_secs(120);
+const REJECT_AFTER_MESSAGES: u64 = u64::MAX - (1 << 13);
+const REPLAY_WINDOW: u64 = 2048;
+
+fn mac(key: &[u8], data: &[u8]) -> [u8; COOKIE_LENGTH] {
+	let mut blake2s: Blake2s = Blake2s::new_keyed(COOKIE_LENGTH, key);
+	blake2s.input(data);
+	let mut out = [0u8; COOKIE_LENGTH];
+	blake2s.result(&mut out);
+	out
+}
+
+fn labelled_key(label: &[u8], public_key: &PublicKey) -> [u8; DHLEN] {
+	let mut temp: Vec<u8> = Vec::from(label);
+	temp.extend(&public_key.as_bytes()[..]);
+	let mut key = [0u8; DHLEN];
+	key.copy_from_slice(&hash(&temp)[..DHLEN]);
+	key
+}
+
+fn hchacha20(key: &[u8; DHLEN], nonce: &[u8]) -> [u8; DHLEN] {
+	let mut x: [u32; 16] = [0u32; 16];
+	x[0] = 0x6170_7865;
+	x[1] = 0x3320_646e;
+	x[2] = 0x7962_2d32;
+	x[3] = 0x6b20_6574;
+	for i in 0..8 {
+		x[4 + i] = LittleEndian::read_u32(&key[i * 4..(i + 1) * 4]);
+	}
+	for i in 0..4 {
+		x[12 + i] = LittleEndian::read_u32(&nonce[i * 4..(i + 1) * 4]);
+	}
+	fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+		x[a] = x[a].wrapping_add(x[b]);
+		x[d] = (x[d] ^ x[a]).rotate_left(16);
+		x[c] = x[c].wrapping_add(x[d]);
+		x[b] = (x[b] ^ x[c]).rotate_left(12);
+		x[a] = x[a].wrapping_add(x[b]);
+		x[d] = (x[d] ^ x[a]).rotate_left(8);
+		x[c] = x[c].wrapping_add(x[d]);
+		x[b] = (x[b] ^ x[c]).rotate_left(7);
+	}
+	for _ in 0..10 {
+		quarter_round(&mut x, 0, 4, 8, 12);
+		quarter_round(&mut x, 1, 5, 9, 13);
+		quarter_round(&mut x, 2, 6, 10, 14);
+		quarter_round(&mut x, 3, 7, 11, 15);
+		quarter_round(&mut x, 0, 5, 10, 15);
+		quarter_round(&mut x, 1, 6, 11, 12);
+		quarter_round(&mut x, 2, 7, 8, 13);
+		quarter_round(&mut x, 3, 4, 9, 14);
+	}
+	let mut out = [0u8; DHLEN];
+	for i in 0..4 {
+		LittleEndian::write_u32(&mut out[i * 4..(i + 1) * 4], x[i]);
+		LittleEndian::write_u32(&mut out[16 + i * 4..16 + (i + 1) * 4], x[12 + i]);
+	}
+	out
+}
+
+fn xaead_encrypt(key: &[u8; DHLEN], nonce: &[u8], ad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+	let subkey = hchacha20(key, &nonce[..16]);
+	encrypt(subkey, LittleEndian::read_u64(&nonce[16..COOKIE_NONCE_LENGTH]), ad, plaintext)
+}
+
+fn xaead_decrypt(key: &[u8; DHLEN], nonce: &[u8], ad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+	let subkey = hchacha20(key, &nonce[..16]);
+	decrypt(subkey, LittleEndian::read_u64(&nonce[16..COOKIE_NONCE_LENGTH]), ad, ciphertext)
+}
+
+fn message_header(message_type: u8) -> Vec<u8> {
+	vec![message_type, 0u8, 0u8, 0u8]
+}
+
+fn check_header(packet: &[u8], message_type: u8, length: usize) -> bool {
+	packet.len() == length && packet[..4] == message_header(message_type)[..]
+}
+
+fn write_index(packet: &mut Vec<u8>, index: u32) {
+	let mut temp = [0u8; 4];
+	LittleEndian::write_u32(&mut temp, index);
+	packet.extend(&temp[..]);
+}
+
+fn verify_mac1(packet: &[u8], mac1_key: &[u8; DHLEN]) -> bool {
+	let mac1_offset = packet.len() - 2 * COOKIE_LENGTH;
+	let expected = mac(&mac1_key[..], &packet[..mac1_offset]);
+	crypto::util::fixed_time_eq(&expected[..], &packet[mac1_offset..mac1_offset + COOKIE_LENGTH])
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Tai64N {
+	t: [u8; TIMESTAMP_LENGTH],
+}
+impl Tai64N {
+	pub fn now() -> Tai64N {
+		let since_epoch = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or(Duration::from_secs(0));
+		let mut t = [0u8; TIMESTAMP_LENGTH];
+		BigEndian::write_u64(&mut t[..8], TAI64_EPOCH + since_epoch.as_secs());
+		BigEndian::write_u32(&mut t[8..], since_epoch.subsec_nanos());
+		Tai64N { t }
+	}
+	pub fn from_bytes(t: [u8; TIMESTAMP_LENGTH]) -> Tai64N {
+		Tai64N { t }
+	}
+	pub fn as_bytes(&self) -> [u8; TIMESTAMP_LENGTH] {
+		self.t
+	}
+}
+
+#[derive(Clone)]
+struct ReplayWindow {
+	next: u64,
+	bitmap: [u64; (REPLAY_WINDOW / 64) as usize],
+}
+impl ReplayWindow {
+	fn new() -> ReplayWindow {
+		ReplayWindow {
+			next: 0,
+			bitmap: [0u64; (REPLAY_WINDOW / 64) as usize],
+		}
+	}
+	fn is_set(&self, counter: u64) -> bool {
+		let bit = counter % REPLAY_WINDOW;
+		self.bitmap[(bit / 64) as usize] & (1u64 << (bit % 64)) != 0
+	}
+	fn check(&self, counter: u64) -> bool {
+		if counter >= REJECT_AFTER_MESSAGES {
+			return false;
+		}
+		if counter >= self.next {
+			return true;
+		}
+		if counter + REPLAY_WINDOW <= self.next {
+			return false;
+		}
+		!self.is_set(counter)
+	}
+	fn update(&mut self, counter: u64) {
+		if counter >= self.next {
+			if counter - self.next >= REPLAY_WINDOW {
+				self.bitmap = [0u64; (REPLAY_WINDOW / 64) as usize];
+			} else {
+				for stale in self.next..counter {
+					let bit = stale % REPLAY_WINDOW;
+					self.bitmap[(bit / 64) as usize] &= !(1u64 << (bit % 64));
+				}
+			}
+			self.next = counter + 1;
+		}
+		let bit = counter % REPLAY_WINDOW;
+		self.bitmap[(bit / 64) as usize] |= 1u64 << (bit % 64);
+	}
+}
+
+pub struct Handshake {
+	initial: HandshakeState,
+	hs: HandshakeState,
+	local_static: PublicKey,
+	remote_static: PublicKey,
+	local_index: u32,
+	remote_index: u32,
+	last_mac1: Option<[u8; COOKIE_LENGTH]>,
+	cookie: Option<([u8; COOKIE_LENGTH], Instant)>,
+}
+impl Handshake {
+	pub fn initiator(s: Keypair, rs: PublicKey, psk: Psk, local_index: u32) -> Handshake {
+		let local_static = s.get_public_key();
+		let hs = HandshakeState::initialize_initiator(IDENTIFIER, s, rs, psk);
+		Handshake {
+			initial: hs.clone(),
+			hs,
+			local_static,
+			remote_static: rs,
+			local_index,
+			remote_index: 0,
+			last_mac1: None,
+			cookie: None,
+		}
+	}
+	pub fn responder(s: Keypair, psk: Psk, local_index: u32) -> Handshake {
+		let local_static = s.get_public_key();
+		let hs = HandshakeState::initialize_responder(IDENTIFIER, s, PublicKey::empty(), psk);
+		Handshake {
+			initial: hs.clone(),
+			hs,
+			local_static,
+			remote_static: PublicKey::empty(),
+			local_index,
+			remote_index: 0,
+			last_mac1: None,
+			cookie: None,
+		}
+	}
+	pub fn set_ephemeral_keypair(&mut self, e: Keypair) {
+		self.initial.set_ephemeral_keypair(e);
+	}
+	pub fn get_remote_static_public_key(&self) -> PublicKey {
+		self.remote_static
+	}
+	pub fn get_local_index(&self) -> u32 {
+		self.local_index
+	}
+	pub fn get_remote_index(&self) -> u32 {
+		self.remote_index
+	}
+	fn clear(&mut self) {
+		self.initial.clear();
+		self.hs.clear();
+	}
+	fn append_macs(&mut self, packet: &mut Vec<u8>) {
+		let mac1 = mac(&labelled_key(LABEL_MAC1, &self.remote_static)[..], &packet[..]);
+		packet.extend(&mac1[..]);
+		self.last_mac1 = Some(mac1);
+		let mut mac2 = [0u8; COOKIE_LENGTH];
+		if let Some((cookie, received)) = self.cookie {
+			if received.elapsed() < COOKIE_MAX_AGE {
+				mac2 = mac(&cookie[..], &packet[..]);
+			} else {
+				self.cookie = None;
+			}
+		}
+		packet.extend(&mac2[..]);
+	}
+	/* create_initiation and create_response return None if our static key is held by an oracle that fails. */
+	pub fn create_initiation(&mut self, timestamp: Tai64N) -> Option<Vec<u8>> {
+		self.hs = self.initial.clone();
+		let message: MessageBuffer = self.hs.write_message_a(&timestamp.as_bytes()[..])?;
+		let mut packet: Vec<u8> = message_header(MESSAGE_HANDSHAKE_INITIATION);
+		write_index(&mut packet, self.local_index);
+		packet.extend(&message.ne[..]);
+		packet.extend(&message.ns[..]);
+		packet.extend(&message.ciphertext[..]);
+		self.append_macs(&mut packet);
+		Some(packet)
+	}
+	/* Callers must reject timestamps not greater than the last one accepted from the same peer. */
+	pub fn consume_initiation(&mut self, packet: &[u8]) -> Option<Tai64N> {
+		if !check_header(packet, MESSAGE_HANDSHAKE_INITIATION, HANDSHAKE_INITIATION_LENGTH) {
+			return None;
+		}
+		if !verify_mac1(packet, &labelled_key(LABEL_MAC1, &self.local_static)) {
+			return None;
+		}
+		self.hs = self.initial.clone();
+		let mut ne = [0u8; DHLEN];
+		ne.copy_from_slice(&packet[8..40]);
+		let mut message = MessageBuffer {
+			ne,
+			ns: Vec::from(&packet[40..88]),
+			ciphertext: Vec::from(&packet[88..116]),
+		};
+		let plaintext: Vec<u8> = self.hs.read_message_a(&mut message)?;
+		if plaintext.len() != TIMESTAMP_LENGTH {
+			return None;
+		}
+		self.remote_index = LittleEndian::read_u32(&packet[4..8]);
+		self.remote_static = self.hs.get_remote_static_public_key();
+		let mut t = [0u8; TIMESTAMP_LENGTH];
+		t.copy_from_slice(&plaintext[..]);
+		Some(Tai64N::from_bytes(t))
+	}
+	pub fn create_response(&mut self) -> Option<(Vec<u8>, Transport)> {
+		let written = self.hs.write_message_b(&ZEROLEN[..]);
+		self.clear();
+		let (_, message, cs1, cs2, _) = written?;
+		let mut packet: Vec<u8> = message_header(MESSAGE_HANDSHAKE_RESPONSE);
+		write_index(&mut packet, self.local_index);
+		write_index(&mut packet, self.remote_index);
+		packet.extend(&message.ne[..]);
+		packet.extend(&message.ciphertext[..]);
+		self.append_macs(&mut packet);
+		Some((packet, Transport::new(cs2, cs1, self.local_index, self.remote_index)))
+	}
+	pub fn consume_response(&mut self, packet: &[u8]) -> Option<Transport> {
+		if !check_header(packet, MESSAGE_HANDSHAKE_RESPONSE, HANDSHAKE_RESPONSE_LENGTH) {
+			return None;
+		}
+		if LittleEndian::read_u32(&packet[8..12]) != self.local_index {
+			return None;
+		}
+		if !verify_mac1(packet, &labelled_key(LABEL_MAC1, &self.local_static)) {
+			return None;
+		}
+		let mut ne = [0u8; DHLEN];
+		ne.copy_from_slice(&packet[12..44]);
+		let mut message = MessageBuffer {
+			ne,
+			ns: Vec::new(),
+			ciphertext: Vec::from(&packet[44..60]),
+		};
+		let (_, _, cs1, cs2, _) = self.hs.read_message_b(&mut message)?;
+		self.clear();
+		self.remote_index = LittleEndian::read_u32(&packet[4..8]);
+		Some(Transport::new(cs1, cs2, self.local_index, self.remote_index))
+	}
+	pub fn consume_cookie_reply(&mut self, packet: &[u8]) -> Option<()> {
+		if !check_header(packet, MESSAGE_HANDSHAKE_COOKIE, COOKIE_REPLY_LENGTH) {
+			return None;
+		}
+		if LittleEndian::read_u32(&packet[4..8]) != self.local_index {
+			return None;
+		}
+		let last_mac1 = self.last_mac1?;
+		let cookie: Vec<u8> = xaead_decrypt(
+			&labelled_key(LABEL_COOKIE, &self.remote_static),
+			&packet[8..32],
+			&last_mac1[..],
+			&packet[32..],
+		)?;
+		let mut temp = [0u8; COOKIE_LENGTH];
+		temp.copy_from_slice(&cookie[..]);
+		self.cookie = Some((temp, Instant::now()));
+		Some(())
+	}
+}
+
+pub struct CookieChecker {
+	mac1_key: [u8; DHLEN],
+	cookie_key: [u8; DHLEN],
+	secret: [u8; DHLEN],
+	secret_generated: Instant,
+}
+impl CookieChecker {
+	pub fn new(local_static: PublicKey) -> CookieChecker {
+		let mut secret = [0u8; DHLEN];
+		rand::thread_rng().fill_bytes(&mut secret);
+		CookieChecker {
+			mac1_key: labelled_key(LABEL_MAC1, &local_static),
+			cookie_key: labelled_key(LABEL_COOKIE, &local_static),
+			secret,
+			secret_generated: Instant::now(),
+		}
+	}
+	fn is_handshake(packet: &[u8]) -> bool {
+		check_header(packet, MESSAGE_HANDSHAKE_INITIATION, HANDSHAKE_INITIATION_LENGTH)
+			|| check_header(packet, MESSAGE_HANDSHAKE_RESPONSE, HANDSHAKE_RESPONSE_LENGTH)
+	}
+	fn cookie(&mut self, source: &[u8]) -> [u8; COOKIE_LENGTH] {
+		if self.secret_generated.elapsed() >= COOKIE_MAX_AGE {
+			rand::thread_rng().fill_bytes(&mut self.secret);
+			self.secret_generated = Instant::now();
+		}
+		mac(&self.secret[..], source)
+	}
+	pub fn verify_mac1(&self, packet: &[u8]) -> bool {
+		CookieChecker::is_handshake(packet) && verify_mac1(packet, &self.mac1_key)
+	}
+	pub fn verify_mac2(&mut self, packet: &[u8], source: &[u8]) -> bool {
+		if !self.verify_mac1(packet) {
+			return false;
+		}
+		let mac2_offset = packet.len() - COOKIE_LENGTH;
+		let expected = mac(&self.cookie(source)[..], &packet[..mac2_offset]);
+		crypto::util::fixed_time_eq(&expected[..], &packet[mac2_offset..])
+	}
+	pub fn create_reply(&mut self, packet: &[u8], source: &[u8]) -> Option<Vec<u8>> {
+		if !self.verify_mac1(packet) {
+			return None;
+		}
+		let mac1_offset = packet.len() - 2 * COOKIE_LENGTH;
+		let mut nonce = [0u8; COOKIE_NONCE_LENGTH];
+		rand::thread_rng().fill_bytes(&mut nonce);
+		let cookie = self.cookie(source);
+		let mut reply: Vec<u8> = message_header(MESSAGE_HANDSHAKE_COOKIE);
+		reply.extend(&packet[4..8]);
+		reply.extend(&nonce[..]);
+		reply.extend(xaead_encrypt(
+			&self.cookie_key,
+			&nonce[..],
+			&packet[mac1_offset..mac1_offset + COOKIE_LENGTH],
+			&cookie[..],
+		));
+		Some(reply)
+	}
+}
+
+pub struct Transport {
+	sender: CipherState,
+	receiver: CipherState,
+	local_index: u32,
+	remote_index: u32,
+	replay: ReplayWindow,
+}
+impl Transport {
+	fn new(sender: CipherState, receiver: CipherState, local_index: u32, remote_index: u32) -> Transport {
+		Transport {
+			sender,
+			receiver,
+			local_index,
+			remote_index,
+			replay: ReplayWindow::new(),
+		}
+	}
+	pub fn get_local_index(&self) -> u32 {
+		self.local_index
+	}
+	pub fn get_remote_index(&self) -> u32 {
+		self.remote_index
+	}
+	/* Zero-pads the payload to a multiple of 16 bytes, as wireguard-go and the kernel module do; receivers take the packet length from its IP header. */
+	pub fn encapsulate(&mut self, payload: &[u8]) -> Vec<u8> {
+		let mut packet: Vec<u8> = message_header(MESSAGE_TRANSPORT_DATA);
+		write_index(&mut packet, self.remote_index);
+		let mut counter = [0u8; 8];
+		LittleEndian::write_u64(&mut counter, self.sender.get_nonce().get_value());
+		packet.extend(&counter[..]);
+		let mut padded: Vec<u8> = Vec::from(payload);
+		padded.resize(payload.len() + (PADDING_MULTIPLE - payload.len() % PADDING_MULTIPLE) % PADDING_MULTIPLE, 0u8);
+		packet.extend(self.sender.encrypt_with_ad(&ZEROLEN[..], &padded[..]));
+		packet
+	}
+	pub fn decapsulate(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+		if packet.len() < TRANSPORT_HEADER_LENGTH + MAC_LENGTH
+			|| packet[..4] != message_header(MESSAGE_TRANSPORT_DATA)[..]
+		{
+			return None;
+		}
+		if LittleEndian::read_u32(&packet[4..8]) != self.local_index {
+			return None;
+		}
+		let counter = LittleEndian::read_u64(&packet[8..16]);
+		if !self.replay.check(counter) {
+			return None;
+		}
+		self.receiver.set_nonce(Nonce::from(counter));
+		let plaintext: Vec<u8> = self
+			.receiver
+			.decrypt_with_ad(&ZEROLEN[..], &packet[TRANSPORT_HEADER_LENGTH..])?;
+		self.replay.update(counter);
+		Some(plaintext)
+	}
+}
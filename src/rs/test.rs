@@ -1,11 +1,10 @@
 #![allow(non_snake_case, non_upper_case_globals)]
 
-use noiseexplorer_$NOISE2RS_N$::{
-	noisesession::NoiseSession,
-	types::{Keypair, Message, MessageBuffer, PrivateKey, PublicKey$NOISE2RS_S$},
+use noiseexplorer_$NOISE2RS_N$::{$NOISE2RS_S$
 };
+use std::{$NOISE2RS_U$};
 
 #[test]
 fn noiseexplorer_test_$NOISE2RS_N$() {
     $NOISE2RS_T$
-}
+}$NOISE2RS_V$
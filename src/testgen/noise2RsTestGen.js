@@ -65,7 +65,7 @@ const gen = (
 		let send = (i % 2 === 0) ? 'initiator_session' : 'responder_session';
 		let recv = (i % 2 === 0) ? 'responder_session' : 'initiator_session';
 		rsTestCode.push([
-			`let mut message${abc[i]}: MessageBuffer = ${send}.send_message(Message::from_str("${messages[i].payload}"));`,
+			`let mut message${abc[i]}: MessageBuffer = ${send}.send_message(Message::from_str("${messages[i].payload}")).unwrap();`,
 			`let mut valid${abc[i]}: bool = false;`,
 			`if let Some(_x) = ${recv}.recv_message(&mut message${abc[i]}) {\n\t\tvalid${abc[i]} = true;\n\t}`,
 			`let t${abc[i]}: Message = Message::from_str("${messages[i].ciphertext}");`
@@ -142,11 +142,828 @@ const assign = (json, data) => {
 	);
 };
 
+/* ---------------------------------------------------------------- *
+ * FEATURE TESTS                                                    *
+ * ---------------------------------------------------------------- */
+
+/* Which features of the generated code a pattern exercises, and the
+ * message indices at which their tests intervene. */
+const features = (json, data) => {
+	let handshakeLength = json.messages.filter((message) => {
+		return message.tokens.length > 0;
+	}).length;
+	let preRecvStatic = json.preMessages.some((preMessage) => {
+		return (preMessage.dir === 'recv') && /s/.test(preMessage.tokens);
+	});
+	let firstStatic = json.messages.findIndex((message) => {
+		return message.tokens.indexOf('s') >= 0;
+	});
+	/* The responder resolves its PSK on reading the psk token, or on reading the message before its own psk token. */
+	let firstPsk = json.messages.findIndex((message) => {
+		return message.tokens.indexOf('psk') >= 0;
+	});
+	let resolvedAt = (firstPsk % 2 === 0) ? firstPsk : (firstPsk - 1);
+	let remoteKnown = json.preMessages.some((preMessage) => {
+		return (preMessage.dir === 'send') && /s/.test(preMessage.tokens);
+	}) || json.messages.slice(0, resolvedAt + 1).some((message, i) => {
+		let tokens = (i === firstPsk) ? message.tokens.slice(0, message.tokens.indexOf('psk')) : message.tokens;
+		return (message.dir === 'send') && (tokens.indexOf('s') >= 0);
+	});
+	/* The first message whose payload is encrypted, once a DH or psk token has keyed the cipher. */
+	let firstEncrypted = json.messages.findIndex((message) => {
+		return message.tokens.some((token) => {
+			return ['ee', 'es', 'se', 'ss', 'psk'].indexOf(token) >= 0;
+		});
+	});
+	/* The first message whose writer performs a DH with its own static key, before ever using it to read. */
+	let staticWrite = ['initiator', 'responder'].map((party, p) => {
+		let own = (p === 0) ? ['se', 'ss'] : ['es', 'ss'];
+		let i = json.messages.findIndex((message) => {
+			return message.tokens.some((token) => {
+				return own.indexOf(token) >= 0;
+			});
+		});
+		return ((i >= 0) && (i % 2 === p)) ? i : -1;
+	}).filter((i) => {
+		return i >= 0;
+	}).sort((a, b) => {
+		return a - b;
+	})[0];
+	return {
+		firstEncrypted: firstEncrypted,
+		staticWrite: (staticWrite === undefined) ? -1 : staticWrite,
+		initiatorRemote: data.hasOwnProperty('init_remote_static') ? 'static_keys().1.generate_public_key()' : 'PublicKey::empty()',
+		responderRemote: data.hasOwnProperty('resp_remote_static') ? `PublicKey::from_str("${data.resp_remote_static}")` : 'PublicKey::empty()',
+		handshakeLength: handshakeLength,
+		oneWay: (handshakeLength === 1),
+		firstStatic: firstStatic,
+		firstPsk: firstPsk,
+		resolvedAt: resolvedAt,
+		hintIndex: (firstPsk % 2 === 0) ? (firstPsk - 2) : resolvedAt,
+		remoteKnown: remoteKnown,
+		selectable: !preRecvStatic && json.messages.some((message) => {
+			return (message.dir === 'recv') && (message.tokens.indexOf('s') >= 0);
+		}),
+		cookie: json.messages[0].tokens.some((token) => {
+			return (token === 'es') || (token === 'ss');
+		}),
+		rotatable: preRecvStatic && json.messages[0].tokens.some((token) => {
+			return (token === 'es') || (token === 'ss');
+		})
+	};
+};
+
+/* Constants and helpers shared by the feature tests, emitted once per crate. */
+const fixtures = (json, data, disco, f) => {
+	let key = (name) => {
+		return data.hasOwnProperty(name) ? data[name] : '0000000000000000000000000000000000000000000000000000000000000001';
+	};
+	let hasPsk = data.hasOwnProperty('init_psks');
+	let ephemerals = [];
+	let [initiator, responder] = ['_initiator', '_responder'];
+	if (data.hasOwnProperty('init_ephemeral')) {
+		initiator = 'initiator';
+		ephemerals.push(`\tinitiator.set_ephemeral(Keypair::from_private_key(PrivateKey::from_str("${data.init_ephemeral}")));`);
+	}
+	if (data.hasOwnProperty('resp_ephemeral')) {
+		responder = 'responder';
+		ephemerals.push(`\tresponder.set_ephemeral(Keypair::from_private_key(PrivateKey::from_str("${data.resp_ephemeral}")));`);
+	}
+	let wireKeys = json.messages.map((message) => {
+		return `(${message.tokens.indexOf('e') >= 0}, ${message.tokens.indexOf('s') >= 0})`;
+	});
+	let list = (vector, field) => {
+		return vector.messages.slice(0, 6).map((message) => {
+			return `\t"${message[field]}",`;
+		});
+	};
+	return [
+		`const PROLOGUE: &str = "${data.hasOwnProperty('init_prologue')? data.init_prologue : ''}";`,
+		...(hasPsk? [`const PSK: &str = "${data.init_psks[0]}";`] : []),
+		`const HANDSHAKE_LENGTH: usize = ${f.handshakeLength};`,
+		`const PAYLOADS: [&str; 6] = [`,
+		...list(data, 'payload'),
+		`];`,
+		`const CIPHERTEXTS: [&str; 6] = [`,
+		...list(data, 'ciphertext'),
+		`];`,
+		`/* The same test vector over Disco, computed from its specification with the strobe-rs and x25519-dalek crates. */`,
+		`const DISCO_CIPHERTEXTS: [&str; 6] = [`,
+		...list(disco, 'ciphertext'),
+		`];`,
+		`/* Whether each handshake message carries an ephemeral and a static key. */`,
+		`const WIRE_KEYS: [(bool, bool); ${wireKeys.length}] = [${wireKeys.join(', ')}];`,
+		``,
+		`fn static_keys() -> (PrivateKey, PrivateKey) {`,
+		`\t(PrivateKey::from_str("${key('init_static')}"), PrivateKey::from_str("${key('resp_static')}"))`,
+		`}`,
+		``,
+		`/* Both parties of the test vector, built by init with the given prologue and static keypairs. */`,
+		`fn init_sessions<T>(`,
+		`\tinit: fn(bool, Message, Keypair, PublicKey${hasPsk? ', Psk' : ''}) -> T,`,
+		`\tprologue: Message,`,
+		`\tinitiator_static: Keypair,`,
+		`\tresponder_static: Keypair,`,
+		`) -> (T, T) {`,
+		`\tlet initiator: T = init(true, prologue.clone(), initiator_static, ${f.initiatorRemote}${hasPsk? ', Psk::from_str(PSK)' : ''});`,
+		`\tlet responder: T = init(false, prologue, responder_static, ${f.responderRemote}${hasPsk? ', Psk::from_str(PSK)' : ''});`,
+		`\t(initiator, responder)`,
+		`}`,
+		``,
+		`fn sessions() -> (NoiseSession, NoiseSession) {`,
+		`\tlet (initiator_static, responder_static) = static_keys();`,
+		`\tinit_sessions(`,
+		`\t\tNoiseSession::init_session,`,
+		`\t\tMessage::from_str(PROLOGUE),`,
+		`\t\tKeypair::from_private_key(initiator_static),`,
+		`\t\tKeypair::from_private_key(responder_static),`,
+		`\t)`,
+		`}`,
+		``,
+		`/* Fixes the ephemeral keys of the test vector, so that messages can be compared with its ciphertexts. */`,
+		`fn set_vector_ephemerals<T: Party>(${initiator}: &mut T, ${responder}: &mut T) {`,
+		...ephemerals,
+		`}`,
+		``,
+		`trait Party {`,
+		`\tfn set_ephemeral(&mut self, e: Keypair);`,
+		`\tfn send(&mut self, message: Message) -> MessageBuffer;`,
+		`\tfn recv(&mut self, message: &mut MessageBuffer) -> Option<Vec<u8>>;`,
+		`}`,
+		`impl Party for NoiseSession {`,
+		`\tfn set_ephemeral(&mut self, e: Keypair) {`,
+		`\t\tself.set_ephemeral_keypair(e);`,
+		`\t}`,
+		`\tfn send(&mut self, message: Message) -> MessageBuffer {`,
+		`\t\tself.send_message(message).unwrap()`,
+		`\t}`,
+		`\tfn recv(&mut self, message: &mut MessageBuffer) -> Option<Vec<u8>> {`,
+		`\t\tself.recv_message(message)`,
+		`\t}`,
+		`}`,
+		`impl Party for NoiseSession<DiscoSymmetricState> {`,
+		`\tfn set_ephemeral(&mut self, e: Keypair) {`,
+		`\t\tself.set_ephemeral_keypair(e);`,
+		`\t}`,
+		`\tfn send(&mut self, message: Message) -> MessageBuffer {`,
+		`\t\tself.send_message(message).unwrap()`,
+		`\t}`,
+		`\tfn recv(&mut self, message: &mut MessageBuffer) -> Option<Vec<u8>> {`,
+		`\t\tself.recv_message(message)`,
+		`\t}`,
+		`}`,
+		``,
+		`/* Sends the given messages of the test vector, showing each to inspect with its receiver before it is read. */`,
+		`fn exchange<T: Party>(`,
+		`\tinitiator: &mut T,`,
+		`\tresponder: &mut T,`,
+		`\tindices: Range<usize>,`,
+		`\tinspect: &mut dyn FnMut(usize, &MessageBuffer, &T),`,
+		`) -> Vec<MessageBuffer> {`,
+		`\tlet mut messages: Vec<MessageBuffer> = Vec::new();`,
+		`\tfor i in indices {`,
+		...(f.oneWay ? [
+			`\t\t/* Only the initiator sends in a one-way pattern. */`,
+			`\t\tlet (sender, receiver): (&mut T, &mut T) = (&mut *initiator, &mut *responder);`
+		] : [
+			`\t\tlet (sender, receiver): (&mut T, &mut T) = if i % 2 == 0 {`,
+			`\t\t\t(&mut *initiator, &mut *responder)`,
+			`\t\t} else {`,
+			`\t\t\t(&mut *responder, &mut *initiator)`,
+			`\t\t};`
+		]),
+		`\t\tlet payload: Message = Message::from_str(PAYLOADS[i]);`,
+		`\t\tlet mut message: MessageBuffer = sender.send(payload.clone());`,
+		`\t\tinspect(i, &message, receiver);`,
+		`\t\tassert!(receiver.recv(&mut message) == Some(payload.as_bytes().clone()), "Message {} FAIL", i);`,
+		`\t\tmessages.push(message);`,
+		`\t}`,
+		`\tmessages`,
+		`}`,
+		``,
+		`fn handshake<T: Party>(initiator: &mut T, responder: &mut T) -> Vec<MessageBuffer> {`,
+		`\texchange(initiator, responder, 0..HANDSHAKE_LENGTH, &mut |_, _, _| {})`,
+		`}`,
+		``,
+		`fn transport<T: Party>(initiator: &mut T, responder: &mut T) -> Vec<MessageBuffer> {`,
+		`\texchange(initiator, responder, HANDSHAKE_LENGTH..PAYLOADS.len(), &mut |_, _, _| {})`,
+		`}`,
+		``,
+		`/* Compares messages, as they would be sent on the wire, with the ciphertexts of a test vector. */`,
+		`fn assert_vectors(messages: &[MessageBuffer], ciphertexts: &[&str]) {`,
+		`\tfor (i, message) in messages.iter().enumerate() {`,
+		`\t\tlet mut wire: Vec<u8> = Vec::new();`,
+		`\t\tif let Some((ephemeral, static_key)) = WIRE_KEYS.get(i) {`,
+		`\t\t\tif *ephemeral {`,
+		`\t\t\t\twire.extend_from_slice(&message.ne[..]);`,
+		`\t\t\t}`,
+		`\t\t\tif *static_key {`,
+		`\t\t\t\twire.extend_from_slice(&message.ns[..]);`,
+		`\t\t\t}`,
+		`\t\t}`,
+		`\t\twire.extend_from_slice(&message.ciphertext[..]);`,
+		`\t\tassert!(wire == *Message::from_str(ciphertexts[i]).as_bytes(), "Test {} FAIL", i);`,
+		`\t}`,
+		`}`
+	].join('\n');
+};
+
+const test = (json, name, body) => {
+	return [
+		`#[test]`,
+		`fn noiseexplorer_test_${json.name.toLowerCase()}_${name}() {`,
+		`\t${body.join('\n\t')}`,
+		`}`
+	].join('\n');
+};
+
+/* The feature tests that apply to the pattern, with the items they import. */
+const featureTests = (json, data, f) => {
+	let tests = [];
+	let uses = {
+		disco: ['DiscoSymmetricState'],
+		noisesession: ['NoiseSession'],
+		noisesocket: ['retry_prologue', 'HandshakeMessage', 'NoiseSocket', 'Response', 'PROTOCOL_NAME'],
+		replay: ['ReplayCache', 'ReplayError'],
+		table: ['SessionTable'],
+		types: ['HandshakeError', 'Keypair', 'Message', 'MessageBuffer', 'PrivateKey', 'PublicKey']
+	};
+	let std = ['ops::Range', 'time::Duration'];
+	let hasStatic = data.hasOwnProperty('init_static') || data.hasOwnProperty('resp_static');
+	let vectorSessions = [
+		`let (mut initiator_session, mut responder_session) = sessions();`,
+		`set_vector_ephemerals(&mut initiator_session, &mut responder_session);`
+	];
+	if (f.oneWay) {
+		tests.push(test(json, 'split', vectorSessions.concat([
+			`let mut messages: Vec<MessageBuffer> = handshake(&mut initiator_session, &mut responder_session);`,
+			`let (mut sender, mut initiator_receiver) = initiator_session.into_split();`,
+			`let (_, mut receiver) = responder_session.into_split();`,
+			`for payload in PAYLOADS.iter().skip(HANDSHAKE_LENGTH) {`,
+			`\tlet mut message: MessageBuffer = sender.send_message(Message::from_str(payload));`,
+			`\tassert!(initiator_receiver.recv_message(&mut message.clone()).is_none());`,
+			`\tassert!(receiver.recv_message(&mut message).unwrap() == *Message::from_str(payload).as_bytes());`,
+			`\tmessages.push(message);`,
+			`}`,
+			`assert_vectors(&messages[..], &CIPHERTEXTS);`
+		])));
+		tests.push([
+			`#[test]`,
+			`#[should_panic(expected = "Responder cannot send in a one-way pattern; send_message")]`,
+			`fn noiseexplorer_test_${json.name.toLowerCase()}_split_responder_sender() {`,
+			`\tlet (mut initiator_session, mut responder_session) = sessions();`,
+			`\thandshake(&mut initiator_session, &mut responder_session);`,
+			`\tlet (mut sender, _) = responder_session.into_split();`,
+			`\tsender.send_message(Message::from_str(PAYLOADS[HANDSHAKE_LENGTH]));`,
+			`}`
+		].join('\n'));
+	} else {
+		tests.push(test(json, 'split', vectorSessions.concat([
+			`let mut messages: Vec<MessageBuffer> = handshake(&mut initiator_session, &mut responder_session);`,
+			`let (mut initiator_sender, mut initiator_receiver) = initiator_session.into_split();`,
+			`let (mut responder_sender, mut responder_receiver) = responder_session.into_split();`,
+			`for (i, payload) in PAYLOADS.iter().enumerate().skip(HANDSHAKE_LENGTH) {`,
+			`\tlet mut message: MessageBuffer = if i % 2 == 0 {`,
+			`\t\tinitiator_sender.send_message(Message::from_str(payload))`,
+			`\t} else {`,
+			`\t\tresponder_sender.send_message(Message::from_str(payload))`,
+			`\t};`,
+			`\tlet receiver = if i % 2 == 0 { &mut responder_receiver } else { &mut initiator_receiver };`,
+			`\tassert!(receiver.recv_message(&mut message).unwrap() == *Message::from_str(payload).as_bytes());`,
+			`\tmessages.push(message);`,
+			`}`,
+			`assert_vectors(&messages[..], &CIPHERTEXTS);`
+		])));
+		tests.push([
+			`#[test]`,
+			`#[should_panic(expected = "Re-handshake in progress; into_split")]`,
+			`fn noiseexplorer_test_${json.name.toLowerCase()}_split_during_rehandshake() {`,
+			`\tlet (mut initiator_session, mut responder_session) = sessions();`,
+			`\thandshake(&mut initiator_session, &mut responder_session);`,
+			`\tlet mut request: MessageBuffer = initiator_session.rehandshake().unwrap();`,
+			`\tlet (_, mut receiver) = responder_session.into_split();`,
+			`\tassert!(receiver.recv_message(&mut request).is_none());`,
+			`\tinitiator_session.into_split();`,
+			`}`
+		].join('\n'));
+	}
+	tests.push(test(json, 'noisesocket', [
+		`let initial: HandshakeMessage = HandshakeMessage::from_bytes(&HandshakeMessage {`,
+		`\tnegotiation_data: b"Noise_${json.name}_448_ChaChaPoly_BLAKE2b".to_vec(),`,
+		`\tnoise_message: vec![0u8; 56],`,
+		`}.to_bytes()[..]).unwrap();`,
+		`let retry: HandshakeMessage = HandshakeMessage::retry_request(PROTOCOL_NAME);`,
+		`assert!(retry.response() == Response::Retry);`,
+		`let (initiator_static, responder_static) = static_keys();`,
+		`let (initiator_session, responder_session) = init_sessions(`,
+		`\tNoiseSession::init_session,`,
+		`\tretry_prologue(&initial, &retry, PROTOCOL_NAME),`,
+		`\tKeypair::from_private_key(initiator_static),`,
+		`\tKeypair::from_private_key(responder_static),`,
+		`);`,
+		`let mut initiator_socket: NoiseSocket = NoiseSocket::new(initiator_session);`,
+		`let mut responder_socket: NoiseSocket = NoiseSocket::new(responder_session);`,
+		`for (i, payload) in PAYLOADS.iter().enumerate() {`,
+		`\tlet payload: Message = Message::from_str(payload);`,
+		`\tlet (sender, receiver) = if i % 2 == 0 {`,
+		`\t\t(&mut initiator_socket, &mut responder_socket)`,
+		`\t} else {`,
+		`\t\t(&mut responder_socket, &mut initiator_socket)`,
+		`\t};`,
+		`\tif i < HANDSHAKE_LENGTH {`,
+		`\t\tlet negotiation_data: &[u8] = if i == 0 { PROTOCOL_NAME } else { &[] };`,
+		`\t\tlet message: HandshakeMessage =`,
+		`\t\t\tHandshakeMessage::from_bytes(&sender.write_handshake_message(negotiation_data, &payload.as_bytes()[..]).unwrap().to_bytes()[..]).unwrap();`,
+		`\t\tassert!(receiver.read_handshake_message(&message).unwrap() == *payload.as_bytes());`,
+		`\t} else {`,
+		`\t\tlet packet: Vec<u8> = sender.write_message(&payload.as_bytes()[..], i * 8);`,
+		`\t\tassert!(receiver.read_message(&packet[..]).unwrap() == *payload.as_bytes());`,
+		`\t}`,
+		`}`,
+		`assert!(initiator_socket.is_handshake_finished() && responder_socket.is_handshake_finished());`
+	]));
+	tests.push(test(json, 'elligator', [
+		`let (mut initiator_session, mut responder_session) = sessions();`,
+		`initiator_session.set_elligator(true);`,
+		`responder_session.set_elligator(true);`,
+		`handshake(&mut initiator_session, &mut responder_session);`,
+		`transport(&mut initiator_session, &mut responder_session);`
+	]));
+	tests.push(test(json, 'elligator_not_representable', [
+		`let (mut initiator_session, _) = sessions();`,
+		`initiator_session.set_elligator(true);`,
+		`initiator_session.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("0101010101010101010101010101010101010101010101010101010101010101")));`,
+		`for _ in 0..2 {`,
+		`\tassert!(initiator_session.send_message(Message::from_vec(b"payload".to_vec())).is_none());`,
+		`\tassert!(initiator_session.get_handshake_error() == Some(HandshakeError::EphemeralNotRepresentable));`,
+		`}`,
+		`let (mut initiator_session, _) = sessions();`,
+		`initiator_session.set_elligator(true);`,
+		`initiator_session.set_ephemeral_keypair(Keypair::from_private_key(PrivateKey::from_str("0101010101010101010101010101010101010101010101010101010101010101")));`,
+		`let mut socket: NoiseSocket = NoiseSocket::new(initiator_session.clone());`,
+		`assert!(socket.write_handshake_message(PROTOCOL_NAME, b"payload").is_none());`,
+		`assert!(!socket.is_handshake_finished());`,
+		`assert!(socket.get_session().get_handshake_error() == Some(HandshakeError::EphemeralNotRepresentable));`,
+		`let mut table: SessionTable<u32, u8> = SessionTable::new(1, 1, Duration::from_secs(60));`,
+		`assert!(table.insert(1, 7, initiator_session).is_some() && table.half_open() == 1);`,
+		`assert!(table.send_message(&1, Message::from_vec(b"payload".to_vec())).is_none());`,
+		`assert!(table.half_open() == 0 && table.send_message(&1, Message::from_vec(b"payload".to_vec())).is_none());`
+	]));
+	tests.push(test(json, 'disco', [
+		`let (initiator_static, responder_static) = static_keys();`,
+		`let (mut initiator_session, mut responder_session): (NoiseSession<DiscoSymmetricState>, NoiseSession<DiscoSymmetricState>) =`,
+		`\tinit_sessions(`,
+		`\t\tNoiseSession::init_disco_session,`,
+		`\t\tMessage::from_str(PROLOGUE),`,
+		`\t\tKeypair::from_private_key(initiator_static),`,
+		`\t\tKeypair::from_private_key(responder_static),`,
+		`\t);`,
+		`set_vector_ephemerals(&mut initiator_session, &mut responder_session);`,
+		`let mut messages: Vec<MessageBuffer> = handshake(&mut initiator_session, &mut responder_session);`,
+		`messages.append(&mut transport(&mut initiator_session, &mut responder_session));`,
+		`assert_vectors(&messages[..], &DISCO_CIPHERTEXTS);`
+	]));
+	if (!f.oneWay) {
+		tests.push(test(json, 'ratchet', [
+			`let (mut initiator_session, mut responder_session) = sessions();`,
+			`initiator_session.set_ratchet(true);`,
+			`responder_session.set_ratchet(true);`,
+			`handshake(&mut initiator_session, &mut responder_session);`,
+			`transport(&mut initiator_session, &mut responder_session);`
+		]));
+		tests.push(test(json, 'rehandshake', [
+			`let (mut initiator_session, mut responder_session) = sessions();`,
+			`handshake(&mut initiator_session, &mut responder_session);`,
+			`transport(&mut initiator_session, &mut responder_session);`,
+			`let exported: Vec<u8> = responder_session.export_keying_material(b"media", b"", 32);`,
+			`let mut request: MessageBuffer = initiator_session.rehandshake().unwrap();`,
+			`let mut in_flight: MessageBuffer = initiator_session.send_message(Message::from_vec(b"in flight".to_vec())).unwrap();`,
+			`assert!(responder_session.recv_message(&mut request).unwrap().is_empty());`,
+			`assert!(responder_session.is_rehandshake_message());`,
+			`let mut response: MessageBuffer = responder_session.take_rehandshake_message().unwrap();`,
+			`let mut fresh: MessageBuffer = responder_session.send_message(Message::from_vec(b"fresh".to_vec())).unwrap();`,
+			`assert!(responder_session.recv_message(&mut in_flight).unwrap() == b"in flight");`,
+			`assert!(!responder_session.is_rehandshake_message());`,
+			`let mut stale: NoiseSession = initiator_session.clone();`,
+			`assert!(stale.recv_message(&mut fresh.clone()).is_none());`,
+			`assert!(initiator_session.recv_message(&mut response).unwrap().is_empty());`,
+			`assert!(initiator_session.recv_message(&mut fresh).unwrap() == b"fresh");`,
+			`let mut confirm: MessageBuffer = initiator_session.take_rehandshake_message().unwrap();`,
+			`let mut after: MessageBuffer = initiator_session.send_message(Message::from_vec(b"after".to_vec())).unwrap();`,
+			`assert!(responder_session.recv_message(&mut confirm).unwrap().is_empty());`,
+			`assert!(responder_session.recv_message(&mut after).unwrap() == b"after");`,
+			`let mut empty: MessageBuffer = initiator_session.send_message(Message::from_vec(Vec::new())).unwrap();`,
+			`assert!(responder_session.recv_message(&mut empty).unwrap().is_empty());`,
+			`assert!(!responder_session.is_rehandshake_message());`,
+			`assert!(responder_session.take_rehandshake_message().is_none());`,
+			`assert!(initiator_session.export_keying_material(b"media", b"", 32) == exported);`
+		]));
+		uses.mux = ['Multiplexer', 'INITIAL_WINDOW'];
+		tests.push(test(json, 'mux', [
+			`fn pump(from: &mut Multiplexer, to: &mut Multiplexer) -> usize {`,
+			`\tlet mut count: usize = 0;`,
+			`\twhile let Some(mut message) = from.poll_message() {`,
+			`\t\tassert!(to.recv_message(&mut message).is_some());`,
+			`\t\tcount += 1;`,
+			`\t}`,
+			`\tcount`,
+			`}`,
+			`let (mut initiator_session, mut responder_session) = sessions();`,
+			`handshake(&mut initiator_session, &mut responder_session);`,
+			`let mut initiator_mux: Multiplexer = Multiplexer::new(initiator_session);`,
+			`let mut responder_mux: Multiplexer = Multiplexer::new(responder_session);`,
+			`let bulk: u32 = initiator_mux.open();`,
+			`let small: u32 = initiator_mux.open();`,
+			`let data: Vec<u8> = (0..3 * INITIAL_WINDOW).map(|x| x as u8).collect();`,
+			`initiator_mux.write(bulk, &data[..]);`,
+			`initiator_mux.write(small, b"small");`,
+			`initiator_mux.close(small);`,
+			`initiator_mux.close(bulk);`,
+			`pump(&mut initiator_mux, &mut responder_mux);`,
+			`assert!(responder_mux.accept() == Some(bulk) && responder_mux.accept() == Some(small));`,
+			`assert!(responder_mux.read(small) == b"small" && responder_mux.is_eof(small));`,
+			`let mut received: Vec<u8> = responder_mux.read(bulk);`,
+			`assert!(received.len() == INITIAL_WINDOW as usize);`,
+			`let reply: u32 = responder_mux.open();`,
+			`responder_mux.write(reply, b"reply");`,
+			`responder_mux.close(reply);`,
+			`while pump(&mut responder_mux, &mut initiator_mux) + pump(&mut initiator_mux, &mut responder_mux) > 0 {`,
+			`\treceived.extend(responder_mux.read(bulk));`,
+			`}`,
+			`assert!(received == data && responder_mux.is_eof(bulk));`,
+			`assert!(initiator_mux.accept() == Some(reply) && initiator_mux.read(reply) == b"reply");`
+		]));
+		tests.push(test(json, 'mux_max_streams', [
+			`let (mut initiator_session, mut responder_session) = sessions();`,
+			`handshake(&mut initiator_session, &mut responder_session);`,
+			`let mut initiator_mux: Multiplexer = Multiplexer::new(initiator_session);`,
+			`let mut responder_mux: Multiplexer = Multiplexer::new(responder_session);`,
+			`responder_mux.set_max_streams(1);`,
+			`let first: u32 = initiator_mux.open();`,
+			`let refused: u32 = initiator_mux.open();`,
+			`initiator_mux.write(first, b"first");`,
+			`initiator_mux.write(refused, b"refused");`,
+			`for _ in 0..2 {`,
+			`\twhile let Some(mut message) = initiator_mux.poll_message() {`,
+			`\t\tassert!(responder_mux.recv_message(&mut message).is_some());`,
+			`\t}`,
+			`\twhile let Some(mut message) = responder_mux.poll_message() {`,
+			`\t\tassert!(initiator_mux.recv_message(&mut message).is_some());`,
+			`\t}`,
+			`\tif responder_mux.accept().is_some() {`,
+			`\t\tresponder_mux.close(first);`,
+			`\t\tinitiator_mux.close(first);`,
+			`\t}`,
+			`}`,
+			`assert!(responder_mux.read(first) == b"first" && responder_mux.read(refused).is_empty());`,
+			`assert!(initiator_mux.is_reset(refused) && !initiator_mux.is_reset(first));`,
+			`initiator_mux.close(refused);`,
+			`let next: u32 = initiator_mux.open();`,
+			`let mut message: MessageBuffer = initiator_mux.poll_message().unwrap();`,
+			`assert!(responder_mux.recv_message(&mut message).is_some() && responder_mux.accept() == Some(next));`,
+			`assert!(responder_mux.poll_message().is_none());`
+		]));
+	}
+	tests.push(test(json, 'session_table', [
+		`let (mut initiator_session, responder_session) = sessions();`,
+		`let mut stale: SessionTable<u32, u8> = SessionTable::new(2, 2, Duration::from_secs(0));`,
+		`assert!(stale.insert(1, 7, responder_session.clone()).is_some());`,
+		`assert!(stale.expire() == 1 && stale.half_open() == 0);`,
+		`let mut table: SessionTable<u32, u8> = SessionTable::new(2, 1, Duration::from_secs(60));`,
+		`assert!(table.insert(1, 7, responder_session.clone()).is_some());`,
+		`assert!(table.insert(2, 7, responder_session.clone()).is_none());`,
+		`assert!(table.insert(2, 8, responder_session.clone()).is_some());`,
+		`assert!(table.insert(3, 9, responder_session).is_none());`,
+		`for (i, payload) in PAYLOADS.iter().enumerate() {`,
+		`\tif i % 2 == 0 {`,
+		`\t\tlet mut message: MessageBuffer = initiator_session.send_message(Message::from_str(payload)).unwrap();`,
+		`\t\tassert!(table.recv_message(&1, &mut message).is_some());`,
+		`\t} else {`,
+		`\t\tlet mut message: MessageBuffer = table.send_message(&1, Message::from_str(payload)).unwrap();`,
+		`\t\tassert!(initiator_session.recv_message(&mut message).is_some());`,
+		`\t}`,
+		`}`,
+		`assert!(table.half_open() == 1 && table.established() == 1);`,
+		`assert!(table.get_session(&1).unwrap().is_handshake_finished());`,
+		`assert!(table.remove(&2).is_none() && table.half_open() == 0);`
+	]));
+	tests.push(test(json, 'replay', [
+		`let (mut initiator_session, mut responder_session) = sessions();`,
+		`let mut replica: NoiseSession = responder_session.clone();`,
+		`let mut cache: ReplayCache = ReplayCache::new(Duration::from_secs(60));`,
+		`let mut message: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();`,
+		`let mut replayed: MessageBuffer = message.clone();`,
+		`assert!(cache.recv_first_message(&mut responder_session, &mut message).is_ok());`,
+		`assert!(cache.recv_first_message(&mut replica, &mut replayed) == Err(ReplayError::Replayed));`,
+		`exchange(&mut initiator_session, &mut responder_session, 1..PAYLOADS.len(), &mut |_, _, _| {});`
+	]));
+	tests.push(test(json, 'replay_elligator', [
+		`let (mut initiator_session, mut responder_session) = sessions();`,
+		`initiator_session.set_elligator(true);`,
+		`responder_session.set_elligator(true);`,
+		`let mut cache: ReplayCache = ReplayCache::new(Duration::from_secs(60));`,
+		`let mut message: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();`,
+		`let replayed: MessageBuffer = message.clone();`,
+		`assert!(cache.recv_first_message(&mut responder_session.clone(), &mut message).is_ok());`,
+		`for flipped in [0x40u8, 0x80, 0xc0].iter() {`,
+		`\tlet mut reencoded: MessageBuffer = replayed.clone();`,
+		`\treencoded.ne[31] ^= flipped;`,
+		`\tassert!(cache.recv_first_message(&mut responder_session.clone(), &mut reencoded) == Err(ReplayError::Replayed));`,
+		`}`
+	]));
+	if (f.cookie) {
+		uses.cookie = ['CookieError', 'CookieGuard', 'CookieMessage'];
+		tests.push(test(json, 'cookie', vectorSessions.concat([
+			`let mut guard: CookieGuard = CookieGuard::new();`,
+			`guard.set_under_load(true);`,
+			`let first: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();`,
+			`let sent: CookieMessage = CookieMessage::first(first.clone());`,
+			`let mut received: CookieMessage = CookieMessage::from_bytes(&sent.to_bytes()[..]).unwrap();`,
+			`let reply: CookieMessage = match guard.recv_first_message(&mut responder_session, b"192.0.2.1:443", &mut received) {`,
+			`	Err(CookieError::Retry(reply)) => CookieMessage::from_bytes(&reply.to_bytes()[..]).unwrap(),`,
+			`	_ => panic!("Cookie not requested"),`,
+			`};`,
+			`let retry: CookieMessage = sent.retry(&reply).unwrap();`,
+			`let mut spoofed: CookieMessage = retry.clone();`,
+			`assert!(matches!(guard.recv_first_message(&mut responder_session, b"192.0.2.2:443", &mut spoofed), Err(CookieError::Retry(_))));`,
+			`let mut received: CookieMessage = CookieMessage::from_bytes(&retry.to_bytes()[..]).unwrap();`,
+			`let plaintext: Vec<u8> = guard.recv_first_message(&mut responder_session, b"192.0.2.1:443", &mut received).ok().unwrap();`,
+			`assert!(plaintext == *Message::from_str(PAYLOADS[0]).as_bytes());`,
+			`let mut messages: Vec<MessageBuffer> = vec![first];`,
+			`messages.append(&mut exchange(&mut initiator_session, &mut responder_session, 1..PAYLOADS.len(), &mut |_, _, _| {}));`,
+			`assert_vectors(&messages[..], &CIPHERTEXTS);`
+		])));
+	}
+	tests.push(test(json, 'low_order', [
+		`let (mut initiator_session, mut responder_session) = sessions();`,
+		`let mut message: MessageBuffer = initiator_session.send_message(Message::from_str(PAYLOADS[0])).unwrap();`,
+		`message.ne = [0u8; 32];`,
+		`let mut permissive: NoiseSession = responder_session.clone();`,
+		`permissive.set_reject_low_order(false);`,
+		`assert!(responder_session.recv_message(&mut message.clone()).is_none());`,
+		`assert!(responder_session.get_handshake_error() == Some(HandshakeError::LowOrderPoint));`,
+		`permissive.recv_message(&mut message);`,
+		`assert!(permissive.get_handshake_error().is_none());`
+	]));
+	tests.push(test(json, 'decrypt_failure', [
+		`let (mut initiator_session, mut responder_session) = sessions();`,
+		`exchange(&mut initiator_session, &mut responder_session, 0..${f.firstEncrypted}, &mut |_, _, _| {});`,
+		`let mut message: MessageBuffer = ${(f.firstEncrypted % 2 === 0)? 'initiator_session' : 'responder_session'}.send_message(Message::from_str(PAYLOADS[${f.firstEncrypted}])).unwrap();`,
+		`let mut truncated: MessageBuffer = message.clone();`,
+		`truncated.ciphertext.truncate(8);`,
+		`let mut tampered: MessageBuffer = message.clone();`,
+		`tampered.ciphertext[0] ^= 0x01;`,
+		`let receiver: &mut NoiseSession = ${(f.firstEncrypted % 2 === 0)? '&mut responder_session' : '&mut initiator_session'};`,
+		`assert!(receiver.clone().recv_message(&mut truncated).is_none());`,
+		`assert!(receiver.clone().recv_message(&mut tampered).is_none());`,
+		`assert!(receiver.recv_message(&mut message).unwrap() == *Message::from_str(PAYLOADS[${f.firstEncrypted}]).as_bytes());`
+	]));
+	if (f.firstStatic >= 0) {
+		let j = f.firstStatic;
+		let reader = (j % 2 === 0) ? 'responder_session' : 'initiator_session';
+		let writer = (j % 2 === 0) ? 'initiator_session' : 'responder_session';
+		tests.push(test(json, 'static_key_verifier', [
+			`let (mut initiator_session, mut responder_session) = sessions();`,
+			`${reader}.set_static_key_verifier(Arc::new(|_: &PublicKey| false));`,
+			`exchange(&mut initiator_session, &mut responder_session, 0..${j}, &mut |_, _, _| {});`,
+			`let mut message: MessageBuffer = ${writer}.send_message(Message::from_str(PAYLOADS[${j}])).unwrap();`,
+			`assert!(${reader}.recv_message(&mut message).is_none());`,
+			`assert!(${reader}.get_handshake_error() == Some(HandshakeError::StaticKeyRejected));`
+		]));
+		uses.knownpeers = ['KnownPeers', 'TrustMode'];
+		let remaining = (j % 2 === 0) ? 'responder' : 'initiator';
+		tests.push(test(json, 'known_peers', [
+			`let path: PathBuf = std::env::temp_dir().join(format!("noiseexplorer_${json.name}_known_peers_{}", std::process::id()));`,
+			`let _ = fs::remove_file(&path);`,
+			`let store: Arc<KnownPeers> = Arc::new(KnownPeers::open(&path, TrustMode::TrustOnFirstUse).unwrap());`,
+			`let (mut initiator_session, mut responder_session) = sessions();`,
+			`${reader}.set_known_peer(&store, "peer");`,
+			`exchange(&mut initiator_session, &mut responder_session, 0..${j + 1}, &mut |_, _, _| {});`,
+			`let ${remaining}_static: PrivateKey = static_keys().${(j % 2 === 0) ? 1 : 0};`,
+			`assert!(store.get("peer").unwrap().as_bytes() == static_keys().${(j % 2 === 0) ? 0 : 1}.generate_public_key().as_bytes());`,
+			`let (mut initiator_session, mut responder_session) = init_sessions(`,
+			`\tNoiseSession::init_session,`,
+			`\tMessage::from_str(PROLOGUE),`,
+			`\t${(j % 2 === 0) ? 'Keypair::new()' : 'Keypair::from_private_key(initiator_static)'},`,
+			`\t${(j % 2 === 0) ? 'Keypair::from_private_key(responder_static)' : 'Keypair::new()'},`,
+			`);`,
+			`${reader}.set_known_peer(&store, "peer");`,
+			`exchange(&mut initiator_session, &mut responder_session, 0..${j}, &mut |_, _, _| {});`,
+			`let mut message: MessageBuffer = ${writer}.send_message(Message::from_str(PAYLOADS[${j}])).unwrap();`,
+			`assert!(${reader}.recv_message(&mut message).is_none());`,
+			`assert!(${reader}.get_handshake_error() == Some(HandshakeError::StaticKeyMismatch));`,
+			`assert!(KnownPeers::open(&path, TrustMode::Strict).unwrap().get("peer").is_some());`,
+			`fs::remove_file(&path).unwrap();`
+		]));
+		uses.cert = ['CertificateAuthority'];
+		let names = [];
+		json.messages.forEach((message, i) => {
+			if ((i < f.handshakeLength) && (message.tokens.indexOf('s') >= 0)) {
+				let receiver = (i % 2 === 0) ? 'responder_session' : 'initiator_session';
+				let name = (i % 2 === 0) ? 'initiator' : 'responder';
+				names.push(`assert!(${receiver}.get_remote_certificate().unwrap().get_name() == "${name}");`);
+			}
+		});
+		tests.push(test(json, 'certificate', [
+			`let authority: CertificateAuthority = CertificateAuthority::from_bytes([0x42u8; 32]);`,
+			`let (initiator_static, responder_static) = static_keys();`,
+			`let (mut initiator_session, mut responder_session) = sessions();`,
+			`initiator_session.set_certificate(authority.issue("initiator", &initiator_static.generate_public_key(), u64::MAX));`,
+			`initiator_session.set_trust_anchors(vec![authority.get_public_key()]);`,
+			`responder_session.set_certificate(authority.issue("responder", &responder_static.generate_public_key(), u64::MAX));`,
+			`responder_session.set_trust_anchors(vec![authority.get_public_key()]);`,
+			`exchange(&mut initiator_session, &mut responder_session, 0..PAYLOADS.len(), &mut |i, message, receiver| {`,
+			`\tif i == ${j} {`,
+			`\t\tlet mut stranger: NoiseSession = receiver.clone();`,
+			`\t\tstranger.set_trust_anchors(vec![CertificateAuthority::from_bytes([0x43u8; 32]).get_public_key()]);`,
+			`\t\tassert!(stranger.recv_message(&mut message.clone()).is_none());`,
+			`\t\tassert!(stranger.get_handshake_error() == Some(HandshakeError::CertificateRejected));`,
+			`\t}`,
+			`});`,
+			...names
+		]));
+	}
+	if (hasStatic) {
+		uses.oracle = ['LocalKeyOracle'];
+		tests.push(test(json, 'static_key_oracle', [
+			`let (initiator_static, responder_static) = static_keys();`,
+			`let (mut initiator_session, mut responder_session) = init_sessions(`,
+			`\tNoiseSession::init_session,`,
+			`\tMessage::from_str(PROLOGUE),`,
+			`\tKeypair::from_oracle(Arc::new(LocalKeyOracle::new(initiator_static))),`,
+			`\tKeypair::from_oracle(Arc::new(LocalKeyOracle::new(responder_static))),`,
+			`);`,
+			`set_vector_ephemerals(&mut initiator_session, &mut responder_session);`,
+			`let mut messages: Vec<MessageBuffer> = handshake(&mut initiator_session, &mut responder_session);`,
+			`messages.extend(transport(&mut initiator_session, &mut responder_session));`,
+			`assert_vectors(&messages[..], &CIPHERTEXTS);`
+		]));
+	}
+	if (f.staticWrite >= 0) {
+		let k = f.staticWrite;
+		let writer = (k % 2 === 0) ? 'initiator' : 'responder';
+		uses.types.push('StaticKeyOracle');
+		tests.push(test(json, 'static_key_oracle_failure', [
+			`struct RefusingOracle(PublicKey);`,
+			`impl StaticKeyOracle for RefusingOracle {`,
+			`\tfn public_key(&self) -> PublicKey {`,
+			`\t\tself.0`,
+			`\t}`,
+			`\tfn dh(&self, _: &PublicKey) -> Option<[u8; 32]> {`,
+			`\t\tNone`,
+			`\t}`,
+			`}`,
+			`let (initiator_static, responder_static) = static_keys();`,
+			`let ${writer}_oracle: Arc<RefusingOracle> = Arc::new(RefusingOracle(${writer}_static.generate_public_key()));`,
+			`let (mut initiator_session, mut responder_session) = init_sessions(`,
+			`\tNoiseSession::init_session,`,
+			`\tMessage::from_str(PROLOGUE),`,
+			`\t${(k % 2 === 0) ? 'Keypair::from_oracle(initiator_oracle)' : 'Keypair::from_private_key(initiator_static)'},`,
+			`\t${(k % 2 === 0) ? 'Keypair::from_private_key(responder_static)' : 'Keypair::from_oracle(responder_oracle)'},`,
+			`);`,
+			`exchange(&mut initiator_session, &mut responder_session, 0..${k}, &mut |_, _, _| {});`,
+			`assert!(${writer}_session.send_message(Message::from_str(PAYLOADS[${k}])).is_none());`,
+			`assert!(${writer}_session.get_handshake_error() == Some(HandshakeError::OracleFailed));`
+		]));
+	}
+	if (f.selectable) {
+		tests.push(test(json, 'static_key_selector', [
+			`let (initiator_static, responder_static) = static_keys();`,
+			`let (mut initiator_session, mut responder_session) = init_sessions(`,
+			`\tNoiseSession::init_session,`,
+			`\tMessage::from_str(PROLOGUE),`,
+			`\tKeypair::from_private_key(initiator_static),`,
+			`\tKeypair::new_empty(),`,
+			`);`,
+			`set_vector_ephemerals(&mut initiator_session, &mut responder_session);`,
+			`let selected: Keypair = Keypair::from_private_key(responder_static);`,
+			`let mut refused: NoiseSession = responder_session.clone();`,
+			`refused.set_static_key_selector(Arc::new(|_: &[u8], _: &[u8]| None));`,
+			`responder_session.set_static_key_selector(Arc::new(move |prologue: &[u8], payload: &[u8]| {`,
+			`\tif prologue == &Message::from_str(PROLOGUE).as_bytes()[..] && payload == &Message::from_str(PAYLOADS[0]).as_bytes()[..] {`,
+			`\t\tSome(selected.clone())`,
+			`\t} else {`,
+			`\t\tNone`,
+			`\t}`,
+			`}));`,
+			`let mut messages: Vec<MessageBuffer> = exchange(&mut initiator_session, &mut responder_session, 0..HANDSHAKE_LENGTH, &mut |i, message, _| {`,
+			`\tif i == 0 {`,
+			`\t\tassert!(refused.recv_message(&mut message.clone()).is_none());`,
+			`\t\tassert!(refused.get_handshake_error() == Some(HandshakeError::NoStaticKey));`,
+			`\t}`,
+			`});`,
+			`messages.extend(transport(&mut initiator_session, &mut responder_session));`,
+			`assert_vectors(&messages[..], &CIPHERTEXTS);`
+		]));
+	}
+	if (f.firstPsk >= 0) {
+		uses.types.push('Psk');
+		let hint = (f.hintIndex >= 0) ? `hint == &Message::from_str(PAYLOADS[${f.hintIndex}]).as_bytes()[..]` : 'hint.is_empty()';
+		tests.push(test(json, 'psk_resolver', [
+			...(f.remoteKnown? [`let expected_remote: PublicKey = static_keys().0.generate_public_key();`] : []),
+			`let (mut initiator_session, _) = sessions();`,
+			`let mut responder_session: NoiseSession = NoiseSession::init_session(`,
+			`\tfalse,`,
+			`\tMessage::from_str(PROLOGUE),`,
+			`\tKeypair::from_private_key(static_keys().1),`,
+			`\t${f.responderRemote},`,
+			`\tPsk::new(),`,
+			`);`,
+			`set_vector_ephemerals(&mut initiator_session, &mut responder_session);`,
+			`responder_session.set_psk_resolver(Arc::new(move |remote: Option<&PublicKey>, hint: &[u8]| {`,
+			`\tif ${f.remoteKnown? 'remote.map(|key| key.as_bytes()) == Some(expected_remote.as_bytes())' : 'remote.is_none()'} && ${hint} {`,
+			`\t\tSome(Psk::from_str(PSK))`,
+			`\t} else {`,
+			`\t\tNone`,
+			`\t}`,
+			`}));`,
+			`let mut messages: Vec<MessageBuffer> = exchange(&mut initiator_session, &mut responder_session, 0..HANDSHAKE_LENGTH, &mut |i, message, receiver| {`,
+			`\tif i == ${f.resolvedAt} {`,
+			`\t\tlet mut refused: NoiseSession = receiver.clone();`,
+			`\t\trefused.set_psk_resolver(Arc::new(|_: Option<&PublicKey>, _: &[u8]| None));`,
+			`\t\tassert!(refused.recv_message(&mut message.clone()).is_none());`,
+			`\t\tassert!(refused.get_handshake_error() == Some(HandshakeError::PskNotFound));`,
+			`\t}`,
+			`});`,
+			`messages.extend(transport(&mut initiator_session, &mut responder_session));`,
+			`assert_vectors(&messages[..], &CIPHERTEXTS);`
+		]));
+	}
+	if (f.firstPsk === 0) {
+		tests.push(test(json, 'psk_resolver_refused', [
+			`let (mut initiator_session, _) = sessions();`,
+			`initiator_session.set_psk_resolver(Arc::new(|_: Option<&PublicKey>, _: &[u8]| None));`,
+			`assert!(initiator_session.send_message(Message::from_str(PAYLOADS[0])).is_none());`,
+			`assert!(initiator_session.get_handshake_error() == Some(HandshakeError::PskNotFound));`
+		]));
+	}
+	if (f.rotatable) {
+		tests.push(test(json, 'static_key_rotation', [
+			`let (initiator_static, responder_static) = static_keys();`,
+			`let (mut initiator_session, mut responder_session) = init_sessions(`,
+			`\tNoiseSession::init_session,`,
+			`\tMessage::from_str(PROLOGUE),`,
+			`\tKeypair::from_private_key(initiator_static),`,
+			`\tKeypair::new(),`,
+			`);`,
+			`set_vector_ephemerals(&mut initiator_session, &mut responder_session);`,
+			`let mut rotated: NoiseSession = responder_session.clone();`,
+			`rotated.set_previous_static_keys(vec![Keypair::new()]);`,
+			`responder_session.set_previous_static_keys(vec![Keypair::new(), Keypair::from_private_key(responder_static)]);`,
+			`let mut messages: Vec<MessageBuffer> = exchange(&mut initiator_session, &mut responder_session, 0..HANDSHAKE_LENGTH, &mut |i, message, _| {`,
+			`\tif i == 0 {`,
+			`\t\tassert!(rotated.recv_message(&mut message.clone()).is_none());`,
+			`\t\tassert!(rotated.get_static_key_index().is_none());`,
+			`\t}`,
+			`});`,
+			`messages.extend(transport(&mut initiator_session, &mut responder_session));`,
+			`assert!(responder_session.get_static_key_index() == Some(2));`,
+			`assert_vectors(&messages[..], &CIPHERTEXTS);`
+		]));
+	}
+	tests.push(test(json, 'associated_data', [
+		`let (mut initiator_session, mut responder_session) = sessions();`,
+		`handshake(&mut initiator_session, &mut responder_session);`,
+		`let payload: Message = Message::from_str(PAYLOADS[HANDSHAKE_LENGTH]);`,
+		`let mut message: MessageBuffer = initiator_session.send_message_with_ad(b"associated", payload.clone());`,
+		`let mut unbound: NoiseSession = responder_session.clone();`,
+		`assert!(unbound.recv_message(&mut message.clone()).is_none());`,
+		`assert!(unbound.recv_message_with_ad(b"other", &mut message.clone()).is_none());`,
+		`assert!(responder_session.recv_message_with_ad(b"associated", &mut message).unwrap() == *payload.as_bytes());`,
+		...(f.oneWay? [] : [
+			`let mut reply: MessageBuffer = responder_session.send_message_with_ad(b"associated", payload.clone());`,
+			`assert!(initiator_session.recv_message_with_ad(b"associated", &mut reply).unwrap() == *payload.as_bytes());`
+		])
+	]));
+	tests.push(test(json, 'keying_material_exporter', [
+		`let (mut initiator_session, mut responder_session) = sessions();`,
+		`handshake(&mut initiator_session, &mut responder_session);`,
+		`let exported: Vec<u8> = initiator_session.export_keying_material(b"media", b"", 48);`,
+		`assert!(exported.len() == 48 && exported == responder_session.export_keying_material(b"media", b"", 48));`,
+		`assert!(exported != initiator_session.export_keying_material(b"media", b"1", 48));`,
+		`assert!(exported[..32] != initiator_session.export_keying_material(b"signing", b"", 32)[..]);`
+	]));
+	if ((f.firstStatic >= 0) || hasStatic || f.selectable || (f.firstPsk >= 0)) {
+		std.push('sync::Arc');
+	}
+	if (f.firstStatic >= 0) {
+		std.push('fs', 'path::PathBuf');
+	}
+	let crateUses = Object.keys(uses).sort().map((module) => {
+		let items = uses[module];
+		return (items.length === 1) ? `\n\t${module}::${items[0]},` : `\n\t${module}::{${items.join(', ')}},`;
+	}).join('');
+	return [crateUses, std.sort().join(', '), tests];
+};
+
 const generate = (json) => {
 	const fs = require('fs');
 	const testVectors = JSON.parse(
 		fs.readFileSync('../tests/cacophony.json', 'utf-8')
 	).vectors;
+	const discoVectors = JSON.parse(
+		fs.readFileSync('../tests/disco.json', 'utf-8')
+	).vectors;
 	for (let i = 0; i < testVectors.length; i++) {
 		let tempA = testVectors[i].protocol_name.split('_');
 		if (
@@ -156,7 +973,13 @@ const generate = (json) => {
 			testVectors[i].protocol_name.split("_")[4] == 'BLAKE2s'
 		) {
 			let test = assign(json, testVectors[i]);
-			return test;
+			let f = features(json, testVectors[i]);
+			let [crateUses, std, tests] = featureTests(json, testVectors[i], f);
+			let disco = discoVectors.find((vector) => {
+				return vector.protocol_name === `Noise_${json.name}_25519_STROBEv1.0.2`;
+			});
+			let feature = [fixtures(json, testVectors[i], disco, f)].concat(tests).join('\n\n');
+			return [test, crateUses, std, `\n\n${feature}`];
 		}
 	}
 }
@@ -169,4 +992,4 @@ if (typeof (module) !== 'undefined') {
 } else {
 	// Web
 	NOISE2RSTESTGEN.generate = generate;
-}
\ No newline at end of file
+}